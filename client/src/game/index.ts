@@ -61,6 +61,7 @@ class Game {
 		if (connected) {
 			this.gameStateDataService.addMessageHandler('CreateUnit', this.handleServerCreateUnit)
 			this.gameStateDataService.addMessageHandler('SetUnitDestination', this.handleServerSetUnitDestination)
+			this.loadInitialGameState()
 
 			this.gameRenderer.start()
 		} else {
@@ -85,10 +86,13 @@ class Game {
 	}
 
 	private handleLoadModelsCompleted = (): void => {
+		this.connectToServer()
+	}
+
+	private loadInitialGameState = (): void => {
 		this.gameStateDataService.fetchInitialGameState().then((resp) => {
-			this.connectToServer()
-			Object.keys(resp).forEach((key) => {
-				const unit = resp[key]
+			Object.keys(resp.units).forEach((key) => {
+				const unit = resp.units[key]
 				if (unit.position.length >= 2) {
 					this.gameWorld.addGameObject(this.createMoveableTower(unit.id, unit.position[0], unit.position[1], unit.destination[0], unit.destination[1]))
 				}
@@ -25,6 +25,8 @@ class GameStateDataService {
 
 	private socket: WebSocket | undefined
 
+	private clientId: string | undefined
+
 	private messageHandlers: MessageHandler[]
 
 	constructor() {
@@ -52,6 +54,7 @@ class GameStateDataService {
 					return r.url
 				})
 				.then((url) => {
+					this.clientId = url.split('/').pop()
 					const socket = new WebSocket(`${this.wsUri}/${url}`)
 					socket.onopen = () => {
 						socket.onmessage = this.onMessageRecived
@@ -76,7 +79,7 @@ class GameStateDataService {
 
 	public fetchInitialGameState = (): Promise<GameState> =>
 		new Promise<GameState>((resolve, reject) => {
-			fetch(`${this.apiUri}/game`)
+			fetch(`${this.apiUri}/game/${this.clientId}`)
 				.then((response) => response.json())
 				.then((state: GameState) => resolve(state))
 				.catch(() => reject())
@@ -1,6 +1,7 @@
 import Unit from './unit'
 
 interface GameState {
-	[unitId: string]: Unit
+	units: { [unitId: string]: Unit }
+	map_seed?: number
 }
 export default GameState
@@ -1,8 +1,10 @@
 interface Unit {
 	position: number[]
 	destination: number[]
-	// unit_type: UnitType,
+	unit_type?: string
 	id: string
+	owner?: number
+	team?: number
 }
 
 export default Unit
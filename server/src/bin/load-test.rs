@@ -0,0 +1,364 @@
+//! Load test for the server: registers fake players, opens a websocket for each of them,
+//! sends random `CreateUnit` and `SetUnitDestination` requests and reports how quickly they are answered.
+//!
+//! `cargo run --release --bin load-test -- --players 50 --rate 5 --duration 30`
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use futures::{SinkExt, StreamExt};
+use hyper::{body, Body, Client, Method, Request};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde_json::{json, Value};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+struct LoadTestConfig {
+    /// Host and port of the server.
+    server: String,
+    players: usize,
+    /// Requests each player sends per second.
+    rate: f64,
+    duration: Duration,
+    /// Player ids are counted up from here, so they don't collide with real players or bots.
+    first_player_id: usize,
+    /// The share of requests that create a unit, the others move one.
+    create_ratio: f64,
+}
+
+impl Default for LoadTestConfig {
+    fn default() -> Self {
+        LoadTestConfig {
+            server: "localhost:80".to_string(),
+            players: 10,
+            rate: 5.,
+            duration: Duration::from_secs(30),
+            first_player_id: 2000,
+            create_ratio: 0.2,
+        }
+    }
+}
+
+impl LoadTestConfig {
+    fn from_args() -> LoadTestConfig {
+        let mut config = LoadTestConfig::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--server" => config.server = expect_value(&arg, args.next()),
+                "--players" => config.players = parse_value(&arg, args.next()),
+                "--rate" => config.rate = parse_value(&arg, args.next()),
+                "--duration" => {
+                    config.duration = Duration::from_secs_f64(parse_value(&arg, args.next()))
+                }
+                "--first-player-id" => config.first_player_id = parse_value(&arg, args.next()),
+                "--create-ratio" => config.create_ratio = parse_value(&arg, args.next()),
+                _ => eprintln!("Ignoring unknown argument: {}", arg),
+            }
+        }
+        config
+    }
+}
+
+fn expect_value(flag: &str, value: Option<String>) -> String {
+    value.unwrap_or_else(|| panic!("{} expects a value", flag))
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T {
+    expect_value(flag, value)
+        .parse()
+        .unwrap_or_else(|_| panic!("{} expects a number", flag))
+}
+
+/// The centers of the tiles units can stand on, where the fake players create and send units.
+fn passable_positions(map: &Value) -> Vec<(f32, f32)> {
+    let tile_size = map["tile_size"].as_f64().unwrap_or(1.) as f32;
+    let rows = map["tiles"].as_array().cloned().unwrap_or_default();
+    // Obstacles block every tile they overlap, like the server does
+    let obstacles: Vec<((f32, f32), f32, f32)> = map["obstacles"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default()
+        .iter()
+        .map(|obstacle| {
+            let number = |value: &Value| value.as_f64().unwrap_or_default() as f32;
+            (
+                (
+                    number(&obstacle["position"][0]),
+                    number(&obstacle["position"][1]),
+                ),
+                number(&obstacle["width"]),
+                number(&obstacle["height"]),
+            )
+        })
+        .collect();
+    let blocked = |(x, y): (f32, f32)| {
+        obstacles.iter().any(|(position, width, height)| {
+            (x - position.0).abs() * 2. < tile_size + width
+                && (y - position.1).abs() * 2. < tile_size + height
+        })
+    };
+    rows.iter()
+        .enumerate()
+        .flat_map(|(y, row)| {
+            let row = row.as_str().unwrap_or_default().to_string();
+            row.chars()
+                .enumerate()
+                .filter(|(_, tile)| matches!(tile, '.' | ',' | '^'))
+                .map(|(x, _)| ((x as f32 + 0.5) * tile_size, (y as f32 + 0.5) * tile_size))
+                .filter(|position| !blocked(*position))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// What one fake player measured.
+#[derive(Default)]
+struct PlayerReport {
+    /// From sending `CreateUnit` to getting the unit, or the error saying why it wasn't created.
+    create_latencies: Vec<Duration>,
+    /// `CreateUnit` requests still waiting for an answer when the test ended.
+    unanswered: usize,
+    /// Time between two game states, how regularly the server ticks as seen by a client.
+    state_intervals: Vec<Duration>,
+    sent: u64,
+    received: u64,
+    received_bytes: u64,
+    errors: HashMap<String, u32>,
+}
+
+async fn register(server: &str, player_id: usize) -> Result<String, String> {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("http://{}/register", server))
+        .header("content-type", "application/json")
+        .body(Body::from(json!({ "user_id": player_id }).to_string()))
+        .map_err(|e| e.to_string())?;
+    let response = Client::new()
+        .request(request)
+        .await
+        .map_err(|e| e.to_string())?;
+    let bytes = body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| e.to_string())?;
+    let response: Value = serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+    response["url"]
+        .as_str()
+        .map(|url| url.to_string())
+        .ok_or_else(|| "register response has no url".to_string())
+}
+
+async fn get_map(server: &str) -> Result<Value, String> {
+    let uri = format!("http://{}/map", server)
+        .parse()
+        .map_err(|e: hyper::http::uri::InvalidUri| e.to_string())?;
+    let response = Client::new().get(uri).await.map_err(|e| e.to_string())?;
+    let bytes = body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| e.to_string())?;
+    serde_json::from_slice(&bytes).map_err(|e| e.to_string())
+}
+
+/// Plays as one fake player until `deadline`.
+async fn run_player(
+    server: String,
+    player_id: usize,
+    rate: f64,
+    create_ratio: f64,
+    positions: Vec<(f32, f32)>,
+    deadline: tokio::time::Instant,
+) -> Result<PlayerReport, String> {
+    let url = register(&server, player_id).await?;
+    let (ws, _) = connect_async(format!("ws://{}/{}", server, url))
+        .await
+        .map_err(|e| e.to_string())?;
+    let (mut ws_sender, mut ws_receiver) = ws.split();
+
+    let mut rng = StdRng::from_entropy();
+    let mut report = PlayerReport::default();
+    let mut units: Vec<String> = Vec::new();
+    // The game thread answers the requests of a player in order, so answers are matched to the oldest request
+    let mut pending_creates: VecDeque<Instant> = VecDeque::new();
+    let mut last_was_create = false;
+    let mut last_state: Option<Instant> = None;
+
+    // Players start at different times, so their requests don't all arrive in the same tick
+    let period = Duration::from_secs_f64(1. / rate);
+    let start = tokio::time::Instant::now() + period.mul_f64(rng.gen());
+    let mut requests = tokio::time::interval_at(start, period);
+    let end = tokio::time::sleep_until(deadline);
+    tokio::pin!(end);
+
+    loop {
+        tokio::select! {
+            _ = &mut end => break,
+            _ = requests.tick() => {
+                let position = positions[rng.gen_range(0..positions.len())];
+                last_was_create = units.is_empty() || rng.gen_bool(create_ratio);
+                let request = if last_was_create {
+                    pending_creates.push_back(Instant::now());
+                    json!({ "CreateUnit": { "position": position } })
+                } else {
+                    let id = &units[rng.gen_range(0..units.len())];
+                    json!({ "SetUnitDestination": { "id": id, "destination": position } })
+                };
+                ws_sender
+                    .send(Message::text(request.to_string()))
+                    .await
+                    .map_err(|e| e.to_string())?;
+                report.sent += 1;
+            }
+            message = ws_receiver.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e.to_string()),
+                    None => return Err("the server closed the connection".to_string()),
+                };
+                report.received += 1;
+                report.received_bytes += text.len() as u64;
+                let response: Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+                if let Some(unit) = response.get("CreateUnit") {
+                    if let Some(id) = unit["id"].as_str() {
+                        units.push(id.to_string());
+                    }
+                    if let Some(sent) = pending_creates.pop_front() {
+                        report.create_latencies.push(sent.elapsed());
+                    }
+                } else if let Some(error) = response.get("ErrorResponse") {
+                    let message = error["message"].as_str().unwrap_or_default();
+                    // Units the player can't afford are rejected by the game thread, in the order they were requested,
+                    // the server rejects the rest of the creates it turns down as soon as it gets them
+                    let (answered, message) = if message.starts_with("Not enough resources") {
+                        (pending_creates.pop_front(), "Not enough resources")
+                    } else if message.starts_with("Units can't be created")
+                        || (message == "The match is over" && last_was_create)
+                    {
+                        (pending_creates.pop_back(), message)
+                    } else {
+                        (None, message)
+                    };
+                    if let Some(sent) = answered {
+                        report.create_latencies.push(sent.elapsed());
+                    }
+                    *report.errors.entry(message.to_string()).or_insert(0) += 1;
+                } else if let Some(destroyed) = response.get("UnitDestroyed") {
+                    units.retain(|id| Some(id.as_str()) != destroyed["id"].as_str());
+                } else if response.get("GameState").is_some() {
+                    let now = Instant::now();
+                    if let Some(last_state) = last_state {
+                        report.state_intervals.push(now - last_state);
+                    }
+                    last_state = Some(now);
+                }
+            }
+        }
+    }
+    report.unanswered = pending_creates.len();
+    let _result = ws_sender.close().await;
+    Ok(report)
+}
+
+/// `p50 12.3ms  p90 ...` for the durations, which must be sorted.
+fn percentiles(sorted: &[Duration]) -> String {
+    if sorted.is_empty() {
+        return "no samples".to_string();
+    }
+    let at = |percentile: f64| {
+        let index = ((sorted.len() - 1) as f64 * percentile).round() as usize;
+        sorted[index].as_secs_f64() * 1000.
+    };
+    format!(
+        "p50 {:.1}ms  p90 {:.1}ms  p99 {:.1}ms  max {:.1}ms",
+        at(0.5),
+        at(0.9),
+        at(0.99),
+        at(1.)
+    )
+}
+
+#[tokio::main]
+async fn main() {
+    let config = LoadTestConfig::from_args();
+    let map = get_map(&config.server)
+        .await
+        .unwrap_or_else(|e| panic!("Could not get the map from {}: {}", config.server, e));
+    let positions = passable_positions(&map);
+    if positions.is_empty() {
+        panic!("The map has no passable tiles");
+    }
+    println!(
+        "{} players sending {} requests per second each to {} for {:?}",
+        config.players, config.rate, config.server, config.duration
+    );
+
+    let started = Instant::now();
+    let deadline = tokio::time::Instant::now() + config.duration;
+    let players: Vec<_> = (0..config.players)
+        .map(|i| {
+            tokio::spawn(run_player(
+                config.server.clone(),
+                config.first_player_id + i,
+                config.rate,
+                config.create_ratio,
+                positions.clone(),
+                deadline,
+            ))
+        })
+        .collect();
+
+    let mut total = PlayerReport::default();
+    let mut failed = 0;
+    for (i, player) in players.into_iter().enumerate() {
+        match player.await.expect("Player task should not panic") {
+            Ok(report) => {
+                total.create_latencies.extend(report.create_latencies);
+                total.unanswered += report.unanswered;
+                total.state_intervals.extend(report.state_intervals);
+                total.sent += report.sent;
+                total.received += report.received;
+                total.received_bytes += report.received_bytes;
+                for (message, count) in report.errors {
+                    *total.errors.entry(message).or_insert(0) += count;
+                }
+            }
+            Err(e) => {
+                eprintln!("Player {} failed: {}", config.first_player_id + i, e);
+                failed += 1;
+            }
+        }
+    }
+    let seconds = started.elapsed().as_secs_f64();
+    total.create_latencies.sort();
+    total.state_intervals.sort();
+
+    println!(
+        "{} of {} players connected",
+        config.players - failed,
+        config.players
+    );
+    println!(
+        "Sent {} requests ({:.1}/s), received {} messages ({:.1}/s, {:.2} MB/s)",
+        total.sent,
+        total.sent as f64 / seconds,
+        total.received,
+        total.received as f64 / seconds,
+        total.received_bytes as f64 / seconds / 1_000_000.
+    );
+    println!(
+        "CreateUnit latency ({} answered, {} unanswered): {}",
+        total.create_latencies.len(),
+        total.unanswered,
+        percentiles(&total.create_latencies)
+    );
+    println!(
+        "Game state interval: {}",
+        percentiles(&total.state_intervals)
+    );
+    let mut errors: Vec<(String, u32)> = total.errors.into_iter().collect();
+    errors.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    for (message, count) in errors {
+        println!("{:>8} x {}", count, message);
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::game::{bots::BotConfig, victory::VictoryCondition};
+
+/// Settings for the server, read from the command line.
+///
+/// ``cargo run -- --save-file world.json --load world.json``
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// File the world is written to and read from by the save and load commands.
+    pub save_file: PathBuf,
+    /// Save file to restore the world from when the server starts.
+    pub load_on_start: Option<PathBuf>,
+    /// Directory the command journal of each run is written to.
+    pub journal_dir: PathBuf,
+    /// Journal to replay instead of taking commands from clients.
+    pub replay: Option<PathBuf>,
+    /// How long spectators have to wait for the messages players get,
+    /// so a spectator can't be used to scout for a player.
+    pub spectator_delay: Duration,
+    /// Map to play on, an empty field when neither it nor `map_seed` is set.
+    pub map: Option<PathBuf>,
+    /// Seed to generate the map from, when no map file is given.
+    pub map_seed: Option<u64>,
+    /// How many players a generated map has spawn points for.
+    pub players: u32,
+    /// How a match can end, the first condition met ends it.
+    pub victory_conditions: Vec<VictoryCondition>,
+    /// Sqlite file finished matches and player ratings are stored in.
+    pub database: PathBuf,
+    /// Whether finished matches change the ratings of their players.
+    pub ranked: bool,
+    /// Players run by the server.
+    pub bots: Vec<BotConfig>,
+    /// Scenario script run by the server, for missions and tutorials.
+    pub script: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            save_file: PathBuf::from("world.save.json"),
+            load_on_start: None,
+            journal_dir: PathBuf::from("replays"),
+            replay: None,
+            spectator_delay: Duration::from_secs(0),
+            map: None,
+            map_seed: None,
+            players: 2,
+            victory_conditions: vec![VictoryCondition::Elimination],
+            database: PathBuf::from("matches.sqlite3"),
+            ranked: false,
+            bots: Vec::new(),
+            script: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_args() -> Config {
+        let mut config = Config::default();
+        let mut args = std::env::args().skip(1);
+        let mut victory_conditions = Vec::new();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--save-file" => config.save_file = PathBuf::from(expect_value(&arg, args.next())),
+                "--load" => {
+                    config.load_on_start = Some(PathBuf::from(expect_value(&arg, args.next())))
+                }
+                "--journal-dir" => {
+                    config.journal_dir = PathBuf::from(expect_value(&arg, args.next()))
+                }
+                "--replay" => config.replay = Some(PathBuf::from(expect_value(&arg, args.next()))),
+                "--spectator-delay" => {
+                    let seconds = expect_value(&arg, args.next());
+                    let seconds = seconds
+                        .parse()
+                        .unwrap_or_else(|_| panic!("{} expects a number of seconds", arg));
+                    config.spectator_delay = Duration::from_secs(seconds);
+                }
+                "--map" => config.map = Some(PathBuf::from(expect_value(&arg, args.next()))),
+                "--seed" => {
+                    let seed = expect_value(&arg, args.next());
+                    config.map_seed = Some(if seed == "random" {
+                        SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .expect("Time went backwards")
+                            .as_nanos() as u64
+                    } else {
+                        seed.parse()
+                            .unwrap_or_else(|_| panic!("{} expects a number or random", arg))
+                    });
+                }
+                "--players" => {
+                    let players = expect_value(&arg, args.next());
+                    config.players = players
+                        .parse()
+                        .unwrap_or_else(|_| panic!("{} expects a number of players", arg));
+                }
+                "--victory" => {
+                    let condition = expect_value(&arg, args.next());
+                    victory_conditions.push(
+                        condition
+                            .parse()
+                            .unwrap_or_else(|e| panic!("{} {}: {}", arg, condition, e)),
+                    );
+                }
+                "--database" => config.database = PathBuf::from(expect_value(&arg, args.next())),
+                "--ranked" => config.ranked = true,
+                "--bot" => {
+                    let bot = expect_value(&arg, args.next());
+                    config.bots.push(
+                        bot.parse()
+                            .unwrap_or_else(|e| panic!("{} {}: {}", arg, bot, e)),
+                    );
+                }
+                "--script" => config.script = Some(PathBuf::from(expect_value(&arg, args.next()))),
+                _ => eprintln!("Ignoring unknown argument: {}", arg),
+            }
+        }
+        if !victory_conditions.is_empty() {
+            config.victory_conditions = victory_conditions;
+        }
+        config
+    }
+}
+
+fn expect_value(flag: &str, value: Option<String>) -> String {
+    value.unwrap_or_else(|| panic!("{} expects a value", flag))
+}
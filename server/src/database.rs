@@ -0,0 +1,499 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use crate::game::{
+    components::{PlayerId, TeamId},
+    resources::PlayerStats,
+    victory::{MatchEnded, Side},
+};
+
+pub type DatabaseRef = Arc<Mutex<Database>>;
+
+/// The rating a player starts with.
+pub const STARTING_RATING: f64 = 1500.;
+/// How much a single match can move a rating.
+const RATING_K_FACTOR: f64 = 32.;
+
+/// A finished match, as it is stored and served by `/matches`.
+#[derive(Serialize, Debug)]
+pub struct MatchRecord {
+    pub id: i64,
+    /// Seconds since the unix epoch.
+    pub ended_at: u64,
+    pub duration_seconds: f64,
+    pub map: String,
+    pub ranked: bool,
+    /// `None` for a draw.
+    pub winner: Option<Side>,
+    pub replay: Option<String>,
+    pub players: Vec<MatchPlayerRecord>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MatchPlayerRecord {
+    pub player_id: PlayerId,
+    pub team: Option<TeamId>,
+    pub won: bool,
+    pub score: u32,
+    pub stats: PlayerStats,
+    /// Ratings around ranked matches, `None` for unranked ones.
+    pub rating_before: Option<f64>,
+    pub rating_after: Option<f64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RatingRecord {
+    pub player_id: PlayerId,
+    pub rating: f64,
+    pub matches: u32,
+    pub wins: u32,
+}
+
+/// The match being recorded, with what the game knows about it besides the result.
+pub struct FinishedMatch<'a> {
+    pub result: &'a MatchEnded,
+    pub duration_seconds: f64,
+    pub map: &'a str,
+    pub ranked: bool,
+    pub teams: &'a HashMap<PlayerId, TeamId>,
+    pub replay: Option<&'a Path>,
+}
+
+/// Match results and player ratings, kept in a sqlite file so they outlive the server.
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    /// Opens the database at `path`, creating it and its tables if they don't exist yet.
+    pub fn open(path: &Path) -> rusqlite::Result<Database> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS matches (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                ended_at INTEGER NOT NULL,
+                duration_seconds REAL NOT NULL,
+                map TEXT NOT NULL,
+                ranked INTEGER NOT NULL,
+                winner TEXT,
+                replay TEXT
+            );
+            CREATE TABLE IF NOT EXISTS match_players (
+                match_id INTEGER NOT NULL REFERENCES matches(id),
+                player_id INTEGER NOT NULL,
+                team INTEGER,
+                won INTEGER NOT NULL,
+                score INTEGER NOT NULL,
+                stats TEXT NOT NULL,
+                rating_before REAL,
+                rating_after REAL,
+                PRIMARY KEY (match_id, player_id)
+            );
+            CREATE INDEX IF NOT EXISTS match_players_player ON match_players(player_id);
+            CREATE TABLE IF NOT EXISTS ratings (
+                player_id INTEGER PRIMARY KEY,
+                rating REAL NOT NULL,
+                matches INTEGER NOT NULL,
+                wins INTEGER NOT NULL
+            );",
+        )?;
+        Ok(Database { connection })
+    }
+
+    /// Stores a finished match, and updates the ratings of its players if it was ranked.
+    /// Returns the id of the match.
+    pub fn record_match(&mut self, finished: &FinishedMatch) -> rusqlite::Result<i64> {
+        let result = finished.result;
+        let side_of = |player_id: PlayerId| match finished.teams.get(&player_id) {
+            Some(team_id) => Side::Team(*team_id),
+            None => Side::Player(player_id),
+        };
+        let transaction = self.connection.transaction()?;
+
+        let mut ratings: HashMap<PlayerId, f64> = HashMap::new();
+        for player_id in result.stats.keys() {
+            let rating = transaction
+                .query_row(
+                    "SELECT rating FROM ratings WHERE player_id = ?1",
+                    params![*player_id as i64],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .unwrap_or(STARTING_RATING);
+            ratings.insert(*player_id, rating);
+        }
+        let sides: Vec<Side> = {
+            let mut sides: Vec<Side> = result.stats.keys().map(|p| side_of(*p)).collect();
+            sides.sort();
+            sides.dedup();
+            sides
+        };
+        // A match against nobody says nothing about how good a player is
+        let ranked = finished.ranked && sides.len() >= 2;
+        let new_ratings = if ranked {
+            elo(&ratings, &side_of, &sides, result.winner)
+        } else {
+            HashMap::new()
+        };
+
+        let ended_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        transaction.execute(
+            "INSERT INTO matches (ended_at, duration_seconds, map, ranked, winner, replay)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                ended_at as i64,
+                finished.duration_seconds,
+                finished.map,
+                ranked,
+                result.winner.map(|winner| to_json(&winner)),
+                finished
+                    .replay
+                    .map(|path| path.to_string_lossy().into_owned()),
+            ],
+        )?;
+        let match_id = transaction.last_insert_rowid();
+
+        for (player_id, player) in &result.stats {
+            let won = result.winner == Some(side_of(*player_id));
+            let rating_after = new_ratings.get(player_id).copied();
+            transaction.execute(
+                "INSERT INTO match_players
+                 (match_id, player_id, team, won, score, stats, rating_before, rating_after)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    match_id,
+                    *player_id as i64,
+                    finished.teams.get(player_id).map(|team| *team as i64),
+                    won,
+                    player.score,
+                    to_json(&player.stats),
+                    rating_after.and(ratings.get(player_id).copied()),
+                    rating_after,
+                ],
+            )?;
+            if let Some(rating) = rating_after {
+                transaction.execute(
+                    "INSERT INTO ratings (player_id, rating, matches, wins) VALUES (?1, ?2, 1, ?3)
+                     ON CONFLICT(player_id) DO UPDATE SET
+                        rating = excluded.rating,
+                        matches = matches + 1,
+                        wins = wins + excluded.wins",
+                    params![*player_id as i64, rating, won as u32],
+                )?;
+            }
+        }
+        transaction.commit()?;
+        Ok(match_id)
+    }
+
+    /// The most recent matches first, only those `player_id` played in if it is set.
+    pub fn match_history(
+        &self,
+        player_id: Option<PlayerId>,
+        limit: u32,
+    ) -> rusqlite::Result<Vec<MatchRecord>> {
+        let mut statement = self.connection.prepare(
+            "SELECT id, ended_at, duration_seconds, map, ranked, winner, replay FROM matches
+             WHERE ?1 IS NULL OR id IN (SELECT match_id FROM match_players WHERE player_id = ?1)
+             ORDER BY id DESC LIMIT ?2",
+        )?;
+        let mut matches = statement
+            .query_map(params![player_id.map(|p| p as i64), limit], |row| {
+                Ok(MatchRecord {
+                    id: row.get(0)?,
+                    ended_at: row.get::<_, i64>(1)? as u64,
+                    duration_seconds: row.get(2)?,
+                    map: row.get(3)?,
+                    ranked: row.get(4)?,
+                    winner: from_json(row.get::<_, Option<String>>(5)?),
+                    replay: row.get(6)?,
+                    players: Vec::new(),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<MatchRecord>>>()?;
+
+        let mut players = self.connection.prepare(
+            "SELECT player_id, team, won, score, stats, rating_before, rating_after
+             FROM match_players WHERE match_id = ?1 ORDER BY player_id",
+        )?;
+        for record in &mut matches {
+            record.players = players
+                .query_map(params![record.id], |row| {
+                    Ok(MatchPlayerRecord {
+                        player_id: row.get::<_, i64>(0)? as PlayerId,
+                        team: row.get::<_, Option<i64>>(1)?.map(|team| team as TeamId),
+                        won: row.get(2)?,
+                        score: row.get(3)?,
+                        stats: from_json(row.get::<_, Option<String>>(4)?).unwrap_or_default(),
+                        rating_before: row.get(5)?,
+                        rating_after: row.get(6)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<MatchPlayerRecord>>>()?;
+        }
+        Ok(matches)
+    }
+
+    /// The highest rated players first.
+    pub fn leaderboard(&self, limit: u32) -> rusqlite::Result<Vec<RatingRecord>> {
+        let mut statement = self.connection.prepare(
+            "SELECT player_id, rating, matches, wins FROM ratings
+             ORDER BY rating DESC, player_id LIMIT ?1",
+        )?;
+        let ratings = statement
+            .query_map(params![limit], |row| {
+                Ok(RatingRecord {
+                    player_id: row.get::<_, i64>(0)? as PlayerId,
+                    rating: row.get(1)?,
+                    matches: row.get(2)?,
+                    wins: row.get(3)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<RatingRecord>>>()?;
+        Ok(ratings)
+    }
+}
+
+/// The Elo ratings of the players after a match.
+/// Sides are rated against each other by the average rating of their players, and the changes are averaged,
+/// so free-for-alls move ratings as much as a duel does. The change of a side is shared by its players
+/// in proportion to how many of them there are, so ratings stay zero-sum however the teams are split,
+/// and players of evenly sized teams move as much as a duel would move them.
+fn elo(
+    ratings: &HashMap<PlayerId, f64>,
+    side_of: &dyn Fn(PlayerId) -> Side,
+    sides: &[Side],
+    winner: Option<Side>,
+) -> HashMap<PlayerId, f64> {
+    let members = |side: Side| {
+        ratings
+            .keys()
+            .filter(|player_id| side_of(**player_id) == side)
+            .count()
+    };
+    let side_rating = |side: Side| {
+        let total: f64 = ratings
+            .iter()
+            .filter(|(player_id, _)| side_of(**player_id) == side)
+            .map(|(_, rating)| *rating)
+            .sum();
+        total / members(side).max(1) as f64
+    };
+    let players_per_side = ratings.len() as f64 / sides.len().max(1) as f64;
+    ratings
+        .iter()
+        .map(|(player_id, rating)| {
+            let side = side_of(*player_id);
+            let others: Vec<Side> = sides.iter().copied().filter(|s| *s != side).collect();
+            let change: f64 = others
+                .iter()
+                .map(|other| {
+                    let expected =
+                        1. / (1. + 10f64.powf((side_rating(*other) - side_rating(side)) / 400.));
+                    let actual = match winner {
+                        Some(winner) if winner == side => 1.,
+                        Some(winner) if winner == *other => 0.,
+                        // A draw, or both lost to a third side
+                        _ => 0.5,
+                    };
+                    RATING_K_FACTOR * (actual - expected)
+                })
+                .sum::<f64>()
+                / others.len().max(1) as f64;
+            let share = players_per_side / members(side).max(1) as f64;
+            (*player_id, rating + change * share)
+        })
+        .collect()
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("Should be able to serialize")
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(value: Option<String>) -> Option<T> {
+    value.and_then(|value| serde_json::from_str(&value).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::victory::{PlayerResult, VictoryCondition};
+
+    fn finished(winner: Option<Side>, players: &[PlayerId]) -> MatchEnded {
+        MatchEnded {
+            winner,
+            condition: VictoryCondition::Elimination,
+            ticks: 100,
+            stats: players
+                .iter()
+                .map(|player_id| (*player_id, PlayerResult::default()))
+                .collect(),
+        }
+    }
+
+    fn record(
+        database: &mut Database,
+        result: &MatchEnded,
+        teams: &HashMap<PlayerId, TeamId>,
+        ranked: bool,
+    ) {
+        database
+            .record_match(&FinishedMatch {
+                result,
+                duration_seconds: 100.,
+                map: "Open field",
+                ranked,
+                teams,
+                replay: None,
+            })
+            .expect("Match should be recorded");
+    }
+
+    fn ratings(database: &Database) -> HashMap<PlayerId, (f64, u32, u32)> {
+        database
+            .leaderboard(100)
+            .expect("Leaderboard should load")
+            .into_iter()
+            .map(|record| {
+                (
+                    record.player_id,
+                    (record.rating, record.matches, record.wins),
+                )
+            })
+            .collect()
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn a_won_duel_moves_both_ratings_by_half_the_k_factor() {
+        let mut database = Database::open(Path::new(":memory:")).unwrap();
+        record(
+            &mut database,
+            &finished(Some(Side::Player(1)), &[1, 2]),
+            &HashMap::new(),
+            true,
+        );
+
+        let ratings = ratings(&database);
+        assert_eq!(ratings[&1], (STARTING_RATING + 16., 1, 1));
+        assert_eq!(ratings[&2], (STARTING_RATING - 16., 1, 0));
+        let history = database.match_history(Some(1), 10).unwrap();
+        assert_eq!(history.len(), 1);
+        assert!(history[0].ranked);
+        assert_eq!(history[0].winner, Some(Side::Player(1)));
+        assert_eq!(history[0].players[0].rating_before, Some(STARTING_RATING));
+        assert!(history[0].players[0].won);
+    }
+
+    #[test]
+    fn a_draw_between_equals_keeps_their_ratings() {
+        let mut database = Database::open(Path::new(":memory:")).unwrap();
+        record(
+            &mut database,
+            &finished(None, &[1, 2]),
+            &HashMap::new(),
+            true,
+        );
+
+        let ratings = ratings(&database);
+        assert_eq!(ratings[&1], (STARTING_RATING, 1, 0));
+        assert_eq!(ratings[&2], (STARTING_RATING, 1, 0));
+    }
+
+    #[test]
+    fn team_matches_are_zero_sum() {
+        let mut database = Database::open(Path::new(":memory:")).unwrap();
+        // Different ratings first, so the team match is between uneven players
+        record(
+            &mut database,
+            &finished(Some(Side::Player(1)), &[1, 2]),
+            &HashMap::new(),
+            true,
+        );
+        let teams: HashMap<PlayerId, TeamId> = [(1, 1), (2, 1), (3, 2)].iter().copied().collect();
+        record(
+            &mut database,
+            &finished(Some(Side::Team(2)), &[1, 2, 3]),
+            &teams,
+            true,
+        );
+
+        let ratings = ratings(&database);
+        let total: f64 = ratings.values().map(|(rating, _, _)| rating).sum();
+        assert_close(total, STARTING_RATING * 3.);
+        assert!(ratings[&3].0 > STARTING_RATING);
+        assert_eq!((ratings[&3].1, ratings[&3].2), (1, 1));
+        // Both members of the losing team lose the same
+        assert_close(
+            ratings[&1].0 - (STARTING_RATING + 16.),
+            ratings[&2].0 - (STARTING_RATING - 16.),
+        );
+    }
+
+    #[test]
+    fn elo_is_zero_sum() {
+        let ratings: HashMap<PlayerId, f64> =
+            [(1, 1400.), (2, 1800.), (3, 1500.), (4, 1650.), (5, 1200.)]
+                .iter()
+                .copied()
+                .collect();
+        let teams: HashMap<PlayerId, TeamId> = [(1, 1), (2, 1), (3, 2)].iter().copied().collect();
+        let side_of = |player_id: PlayerId| match teams.get(&player_id) {
+            Some(team_id) => Side::Team(*team_id),
+            None => Side::Player(player_id),
+        };
+        let sides = [
+            Side::Team(1),
+            Side::Team(2),
+            Side::Player(4),
+            Side::Player(5),
+        ];
+        for winner in [
+            None,
+            Some(Side::Team(1)),
+            Some(Side::Team(2)),
+            Some(Side::Player(5)),
+        ] {
+            let after = elo(&ratings, &side_of, &sides, winner);
+            let change: f64 = after
+                .iter()
+                .map(|(player_id, rating)| rating - ratings[player_id])
+                .sum();
+            assert_close(change, 0.);
+        }
+    }
+
+    #[test]
+    fn unranked_matches_are_recorded_without_ratings() {
+        let mut database = Database::open(Path::new(":memory:")).unwrap();
+        record(
+            &mut database,
+            &finished(Some(Side::Player(1)), &[1, 2]),
+            &HashMap::new(),
+            false,
+        );
+
+        assert!(ratings(&database).is_empty());
+        let history = database.match_history(None, 10).unwrap();
+        assert_eq!(history.len(), 1);
+        assert!(!history[0].ranked);
+        for player in &history[0].players {
+            assert_eq!((player.rating_before, player.rating_after), (None, None));
+        }
+        assert!(history[0].players[0].won);
+        assert!(!history[0].players[1].won);
+    }
+}
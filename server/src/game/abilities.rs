@@ -0,0 +1,214 @@
+use legion::{world::SubWorld, Entity, EntityStore};
+use serde::{Deserialize, Serialize};
+use vector2d::Vector2D;
+
+use crate::game::{
+    combat::distance_to_area,
+    components::{
+        AbilityCooldowns, AbilityType, CastAbility, Footprint, Health, Owner, PlayerId, Position,
+        Stun, UnitId, UnitType,
+    },
+    resources::TeamsResource,
+    status_effects::StatusEffect,
+};
+
+/// What an ability is aimed at.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AbilityTarget {
+    /// A position on the map.
+    Point,
+    /// A single unit or building.
+    Unit,
+    /// The unit using the ability.
+    Caster,
+}
+
+/// What an ability does to the units and buildings it affects.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum AbilityEffect {
+    /// Takes health from enemies.
+    Damage(f32),
+    /// Gives health to allies, up to their maximum.
+    Heal(f32),
+    /// Puts a status effect on enemies.
+    Status(StatusEffect),
+}
+
+/// The stats of an `AbilityType`.
+pub struct AbilityDefinition {
+    pub target: AbilityTarget,
+    /// Meters from the unit the target can be, unused for abilities that target the caster.
+    pub range: f32,
+    /// Ticks before the ability can be used again.
+    pub cooldown: u64,
+    /// Meters around the target the effect reaches, 0 only affects the target itself.
+    pub radius: f32,
+    /// An ability either only has effects on allies or only has effects on enemies.
+    pub effects: &'static [AbilityEffect],
+}
+
+impl AbilityDefinition {
+    /// Whether the ability affects allies rather than enemies.
+    pub fn helps_allies(&self) -> bool {
+        self.effects
+            .iter()
+            .any(|effect| matches!(effect, AbilityEffect::Heal(_)))
+    }
+}
+
+impl AbilityType {
+    pub fn definition(self) -> AbilityDefinition {
+        match self {
+            AbilityType::Mend => AbilityDefinition {
+                target: AbilityTarget::Unit,
+                range: 3.,
+                cooldown: 10,
+                radius: 0.,
+                effects: &[AbilityEffect::Heal(20.)],
+            },
+            AbilityType::Grenade => AbilityDefinition {
+                target: AbilityTarget::Point,
+                range: 12.,
+                cooldown: 20,
+                radius: 3.,
+                effects: &[
+                    AbilityEffect::Damage(20.),
+                    // Sets whoever it hits on fire
+                    AbilityEffect::Status(StatusEffect::DamageOverTime {
+                        damage: 5.,
+                        ticks: 3,
+                    }),
+                ],
+            },
+            AbilityType::Flashbang => AbilityDefinition {
+                target: AbilityTarget::Point,
+                range: 10.,
+                cooldown: 30,
+                radius: 4.,
+                effects: &[
+                    AbilityEffect::Status(StatusEffect::Stun { ticks: 2 }),
+                    AbilityEffect::Status(StatusEffect::Slow {
+                        factor: 0.5,
+                        ticks: 5,
+                    }),
+                ],
+            },
+            AbilityType::Rally => AbilityDefinition {
+                target: AbilityTarget::Caster,
+                range: 0.,
+                cooldown: 30,
+                radius: 6.,
+                effects: &[AbilityEffect::Heal(15.)],
+            },
+        }
+    }
+}
+
+/// Checks that `caster` can use `ability` on the target now, and returns what the ability is used on.
+/// `position` is needed for abilities that target a point and `target` for abilities that target a unit.
+/// Returns why the ability can't be used as a message for the player.
+#[allow(clippy::too_many_arguments)]
+pub fn check_ability(
+    world: &SubWorld,
+    teams: &TeamsResource,
+    caster: Entity,
+    ability: AbilityType,
+    position: Option<(f32, f32)>,
+    target: Option<Entity>,
+    player_id: PlayerId,
+    tick: u64,
+) -> Result<CastAbility, String> {
+    let caster = world
+        .entry_ref(caster)
+        .map_err(|_| "That unit does not exist".to_string())?;
+    if caster.get_component::<Owner>().map(|o| o.player_id) != Ok(player_id) {
+        return Err("You can only use abilities of your own units".to_string());
+    }
+    let unit_type = caster.get_component::<UnitType>().ok();
+    if !unit_type
+        .map(|unit_type| unit_type.definition().abilities.contains(&ability))
+        .unwrap_or(false)
+    {
+        return Err(format!("That unit can't use {:?}", ability));
+    }
+    let ticks_left = caster
+        .get_component::<AbilityCooldowns>()
+        .map(|cooldowns| cooldowns.ticks_left(ability, tick))
+        .unwrap_or(0);
+    if ticks_left > 0 {
+        return Err(format!(
+            "{:?} is ready again in {} ticks",
+            ability, ticks_left
+        ));
+    }
+    if caster.get_component::<Stun>().is_ok() {
+        return Err("That unit is stunned".to_string());
+    }
+
+    let caster_position = caster
+        .get_component::<Position>()
+        .map(|pos| Vector2D::new(pos.x, pos.y))
+        .map_err(|_| "That unit does not exist".to_string())?;
+    let definition = ability.definition();
+    let out_of_range = || {
+        format!(
+            "The target is out of range of {:?}, which reaches {} meters",
+            ability, definition.range
+        )
+    };
+    match definition.target {
+        AbilityTarget::Caster => Ok(CastAbility {
+            ability,
+            position: (caster_position.x, caster_position.y),
+            target: None,
+        }),
+        AbilityTarget::Point => {
+            let position =
+                position.ok_or_else(|| format!("{:?} has to be used on a position", ability))?;
+            if (Vector2D::new(position.0, position.1) - caster_position).length() > definition.range
+            {
+                return Err(out_of_range());
+            }
+            Ok(CastAbility {
+                ability,
+                position,
+                target: None,
+            })
+        }
+        AbilityTarget::Unit => {
+            let target = target
+                .and_then(|target| world.entry_ref(target).ok())
+                .filter(|target| target.get_component::<Health>().is_ok())
+                .ok_or_else(|| format!("{:?} has to be used on a unit or building", ability))?;
+            let target_owner = target.get_component::<Owner>().map(|o| o.player_id).ok();
+            let allied = target_owner
+                .map(|owner| teams.are_allies(player_id, owner))
+                .unwrap_or(false);
+            if definition.helps_allies() && !allied {
+                return Err(format!("{:?} can only be used on allies", ability));
+            }
+            if !definition.helps_allies() && (allied || target_owner.is_none()) {
+                return Err(format!("{:?} can only be used on enemies", ability));
+            }
+            let target_position = target
+                .get_component::<Position>()
+                .map(|pos| Vector2D::new(pos.x, pos.y))
+                .map_err(|_| format!("{:?} has to be used on a unit or building", ability))?;
+            let half_size = target
+                .get_component::<Footprint>()
+                .map(|f| Vector2D::new(f.width / 2., f.height / 2.))
+                .unwrap_or_else(|_| Vector2D::new(0., 0.));
+            if distance_to_area(target_position, half_size, caster_position) > definition.range {
+                return Err(out_of_range());
+            }
+            Ok(CastAbility {
+                ability,
+                position: (target_position.x, target_position.y),
+                target: target
+                    .get_component::<UnitId>()
+                    .ok()
+                    .map(|id| id.id.clone()),
+            })
+        }
+    }
+}
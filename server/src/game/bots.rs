@@ -0,0 +1,608 @@
+use std::{f32::consts::TAU, str::FromStr};
+
+use legion::{IntoQuery, Resources, World};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::game::{
+    commands::GameCommand,
+    components::{
+        AbilityCooldowns, AbilityType, BuildingType, Construction, HarvestState, Harvester, Order,
+        Owner, PlayerId, Position, ProductionQueue, ResourceNode, TeamId, UnitId, UnitType,
+    },
+    game_state::Unit,
+    placement::check_placement,
+    resources::{EconomyResource, MapResource, TeamsResource, TimeResource, VisibilityResource},
+};
+
+/// Bots get player ids from here up, so they don't clash with the ids of people.
+pub const FIRST_BOT_PLAYER_ID: PlayerId = 1000;
+/// How close enemies have to get to the headquarters before a bot pulls its army back to defend it.
+const DEFENSE_RADIUS: f32 = 25.;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!(
+                "unknown difficulty {}, expected easy, normal or hard",
+                s
+            )),
+        }
+    }
+}
+
+/// How a bot of a difficulty plays.
+struct DifficultySettings {
+    /// Ticks between the decisions of the bot.
+    think_interval: u64,
+    max_workers: usize,
+    max_barracks: usize,
+    /// Soldiers the bot gathers before it attacks.
+    attack_army_size: usize,
+    /// Whether soldiers throw grenades at enemies in range.
+    uses_abilities: bool,
+}
+
+impl Difficulty {
+    fn settings(self) -> DifficultySettings {
+        match self {
+            Difficulty::Easy => DifficultySettings {
+                think_interval: 5,
+                max_workers: 4,
+                max_barracks: 1,
+                attack_army_size: 8,
+                uses_abilities: false,
+            },
+            Difficulty::Normal => DifficultySettings {
+                think_interval: 3,
+                max_workers: 8,
+                max_barracks: 1,
+                attack_army_size: 6,
+                uses_abilities: false,
+            },
+            Difficulty::Hard => DifficultySettings {
+                think_interval: 1,
+                max_workers: 12,
+                max_barracks: 2,
+                attack_army_size: 5,
+                uses_abilities: true,
+            },
+        }
+    }
+}
+
+/// A bot from the command line, `--bot <difficulty>[:<team>]`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct BotConfig {
+    pub difficulty: Difficulty,
+    pub team: Option<TeamId>,
+}
+
+impl FromStr for BotConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (difficulty, team) = match s.split_once(':') {
+            Some((difficulty, team)) => (
+                difficulty,
+                Some(
+                    team.parse()
+                        .map_err(|_| format!("{} is not a team", team))?,
+                ),
+            ),
+            None => (s, None),
+        };
+        Ok(BotConfig {
+            difficulty: difficulty.parse()?,
+            team,
+        })
+    }
+}
+
+/// A player run by the server, it plays by sending the same commands a client would.
+/// Bots only know about enemies their units can see, or buildings they have seen.
+pub struct Bot {
+    pub player_id: PlayerId,
+    pub difficulty: Difficulty,
+    team: Option<TeamId>,
+    /// Where the bot builds its base.
+    spawn: (f32, f32),
+    started: bool,
+}
+
+/// What a bot knows about the world when it decides what to do.
+struct BotView {
+    headquarters: Vec<(String, (f32, f32), bool)>,
+    barracks: Vec<(String, (f32, f32), bool)>,
+    /// Buildings that are done and have room in their production queue.
+    idle_producers: Vec<(String, BuildingType)>,
+    workers: Vec<(String, (f32, f32), HarvestState)>,
+    soldiers: Vec<(String, (f32, f32), Order, u64)>,
+    resource_nodes: Vec<(String, (f32, f32))>,
+    enemies: Vec<(f32, f32)>,
+    enemy_buildings: Vec<(f32, f32)>,
+}
+
+impl Bot {
+    /// The `index`th bot of the match, which takes the spawn points from the last one down,
+    /// or a corner of the map if it has none.
+    pub fn new(index: usize, config: BotConfig, map: &MapResource) -> Bot {
+        let spawn_points = &map.definition.spawn_points;
+        let spawn = if spawn_points.is_empty() {
+            let (width, height) = map.size();
+            let corners = [(0.8, 0.8), (0.2, 0.2), (0.2, 0.8), (0.8, 0.2)];
+            let corner = corners[index % corners.len()];
+            (width * corner.0, height * corner.1)
+        } else {
+            spawn_points[spawn_points.len() - 1 - index % spawn_points.len()]
+        };
+        Bot {
+            player_id: FIRST_BOT_PLAYER_ID + index,
+            difficulty: config.difficulty,
+            team: config.team,
+            spawn,
+            started: false,
+        }
+    }
+
+    /// The commands the bot gives this tick, read from the world as the last tick left it.
+    pub fn commands(&mut self, world: &World, resources: &Resources) -> Vec<GameCommand> {
+        let settings = self.difficulty.settings();
+        let tick = resources
+            .get::<TimeResource>()
+            .expect("Must have a time resource")
+            .ticks;
+        let mut commands = Vec::new();
+        if !self.started {
+            self.started = true;
+            if let Some(team_id) = self.team {
+                commands.push(GameCommand::JoinTeamCommand {
+                    player_id: self.player_id,
+                    team_id,
+                });
+            }
+        }
+        if !tick.is_multiple_of(settings.think_interval) {
+            return commands;
+        }
+
+        let view = self.view(world, resources, tick);
+        let map = resources
+            .get::<MapResource>()
+            .expect("Must have a map resource");
+        let mut balance = resources
+            .get::<EconomyResource>()
+            .expect("Must have an economy resource")
+            .balance(self.player_id);
+        let base = view
+            .headquarters
+            .first()
+            .map(|(_, position, _)| *position)
+            .unwrap_or(self.spawn);
+
+        // A base first, with a couple of workers to gather while it is built
+        if view.headquarters.is_empty() {
+            let cost = BuildingType::Headquarters.definition().cost;
+            if balance >= cost {
+                if let Some(position) =
+                    find_building_spot(world, &map, BuildingType::Headquarters, self.spawn)
+                {
+                    commands.push(self.place_building(BuildingType::Headquarters, position));
+                    balance -= cost;
+                }
+            }
+            if view.workers.is_empty() {
+                for i in 0..2 {
+                    let cost = UnitType::Worker.definition().cost;
+                    // A spawn by the edge of the map or by water puts the workers where they can stand
+                    let position = map.nearest_passable((base.0 - 5., base.1 + i as f32 * 2.));
+                    if let (true, Some(position)) = (balance >= cost, position) {
+                        commands.push(self.create_unit(UnitType::Worker, position));
+                        balance -= cost;
+                    }
+                }
+            }
+        }
+
+        // Idle workers gather from the node closest to the base
+        if let Some((node, _)) = closest(&view.resource_nodes, base, |(_, position)| *position) {
+            for (id, _, state) in &view.workers {
+                if *state == HarvestState::Idle {
+                    commands.push(GameCommand::HarvestCommand {
+                        uuid: id.clone(),
+                        node_id: node.clone(),
+                        player_id: self.player_id,
+                    });
+                }
+            }
+        }
+
+        // Enough workers, then barracks, then soldiers
+        let has_headquarters = view.headquarters.iter().any(|(_, _, done)| *done);
+        for (building_id, building_type) in &view.idle_producers {
+            let unit_type = match building_type {
+                BuildingType::Headquarters if view.workers.len() < settings.max_workers => {
+                    UnitType::Worker
+                }
+                BuildingType::Barracks => UnitType::Soldier,
+                _ => continue,
+            };
+            let cost = unit_type.definition().cost;
+            if balance >= cost {
+                commands.push(GameCommand::QueueProductionCommand {
+                    building_id: building_id.clone(),
+                    unit_type,
+                    player_id: self.player_id,
+                });
+                balance -= cost;
+            }
+        }
+        let barracks_cost = BuildingType::Barracks.definition().cost;
+        if has_headquarters
+            && view.barracks.len() < settings.max_barracks
+            && view.workers.len() >= settings.max_workers / 2
+            && balance >= barracks_cost
+        {
+            if let Some(position) = find_building_spot(world, &map, BuildingType::Barracks, base) {
+                commands.push(self.place_building(BuildingType::Barracks, position));
+            }
+        }
+
+        // The army defends the base, and attacks once it is big enough
+        let threat = view
+            .enemies
+            .iter()
+            .copied()
+            .filter(|enemy| distance(*enemy, base) <= DEFENSE_RADIUS)
+            .min_by(|a, b| distance(*a, base).total_cmp(&distance(*b, base)));
+        let target = match threat {
+            Some(threat) => Some(threat),
+            None if view.soldiers.len() >= settings.attack_army_size => Some(
+                closest(&view.enemy_buildings, base, |position| *position)
+                    .or_else(|| closest(&view.enemies, base, |position| *position))
+                    .copied()
+                    .unwrap_or_else(|| self.scouting_target(&map, base)),
+            ),
+            None => None,
+        };
+        for (id, position, order, grenade_ready_in) in &view.soldiers {
+            let grenade = AbilityType::Grenade.definition();
+            let grenade_target = view
+                .enemies
+                .iter()
+                .copied()
+                .filter(|enemy| distance(*enemy, *position) <= grenade.range)
+                .min_by(|a, b| distance(*a, *position).total_cmp(&distance(*b, *position)));
+            if let (true, 0, Some(enemy)) =
+                (settings.uses_abilities, grenade_ready_in, grenade_target)
+            {
+                commands.push(GameCommand::UseAbilityCommand {
+                    uuid: id.clone(),
+                    ability: AbilityType::Grenade,
+                    position: Some(enemy),
+                    target_id: None,
+                    player_id: self.player_id,
+                });
+            }
+            let target = match target {
+                Some(target) => target,
+                None => continue,
+            };
+            // Soldiers already on their way keep going, so they don't stop to take new orders
+            let already_going =
+                matches!(order, Order::AttackMove { position } if distance(*position, target) < 5.);
+            if !already_going {
+                commands.push(GameCommand::AttackMoveCommand {
+                    uuid: id.clone(),
+                    position: map.clamp(target),
+                    player_id: self.player_id,
+                });
+            }
+        }
+        commands
+    }
+
+    fn view(&self, world: &World, resources: &Resources, tick: u64) -> BotView {
+        let teams = resources
+            .get::<TeamsResource>()
+            .expect("Must have a teams resource");
+        let visibility = resources
+            .get::<VisibilityResource>()
+            .expect("Must have a visibility resource");
+        let is_enemy = |owner: PlayerId| !teams.are_allies(self.player_id, owner);
+        let mut view = BotView {
+            headquarters: Vec::new(),
+            barracks: Vec::new(),
+            idle_producers: Vec::new(),
+            workers: Vec::new(),
+            soldiers: Vec::new(),
+            resource_nodes: Vec::new(),
+            enemies: Vec::new(),
+            enemy_buildings: Vec::new(),
+        };
+
+        <(
+            &UnitId,
+            &BuildingType,
+            &Position,
+            &Owner,
+            Option<&ProductionQueue>,
+            Option<&Construction>,
+        )>::query()
+        .for_each(
+            world,
+            |(id, building_type, pos, owner, queue, construction)| {
+                if owner.player_id != self.player_id {
+                    return;
+                }
+                let building = (id.id.clone(), (pos.x, pos.y), construction.is_none());
+                match building_type {
+                    BuildingType::Headquarters => view.headquarters.push(building),
+                    BuildingType::Barracks => view.barracks.push(building),
+                }
+                // Queuing one unit at a time keeps resources free for other things
+                if construction.is_none() && queue.is_some_and(|queue| queue.queue.is_empty()) {
+                    view.idle_producers.push((id.id.clone(), *building_type));
+                }
+            },
+        );
+        <(
+            &UnitId,
+            &UnitType,
+            &Position,
+            &Owner,
+            Option<&Harvester>,
+            Option<&Order>,
+            Option<&AbilityCooldowns>,
+        )>::query()
+        .for_each(
+            world,
+            |(id, unit_type, pos, owner, harvester, order, cooldowns)| {
+                let position = (pos.x, pos.y);
+                if owner.player_id != self.player_id {
+                    let visible = visibility
+                        .visible
+                        .get(&self.player_id)
+                        .is_some_and(|visible| visible.contains(&id.id));
+                    if visible && is_enemy(owner.player_id) {
+                        view.enemies.push(position);
+                    }
+                    return;
+                }
+                match (unit_type, harvester) {
+                    (UnitType::Worker, Some(harvester)) => {
+                        view.workers
+                            .push((id.id.clone(), position, harvester.state))
+                    }
+                    _ => view.soldiers.push((
+                        id.id.clone(),
+                        position,
+                        order.cloned().unwrap_or(Order::Idle),
+                        cooldowns
+                            .map(|cooldowns| cooldowns.ticks_left(AbilityType::Grenade, tick))
+                            .unwrap_or(0),
+                    )),
+                }
+            },
+        );
+        <(&ResourceNode, &Position)>::query().for_each(world, |(node, pos)| {
+            view.resource_nodes.push((node.id.clone(), (pos.x, pos.y)));
+        });
+        if let Some(structures) = visibility.last_known_structures.get(&self.player_id) {
+            view.enemy_buildings = structures
+                .values()
+                .filter(|building| building.owner.is_some_and(is_enemy))
+                .map(|building| building.position)
+                .collect();
+        }
+        view
+    }
+
+    /// Where to look for enemies when none have been seen: the spawn point furthest from the base,
+    /// or the other side of the map.
+    fn scouting_target(&self, map: &MapResource, base: (f32, f32)) -> (f32, f32) {
+        map.definition
+            .spawn_points
+            .iter()
+            .copied()
+            .max_by(|a, b| distance(*a, base).total_cmp(&distance(*b, base)))
+            .filter(|spawn| distance(*spawn, base) > DEFENSE_RADIUS)
+            .unwrap_or_else(|| {
+                let (width, height) = map.size();
+                (width - base.0, height - base.1)
+            })
+    }
+
+    fn place_building(&self, building_type: BuildingType, position: (f32, f32)) -> GameCommand {
+        GameCommand::PlaceBuildingCommand {
+            id: Uuid::new_v4().to_string(),
+            building_type,
+            position,
+            owner: self.player_id,
+        }
+    }
+
+    fn create_unit(&self, unit_type: UnitType, position: (f32, f32)) -> GameCommand {
+        GameCommand::CreateUnitCommand {
+            unit: Unit {
+                position,
+                destination: position,
+                id: Uuid::new_v4().to_string(),
+                owner: Some(self.player_id),
+                team: self.team,
+                unit_type,
+                facing: 0.,
+                health: unit_type.definition().max_health,
+                ..Default::default()
+            },
+            free: false,
+        }
+    }
+}
+
+/// The first spot around `near` where a building fits, in rings going outward.
+fn find_building_spot(
+    world: &World,
+    map: &MapResource,
+    building_type: BuildingType,
+    near: (f32, f32),
+) -> Option<(f32, f32)> {
+    (0..6).find_map(|ring| {
+        let radius = ring as f32 * 8.;
+        let steps = (ring * 8).max(1);
+        (0..steps).find_map(|step| {
+            let angle = step as f32 / steps as f32 * TAU;
+            let position =
+                map.clamp((near.0 + radius * angle.cos(), near.1 + radius * angle.sin()));
+            check_placement(world, map, building_type, position, &[])
+                .ok()
+                .map(|_| position)
+        })
+    })
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+fn closest<T>(items: &[T], to: (f32, f32), position: impl Fn(&T) -> (f32, f32)) -> Option<&T> {
+    items
+        .iter()
+        .min_by(|a, b| distance(position(a), to).total_cmp(&distance(position(b), to)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::game_loop::Game;
+
+    fn bot(game: &Game) -> Bot {
+        let config = BotConfig {
+            difficulty: Difficulty::Hard,
+            team: None,
+        };
+        let map = game.resources.get::<MapResource>().unwrap();
+        Bot::new(0, config, &map)
+    }
+
+    #[test]
+    fn starts_with_a_headquarters_and_two_workers() {
+        let game = Game::empty();
+        let mut bot = bot(&game);
+
+        let commands = bot.commands(&game.world, &game.resources);
+        let headquarters = commands
+            .iter()
+            .filter(|command| {
+                matches!(
+                    command,
+                    GameCommand::PlaceBuildingCommand {
+                        building_type: BuildingType::Headquarters,
+                        ..
+                    }
+                )
+            })
+            .count();
+        let workers = commands
+            .iter()
+            .filter(|command| {
+                matches!(
+                    command,
+                    GameCommand::CreateUnitCommand { unit, free: false }
+                        if unit.unit_type == UnitType::Worker
+                )
+            })
+            .count();
+        assert_eq!((headquarters, workers), (1, 2));
+    }
+
+    #[test]
+    fn sends_idle_workers_to_the_closest_node_and_queues_more() {
+        let mut game = Game::empty();
+        let mut bot = bot(&game);
+        let base = bot.spawn;
+        game.spawn_building("hq", BuildingType::Headquarters, base, FIRST_BOT_PLAYER_ID);
+        game.spawn_unit(
+            "worker",
+            UnitType::Worker,
+            (base.0 - 10., base.1),
+            FIRST_BOT_PLAYER_ID,
+        );
+        game.handle(vec![
+            GameCommand::CreateResourceNodeCommand {
+                id: "near".to_string(),
+                position: (base.0 - 20., base.1),
+                amount: 100,
+            },
+            GameCommand::CreateResourceNodeCommand {
+                id: "far".to_string(),
+                position: (10., 10.),
+                amount: 100,
+            },
+        ]);
+
+        let commands = bot.commands(&game.world, &game.resources);
+        assert!(commands.iter().any(|command| matches!(
+            command,
+            GameCommand::HarvestCommand { uuid, node_id, .. } if uuid == "worker" && node_id == "near"
+        )));
+        assert!(commands.iter().any(|command| matches!(
+            command,
+            GameCommand::QueueProductionCommand {
+                building_id,
+                unit_type: UnitType::Worker,
+                ..
+            } if building_id == "hq"
+        )));
+        assert!(!commands
+            .iter()
+            .any(|command| matches!(command, GameCommand::PlaceBuildingCommand { .. })));
+    }
+
+    #[test]
+    fn attacks_once_the_army_is_big_enough() {
+        let mut game = Game::empty();
+        let mut bot = bot(&game);
+        let base = bot.spawn;
+        game.spawn_building("hq", BuildingType::Headquarters, base, FIRST_BOT_PLAYER_ID);
+        let army_size = Difficulty::Hard.settings().attack_army_size;
+        for i in 0..army_size - 1 {
+            game.spawn_unit(
+                &format!("soldier{}", i),
+                UnitType::Soldier,
+                (base.0 - 10., base.1 - i as f32 * 2.),
+                FIRST_BOT_PLAYER_ID,
+            );
+        }
+        let attack_moves = |commands: &[GameCommand]| {
+            commands
+                .iter()
+                .filter(|command| matches!(command, GameCommand::AttackMoveCommand { .. }))
+                .count()
+        };
+
+        let commands = bot.commands(&game.world, &game.resources);
+        assert_eq!(attack_moves(&commands), 0);
+
+        game.spawn_unit(
+            "last",
+            UnitType::Soldier,
+            (base.0 - 12., base.1),
+            FIRST_BOT_PLAYER_ID,
+        );
+        let commands = bot.commands(&game.world, &game.resources);
+        assert_eq!(attack_moves(&commands), army_size);
+    }
+}
@@ -0,0 +1,42 @@
+use crate::game::components::{BuildingType, UnitType};
+
+/// The stats every building of a `BuildingType` starts with.
+pub struct BuildingTypeDefinition {
+    /// Resources the owner pays to create the building.
+    pub cost: u32,
+    /// Seconds it takes to construct the building.
+    pub build_time: f32,
+    /// Width and height in meters.
+    pub footprint: (f32, f32),
+    pub max_health: f32,
+    pub sight_radius: f32,
+    /// Whether harvesters can drop off cargo at the building.
+    pub drop_off: bool,
+    /// The unit types the building can produce.
+    pub produces: &'static [UnitType],
+}
+
+impl BuildingType {
+    pub fn definition(self) -> BuildingTypeDefinition {
+        match self {
+            BuildingType::Headquarters => BuildingTypeDefinition {
+                cost: 400,
+                build_time: 60.,
+                footprint: (6., 6.),
+                max_health: 1500.,
+                sight_radius: 15.,
+                drop_off: true,
+                produces: &[UnitType::Worker],
+            },
+            BuildingType::Barracks => BuildingTypeDefinition {
+                cost: 200,
+                build_time: 30.,
+                footprint: (4., 4.),
+                max_health: 800.,
+                sight_radius: 10.,
+                drop_off: false,
+                produces: &[UnitType::Soldier],
+            },
+        }
+    }
+}
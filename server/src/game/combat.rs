@@ -0,0 +1,108 @@
+use legion::{world::SubWorld, Entity, EntityStore, IntoQuery};
+use vector2d::Vector2D;
+
+use crate::game::{
+    components::{Footprint, Health, Owner, PlayerId, Position, UnitId},
+    resources::TeamsResource,
+};
+
+/// A unit or building that can be attacked.
+pub struct Attackable {
+    pub entity: Entity,
+    pub id: String,
+    pub position: Vector2D<f32>,
+    /// Half the width and height of buildings, zero for units.
+    pub half_size: Vector2D<f32>,
+    pub owner: PlayerId,
+}
+
+impl Attackable {
+    /// Meters from `from` to the closest point of the target.
+    pub fn distance(&self, from: Vector2D<f32>) -> f32 {
+        distance_to_area(self.position, self.half_size, from)
+    }
+}
+
+/// Meters from `from` to the closest point of the rectangle around `center`, 0 inside it.
+pub fn distance_to_area(
+    center: Vector2D<f32>,
+    half_size: Vector2D<f32>,
+    from: Vector2D<f32>,
+) -> f32 {
+    let dx = ((center.x - from.x).abs() - half_size.x).max(0.);
+    let dy = ((center.y - from.y).abs() - half_size.y).max(0.);
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// Every owned unit and building that is still alive.
+/// The world needs read access to `UnitId`, `Position`, `Owner`, `Health` and `Footprint`.
+pub fn attackables(world: &SubWorld) -> Vec<Attackable> {
+    <(
+        Entity,
+        &UnitId,
+        &Position,
+        &Owner,
+        &Health,
+        Option<&Footprint>,
+    )>::query()
+    .iter(world)
+    .filter(|(_, _, _, _, health, _)| health.current > 0.)
+    .map(|(entity, id, pos, owner, _, footprint)| Attackable {
+        entity: *entity,
+        id: id.id.clone(),
+        position: Vector2D::new(pos.x, pos.y),
+        half_size: footprint
+            .map(|f| Vector2D::new(f.width / 2., f.height / 2.))
+            .unwrap_or_else(|| Vector2D::new(0., 0.)),
+        owner: owner.player_id,
+    })
+    .collect()
+}
+
+/// The attackables within `radius` of `position`, with their distance to it.
+pub fn within(
+    attackables: &[Attackable],
+    position: Vector2D<f32>,
+    radius: f32,
+) -> impl Iterator<Item = (&Attackable, f32)> {
+    attackables
+        .iter()
+        .map(move |other| (other, other.distance(position)))
+        .filter(move |(_, distance)| *distance <= radius)
+}
+
+/// The attackables within `radius` of `position` that are enemies of `player_id`.
+pub fn enemies_within<'a>(
+    attackables: &'a [Attackable],
+    teams: &'a TeamsResource,
+    player_id: PlayerId,
+    position: Vector2D<f32>,
+    radius: f32,
+) -> impl Iterator<Item = (&'a Attackable, f32)> + 'a {
+    within(attackables, position, radius)
+        .filter(move |(other, _)| !teams.are_allies(player_id, other.owner))
+}
+
+/// Takes `damage` from the health of `entity`, `remove_dead` removes it once it runs out.
+/// Returns the health that was actually taken, which is less than `damage` if the entity had less left.
+/// The world needs write access to `Health`.
+pub fn deal_damage(world: &mut SubWorld, entity: Entity, damage: f32) -> f32 {
+    if let Ok(mut entry) = world.entry_mut(entity) {
+        if let Ok(health) = entry.get_component_mut::<Health>() {
+            let dealt = damage.min(health.current.max(0.));
+            health.current -= damage;
+            return dealt;
+        }
+    }
+    0.
+}
+
+/// Gives `amount` of health to `entity`, up to its maximum.
+/// The world needs write access to `Health`.
+pub fn heal(world: &mut SubWorld, entity: Entity, amount: f32) {
+    if let Ok(mut entry) = world.entry_mut(entity) {
+        if let Ok(health) = entry.get_component_mut::<Health>() {
+            health.current = (health.current + amount).min(health.max);
+        }
+    }
+}
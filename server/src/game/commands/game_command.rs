@@ -1,8 +1,48 @@
-use crate::game::game_state::Unit;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+use crate::game::{
+    components::{AbilityType, BuildingType, PlayerId, TeamId, UnitType},
+    formation::Formation,
+    game_state::Unit,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum GameCommand {
-    CreateUnitCommand { unit: Unit },
-    SetUnitDestinationCommand { position: (f32, f32), uuid: String },
+    CreateUnitCommand {
+        unit: Unit,
+        /// Set for units placed by the map, which the owner does not pay for.
+        #[serde(default)]
+        free: bool,
+    },
+    SetUnitDestinationCommand { position: (f32, f32), uuid: String, player_id: PlayerId },
+    StopCommand { uuid: String, player_id: PlayerId },
+    HoldPositionCommand { uuid: String, player_id: PlayerId },
+    PatrolCommand { uuid: String, a: (f32, f32), b: (f32, f32), player_id: PlayerId },
+    AttackMoveCommand { uuid: String, position: (f32, f32), player_id: PlayerId },
+    UseAbilityCommand {
+        uuid: String,
+        ability: AbilityType,
+        /// For abilities used on a position.
+        position: Option<(f32, f32)>,
+        /// For abilities used on a unit or building.
+        target_id: Option<String>,
+        player_id: PlayerId,
+    },
+    MoveGroupCommand { ids: Vec<String>, destination: (f32, f32), formation: Formation, player_id: PlayerId },
     ResetGameCommand,
+    SaveGameCommand,
+    LoadGameCommand,
+    JoinTeamCommand { player_id: PlayerId, team_id: TeamId },
+    /// Placed by the map or a script, players can't create resource nodes.
+    CreateResourceNodeCommand { id: String, position: (f32, f32), amount: u32 },
+    HarvestCommand { uuid: String, node_id: String, player_id: PlayerId },
+    PlaceBuildingCommand {
+        id: String,
+        building_type: BuildingType,
+        position: (f32, f32),
+        owner: PlayerId,
+    },
+    QueueProductionCommand { building_id: String, unit_type: UnitType, player_id: PlayerId },
+    SetRallyPointCommand { building_id: String, position: (f32, f32), player_id: PlayerId },
 }
@@ -0,0 +1,21 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::AbilityType;
+
+/// The tick at which each ability a unit has used can be used again.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AbilityCooldowns {
+    pub ready_at: HashMap<AbilityType, u64>,
+}
+
+impl AbilityCooldowns {
+    /// Ticks left until the ability can be used again, 0 if it is ready.
+    pub fn ticks_left(&self, ability: AbilityType, tick: u64) -> u64 {
+        self.ready_at
+            .get(&ability)
+            .map(|ready_at| ready_at.saturating_sub(tick))
+            .unwrap_or(0)
+    }
+}
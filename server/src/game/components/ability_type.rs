@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+/// An ability units can use, the stats of each ability are in `abilities`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AbilityType {
+    /// Patches up a damaged ally.
+    Mend,
+    /// Thrown at a point, hurts every enemy around it and sets them on fire.
+    Grenade,
+    /// Thrown at a point, stuns and then slows every enemy around it.
+    Flashbang,
+    /// Heals the unit and the allies around it.
+    Rally,
+}
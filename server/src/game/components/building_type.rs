@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+/// What kind of building an entity is, the stats of each kind are in `building_types`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BuildingType {
+    /// Produces workers, and is where they drop off what they gather.
+    Headquarters,
+    /// Produces soldiers.
+    Barracks,
+}
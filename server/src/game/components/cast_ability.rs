@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::AbilityType;
+
+/// An ability a unit is using this tick, `cast_abilities` applies its effect and removes it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CastAbility {
+    pub ability: AbilityType,
+    /// Where the effect is centered.
+    pub position: (f32, f32),
+    /// The unit the ability is used on, for abilities that target a single unit.
+    pub target: Option<String>,
+}
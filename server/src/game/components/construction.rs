@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+/// Part of its maximum health a building has when it is placed, it gains the rest while under construction.
+pub const CONSTRUCTION_START_HEALTH: f32 = 0.1;
+
+/// Marks a building that is still being constructed, it can't produce units or take cargo until it is done.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Construction {
+    /// From 0 when placed to 1 when done.
+    pub progress: f32,
+}
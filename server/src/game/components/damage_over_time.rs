@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::PlayerId;
+
+/// The most times damage over time can stack on a unit.
+pub const MAX_DAMAGE_OVER_TIME_STACKS: u32 = 3;
+
+/// Takes `damage` health per stack every tick until the effect runs out.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DamageOverTime {
+    pub damage: f32,
+    pub stacks: u32,
+    pub ticks_left: u64,
+    /// The player the damage is counted for.
+    #[serde(default)]
+    pub source: Option<PlayerId>,
+}
@@ -0,0 +1,5 @@
+use serde::{Deserialize, Serialize};
+
+/// Marks an entity where harvesters of the same owner can drop off their cargo.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct DropOff;
@@ -0,0 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+/// The direction a unit is heading in, in radians counter-clockwise from the x-axis.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Facing {
+    pub angle: f32,
+}
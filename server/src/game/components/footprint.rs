@@ -0,0 +1,8 @@
+use serde::{Deserialize, Serialize};
+
+/// The area a building covers on the map, in meters, centered on its position.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Footprint {
+    pub width: f32,
+    pub height: f32,
+}
@@ -0,0 +1,8 @@
+use serde::{Deserialize, Serialize};
+
+/// Caps the speed of a unit moving with a group to the speed of the slowest unit in it,
+/// so the group keeps its formation on the way.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GroupSpeed {
+    pub max_speed: f32,
+}
@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum HarvestState {
+    Idle,
+    MovingToNode,
+    Gathering,
+    /// Carrying cargo back to the closest drop-off.
+    Returning,
+}
+
+/// Lets a unit gather from resource nodes and bring the cargo to a drop-off.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Harvester {
+    pub state: HarvestState,
+    /// Id of the resource node the harvester gathers from.
+    pub node: Option<String>,
+    /// Resources carried right now.
+    pub cargo: u32,
+    /// How much the harvester can carry before it has to return.
+    pub capacity: u32,
+    /// Resources gathered per second.
+    pub gather_rate: f32,
+    /// Gathered resources that don't add up to a whole unit yet.
+    pub progress: f32,
+}
+
+impl Harvester {
+    pub fn new(capacity: u32, gather_rate: f32) -> Harvester {
+        Harvester {
+            state: HarvestState::Idle,
+            node: None,
+            cargo: 0,
+            capacity,
+            gather_rate,
+            progress: 0.,
+        }
+    }
+}
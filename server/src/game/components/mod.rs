@@ -9,3 +9,87 @@ pub use unit_id::*;
 
 pub mod destination;
 pub use destination::*;
+
+pub mod owner;
+pub use owner::*;
+
+pub mod vision;
+pub use vision::*;
+
+pub mod structure;
+pub use structure::*;
+
+pub mod team;
+pub use team::*;
+
+pub mod unit_type;
+pub use unit_type::*;
+
+pub mod resource_node;
+pub use resource_node::*;
+
+pub mod drop_off;
+pub use drop_off::*;
+
+pub mod harvester;
+pub use harvester::*;
+
+pub mod building_type;
+pub use building_type::*;
+
+pub mod footprint;
+pub use footprint::*;
+
+pub mod health;
+pub use health::*;
+
+pub mod production_queue;
+pub use production_queue::*;
+
+pub mod rally_point;
+pub use rally_point::*;
+
+pub mod construction;
+pub use construction::*;
+
+pub mod route;
+pub use route::*;
+
+pub mod facing;
+pub use facing::*;
+
+pub mod movement_profile;
+pub use movement_profile::*;
+
+pub mod group_speed;
+pub use group_speed::*;
+
+pub mod order;
+pub use order::*;
+
+pub mod weapon;
+pub use weapon::*;
+
+pub mod target;
+pub use target::*;
+
+pub mod projectile;
+pub use projectile::*;
+
+pub mod ability_type;
+pub use ability_type::*;
+
+pub mod ability_cooldowns;
+pub use ability_cooldowns::*;
+
+pub mod cast_ability;
+pub use cast_ability::*;
+
+pub mod slow;
+pub use slow::*;
+
+pub mod stun;
+pub use stun::*;
+
+pub mod damage_over_time;
+pub use damage_over_time::*;
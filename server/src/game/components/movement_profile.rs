@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// How a unit speeds up, slows down and turns.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MovementProfile {
+    /// Meters/second on open ground.
+    pub max_speed: f32,
+    /// Meters/second gained each second when speeding up.
+    pub acceleration: f32,
+    /// Meters/second lost each second when slowing down.
+    pub deceleration: f32,
+    /// Radians the unit can turn each second.
+    pub turn_rate: f32,
+}
+
+impl Default for MovementProfile {
+    fn default() -> Self {
+        MovementProfile {
+            max_speed: 5.,
+            acceleration: 5.,
+            deceleration: 10.,
+            turn_rate: std::f32::consts::PI,
+        }
+    }
+}
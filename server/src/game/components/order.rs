@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+/// What a unit has been ordered to do, `follow_orders` moves it on to the next step of the order.
+/// Units without an order are idle.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Order {
+    /// Stays where it is.
+    Idle,
+    /// Moves to the position, and is idle once it gets there.
+    Move { position: (f32, f32) },
+    /// Moves to the position, fighting any enemy it comes across on the way.
+    AttackMove { position: (f32, f32) },
+    /// Stays where it is, and only fights enemies that come within range.
+    HoldPosition,
+    /// Moves back and forth between `a` and `b`, starting with `a`, fighting any enemy it comes across.
+    Patrol {
+        a: (f32, f32),
+        b: (f32, f32),
+        /// Whether the unit is on its way to `b`, rather than `a`.
+        towards_b: bool,
+    },
+}
+
+impl Order {
+    /// Where the order has the unit go next, `None` if it should stand still.
+    pub fn destination(&self) -> Option<(f32, f32)> {
+        match self {
+            Order::Idle | Order::HoldPosition => None,
+            Order::Move { position } | Order::AttackMove { position } => Some(*position),
+            Order::Patrol { a, b, towards_b } => Some(if *towards_b { *b } else { *a }),
+        }
+    }
+}
@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+/// Id of a player, this is the `user_id` a client registered with.
+pub type PlayerId = usize;
+
+/// The player that controls an entity.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Owner {
+    pub player_id: PlayerId,
+}
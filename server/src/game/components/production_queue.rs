@@ -0,0 +1,18 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::UnitType;
+
+/// How many units can wait in a production queue.
+pub const MAX_QUEUE_LENGTH: usize = 5;
+
+/// Units a building has been paid to produce, the first one is in production.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ProductionQueue {
+    pub queue: VecDeque<UnitType>,
+    /// Seconds spent producing the first unit in the queue.
+    pub progress: f32,
+    /// Units produced so far, used to give produced units ids that are the same when replayed.
+    pub produced: u32,
+}
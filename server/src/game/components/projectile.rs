@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::{PlayerId, ProjectileStats};
+
+/// A shot on its way to its target, it is moved by its `Velocity` like units are.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Projectile {
+    pub id: String,
+    /// The unit that fired it.
+    pub attacker: String,
+    /// The owner of the attacker, whose allies the projectile doesn't hurt.
+    pub owner: PlayerId,
+    pub target: String,
+    pub damage: f32,
+    pub stats: ProjectileStats,
+    /// Where the projectile hits, homing projectiles keep it on their target.
+    pub impact: (f32, f32),
+}
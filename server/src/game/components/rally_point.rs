@@ -0,0 +1,8 @@
+use serde::{Deserialize, Serialize};
+
+/// Where units produced by a building move to after they are spawned.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RallyPoint {
+    pub x: f32,
+    pub y: f32,
+}
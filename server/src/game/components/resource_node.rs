@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+/// A spot on the map harvesters can gather resources from, removed once it runs out.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ResourceNode {
+    /// Unique id, like `UnitId`, so clients can point harvesters at the node.
+    pub id: String,
+    /// Resources left in the node.
+    pub amount: u32,
+}
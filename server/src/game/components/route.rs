@@ -0,0 +1,11 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+/// The points a unit moves through to get to its `Destination` around terrain it can't cross.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Route {
+    pub waypoints: VecDeque<(f32, f32)>,
+    /// The destination the route was found for, a new route is needed when it changes.
+    pub destination: (f32, f32),
+}
@@ -0,0 +1,8 @@
+use serde::{Deserialize, Serialize};
+
+/// Slows a unit down, its speed is multiplied by `factor` until the effect runs out.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Slow {
+    pub factor: f32,
+    pub ticks_left: u64,
+}
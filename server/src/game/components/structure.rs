@@ -0,0 +1,4 @@
+/// Marks entities that never move.
+/// Players remember where they last saw a structure after it is hidden by fog of war.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Structure;
@@ -0,0 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+/// A stunned unit can't move, attack, use abilities or be given orders until the effect runs out.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Stun {
+    pub ticks_left: u64,
+}
@@ -0,0 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+/// The enemy a unit is fighting, it goes back to its order once there is no enemy left to fight.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Target {
+    pub id: String,
+}
@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+/// Id of a team, picked by the players when they register.
+pub type TeamId = usize;
+
+/// The team an entity belongs to, only set when its owner has joined a team.
+/// Entities of the same team are allies, they share vision and don't fight each other.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Team {
+    pub team_id: TeamId,
+}
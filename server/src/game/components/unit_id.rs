@@ -1,6 +1,9 @@
+use serde::{Deserialize, Serialize};
+
 /// The unites id, this is different from entity id,
 /// since this is created before a unit is added to a world
 /// and this id is unique independent of worlds
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct UnitId {
     pub id: String,
 }
@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+/// What kind of unit an entity is, the stats of each kind are in `unit_types`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum UnitType {
+    /// Gathers from resource nodes.
+    #[default]
+    Worker,
+    Soldier,
+}
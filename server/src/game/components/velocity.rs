@@ -1,5 +1,7 @@
+use serde::{Deserialize, Serialize};
 
 ///Stores inforamtion about an entities velocity in meters/seconds.
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Velocity{
     ///the x-axis velocity in meters/seconds.
     pub dx : f32,
@@ -0,0 +1,8 @@
+use serde::{Deserialize, Serialize};
+
+/// How far an entity can see, everything outside the vision of a player's entities is hidden from that player.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Vision {
+    /// Radius in meters around the entity's position.
+    pub sight_radius: f32,
+}
@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+/// Lets a unit attack enemies.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Weapon {
+    /// Health taken from the target by each attack.
+    pub damage: f32,
+    /// Meters the target can be away, measured to the edge of buildings.
+    pub range: f32,
+    /// Seconds between attacks.
+    pub cooldown: f32,
+    /// Meters within which units that are allowed to chase enemies go after them.
+    pub acquisition_range: f32,
+    /// How the shots of a ranged weapon travel, `None` for weapons that hit right away.
+    #[serde(default)]
+    pub projectile: Option<ProjectileStats>,
+    /// Seconds until the weapon can attack again.
+    #[serde(default)]
+    pub ready_in: f32,
+}
+
+/// How the projectiles fired by a weapon travel.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct ProjectileStats {
+    /// Meters per second.
+    pub speed: f32,
+    /// Homing projectiles follow their target and always hit it. Other projectiles fly to where
+    /// the target was when they were fired, and hit every enemy within `splash_radius` of that point.
+    pub homing: bool,
+    #[serde(default)]
+    pub splash_radius: f32,
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+use crate::game::{
+    components::{AbilityType, PlayerId},
+    game_state::Unit,
+    victory::MatchEnded,
+};
+
+/// Something that happened during a tick that clients should be told about,
+/// besides the state itself.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum GameEvent {
+    UnitCreated {
+        unit: Unit,
+    },
+    /// A unit or building ran out of health and was removed.
+    UnitDestroyed {
+        id: String,
+        owner: Option<PlayerId>,
+    },
+    ProjectileFired(ProjectileFired),
+    ProjectileImpact(ProjectileImpact),
+    AbilityUsed(AbilityUsed),
+    /// A victory condition was met, the match is over.
+    MatchEnded(MatchEnded),
+    /// A command from the player could not be carried out.
+    CommandRejected {
+        player_id: PlayerId,
+        message: String,
+    },
+}
+
+/// A unit fired a projectile, which flies from `position` toward `impact` at `speed` meters per second.
+/// Homing projectiles follow their target, so they may hit somewhere else.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProjectileFired {
+    pub id: String,
+    pub attacker: String,
+    pub target: String,
+    pub owner: PlayerId,
+    pub position: (f32, f32),
+    pub impact: (f32, f32),
+    pub speed: f32,
+    pub homing: bool,
+}
+
+/// A projectile hit at `position`, damaging the units and buildings in `hit`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProjectileImpact {
+    pub id: String,
+    pub attacker: String,
+    pub target: String,
+    pub owner: PlayerId,
+    pub position: (f32, f32),
+    pub hit: Vec<String>,
+}
+
+/// A unit used an ability centered on `position`, affecting the units and buildings in `hit`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AbilityUsed {
+    pub caster: String,
+    pub owner: PlayerId,
+    pub ability: AbilityType,
+    pub position: (f32, f32),
+    pub hit: Vec<String>,
+}
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+
+/// Meters between the units of a formation.
+pub const FORMATION_SPACING: f32 = 2.;
+
+/// How a group of units lines up around the destination of a group move.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Formation {
+    /// A single row, side by side.
+    Line,
+    /// Rows about as wide as the group is deep.
+    #[default]
+    Box,
+    /// A point in front, with every row behind it one unit wider.
+    Wedge,
+}
+
+impl Formation {
+    /// How many units are in each row, from the front row back.
+    fn rows(self, count: usize) -> Vec<usize> {
+        let width = match self {
+            Formation::Line => count.max(1),
+            Formation::Box => (count as f32).sqrt().ceil().max(1.) as usize,
+            Formation::Wedge => {
+                let mut rows = Vec::new();
+                let mut placed = 0;
+                while placed < count {
+                    let row = (rows.len() + 1).min(count - placed);
+                    rows.push(row);
+                    placed += row;
+                }
+                return rows;
+            }
+        };
+        let mut rows = vec![width; count / width];
+        let last_row = count % width;
+        if last_row > 0 {
+            rows.push(last_row);
+        }
+        rows
+    }
+
+    /// Gives each of the units at `positions` a slot in the formation around `target`,
+    /// with the front of the formation facing the way the group has to move.
+    /// Units keep their place in the group, the units in front get the front slots
+    /// and the units on the left get the left slots.
+    pub fn slots(self, positions: &[(f32, f32)], target: (f32, f32)) -> Vec<(f32, f32)> {
+        if positions.is_empty() {
+            return Vec::new();
+        }
+        let count = positions.len() as f32;
+        let center = (
+            positions.iter().map(|p| p.0).sum::<f32>() / count,
+            positions.iter().map(|p| p.1).sum::<f32>() / count,
+        );
+        let (dx, dy) = (target.0 - center.0, target.1 - center.1);
+        let length = (dx * dx + dy * dy).sqrt();
+        let forward = if length > 0. {
+            (dx / length, dy / length)
+        } else {
+            (1., 0.)
+        };
+        let left = (-forward.1, forward.0);
+        let along =
+            |p: (f32, f32), axis: (f32, f32)| (p.0 - center.0) * axis.0 + (p.1 - center.1) * axis.1;
+
+        let mut order: Vec<usize> = (0..positions.len()).collect();
+        order.sort_by(|a, b| {
+            along(positions[*b], forward).total_cmp(&along(positions[*a], forward))
+        });
+        let rows = self.rows(positions.len());
+        let mut slots = vec![target; positions.len()];
+        let mut start = 0;
+        for (row, row_size) in rows.iter().enumerate() {
+            let units = &mut order[start..start + row_size];
+            units.sort_by(|a, b| along(positions[*b], left).total_cmp(&along(positions[*a], left)));
+            // Rows are centered on the target, both across and along the direction of the move
+            let forward_offset = ((rows.len() - 1) as f32 / 2. - row as f32) * FORMATION_SPACING;
+            for (column, unit) in units.iter().enumerate() {
+                let left_offset = ((row_size - 1) as f32 / 2. - column as f32) * FORMATION_SPACING;
+                slots[*unit] = (
+                    target.0 + forward.0 * forward_offset + left.0 * left_offset,
+                    target.1 + forward.1 * forward_offset + left.1 * left_offset,
+                );
+            }
+            start += row_size;
+        }
+        slots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_fill_the_shape_of_the_formation() {
+        assert_eq!(Formation::Line.rows(4), vec![4]);
+        assert_eq!(Formation::Box.rows(4), vec![2, 2]);
+        assert_eq!(Formation::Box.rows(5), vec![3, 2]);
+        assert_eq!(Formation::Wedge.rows(6), vec![1, 2, 3]);
+        assert_eq!(Formation::Wedge.rows(4), vec![1, 2, 1]);
+    }
+
+    #[test]
+    fn no_units_have_no_slots() {
+        assert!(Formation::Box.slots(&[], (10., 10.)).is_empty());
+    }
+
+    #[test]
+    fn units_keep_their_place_across_a_line() {
+        let positions = [(0., 2.), (0., -2.), (0., 0.)];
+        let slots = Formation::Line.slots(&positions, (10., 0.));
+        assert_eq!(slots, vec![(10., 2.), (10., -2.), (10., 0.)]);
+    }
+
+    #[test]
+    fn units_in_front_get_the_front_slots() {
+        // Moving down, so the unit with the highest y is in front
+        let positions = [(0., 0.), (1., 0.), (0., 1.), (1., 1.)];
+        let slots = Formation::Box.slots(&positions, (0.5, 20.));
+        for (slot, position) in slots.iter().zip(&positions) {
+            let front = position.1 == 1.;
+            assert_eq!(slot.1, if front { 21. } else { 19. });
+        }
+        let mut xs: Vec<f32> = slots.iter().map(|slot| slot.0).collect();
+        xs.sort_by(|a, b| a.total_cmp(b));
+        assert_eq!(xs, vec![-0.5, -0.5, 1.5, 1.5]);
+    }
+
+    #[test]
+    fn a_wedge_has_one_unit_at_its_point() {
+        let positions: Vec<(f32, f32)> = (0..6).map(|i| (i as f32, 0.)).collect();
+        let slots = Formation::Wedge.slots(&positions, (2.5, -30.));
+        let front = slots
+            .iter()
+            .map(|slot| slot.1)
+            .fold(f32::INFINITY, f32::min);
+        assert_eq!(front, -32.);
+        assert_eq!(slots.iter().filter(|slot| slot.1 == front).count(), 1);
+        assert_eq!(slots.iter().filter(|slot| slot.1 == -28.).count(), 3);
+    }
+}
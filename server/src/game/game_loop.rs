@@ -0,0 +1,455 @@
+use std::{io, path::PathBuf};
+
+use legion::{systems::CommandBuffer, Resources, Schedule, World};
+
+use crate::{
+    game::{
+        bots::Bot,
+        commands::GameCommand,
+        journal::{CommandJournal, Replay},
+        resources::{
+            EconomyResource, EventsResource, MapResource, MatchResource, StatsResource,
+            TeamsResource, TimeResource, VisibilityResource, TICK_DURATION,
+        },
+        save_game::{read_save, restore_world, save_world, SaveFile},
+        schedule::create_schedule,
+        scripting::Script,
+        victory::VictoryCondition,
+    },
+    UidEntityMap,
+};
+
+/// The world and the systems that run it, with the bots, the script and the journal.
+/// The game thread runs one tick of it every `TICK_DURATION`.
+pub struct Game {
+    pub world: World,
+    pub resources: Resources,
+    schedule: Schedule,
+    map: MapResource,
+    victory_conditions: Vec<VictoryCondition>,
+    /// File the world is written to and read from by the save and load commands.
+    save_file: PathBuf,
+    pub bots: Vec<Bot>,
+    pub script: Option<Script>,
+    /// Where commands come from instead of clients, bots and the script, when replaying.
+    pub replay: Option<Replay>,
+    pub journal: Option<CommandJournal>,
+}
+
+impl Game {
+    /// A game on `map`, starting from `start` or from what is on the map if there is no save to start from.
+    pub fn new(
+        map: MapResource,
+        victory_conditions: Vec<VictoryCondition>,
+        save_file: PathBuf,
+        start: Option<SaveFile>,
+    ) -> io::Result<Game> {
+        let mut game = Game {
+            world: World::default(),
+            resources: Resources::default(),
+            schedule: create_schedule(),
+            map,
+            victory_conditions,
+            save_file,
+            bots: Vec::new(),
+            script: None,
+            replay: None,
+            journal: None,
+        };
+        let resources = &mut game.resources;
+        resources.insert(TimeResource::default());
+        resources.insert(UidEntityMap::default());
+        resources.insert(VisibilityResource::default());
+        resources.insert(TeamsResource::default());
+        resources.insert(EconomyResource::default());
+        resources.insert(EventsResource::default());
+        resources.insert(game.map.clone());
+        resources.insert(MatchResource::new(game.victory_conditions.clone()));
+        resources.insert(StatsResource::default());
+
+        match start {
+            Some(save) => restore_world(save, &mut game.world, &mut game.resources)?,
+            None => {
+                let mut command_buffer = CommandBuffer::new(&game.world);
+                push_map_commands(&mut command_buffer, &game.map);
+                command_buffer.flush(&mut game.world, &mut game.resources);
+            }
+        }
+        Ok(game)
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.resources
+            .get::<TimeResource>()
+            .expect("Must have a time resource")
+            .ticks
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.resources
+            .get::<MatchResource>()
+            .expect("Must have a match resource")
+            .is_over()
+    }
+
+    /// Runs one tick with the commands clients sent since the last one,
+    /// which are ignored when replaying. Returns whether the match ended this tick.
+    pub fn tick(&mut self, mut commands: Vec<GameCommand>) -> bool {
+        let tick = self.ticks();
+        let match_over = self.is_over();
+        if let Some(replay) = &mut self.replay {
+            let was_finished = replay.is_finished();
+            commands = replay.commands_for_tick(tick);
+            if replay.is_finished() && !was_finished {
+                println!("Replay finished at tick {}", tick);
+            }
+        } else if !match_over {
+            // Bot and script commands are journaled with the rest, so replays don't run them.
+            // Once the match is decided they would only be ignored, so they stop being asked
+            for bot in &mut self.bots {
+                commands.extend(bot.commands(&self.world, &self.resources));
+            }
+            if let Some(script) = &mut self.script {
+                commands.extend(script.commands(&self.world, &self.resources));
+            }
+        }
+
+        // Saving and loading are done after the schedule, so they see the commands of this tick
+        let mut world_commands = Vec::new();
+        let mut command_buffer = CommandBuffer::new(&self.world);
+        for command in commands {
+            // Loads are journaled with the world they load once they are done
+            if let (Some(journal), false) = (
+                &mut self.journal,
+                matches!(command, GameCommand::LoadGameCommand),
+            ) {
+                if let Err(e) = journal.record(tick, &command) {
+                    eprintln!("error recording command: {}", e);
+                }
+            }
+            match command {
+                GameCommand::ResetGameCommand => {
+                    self.world.clear();
+                    self.resources.insert(UidEntityMap::default());
+                    self.resources.insert(VisibilityResource::default());
+                    self.resources.insert(EconomyResource::default());
+                    self.resources
+                        .insert(MatchResource::new(self.victory_conditions.clone()));
+                    self.resources.insert(StatsResource::default());
+                    push_map_commands(&mut command_buffer, &self.map);
+                    if let Some(script) = &mut self.script {
+                        script.restart();
+                    }
+                }
+                // Replays don't save, so they don't overwrite the save of the game that is being played
+                GameCommand::SaveGameCommand if self.replay.is_some() => {}
+                GameCommand::SaveGameCommand | GameCommand::LoadGameCommand => {
+                    world_commands.push(command)
+                }
+                // Clients are told the match is over, this catches commands sent as it ended
+                _ if match_over => {}
+                // The extra 1 here, is to get around bug that you need 2 components when pushing to buffer
+                command => {
+                    command_buffer.push((command, 1));
+                }
+            }
+        }
+        if let Some(journal) = &mut self.journal {
+            if let Err(e) = journal.flush() {
+                eprintln!("error writing command journal: {}", e);
+            }
+        }
+        command_buffer.flush(&mut self.world, &mut self.resources);
+
+        // The world stays as it was when the match ended, until it is reset or loaded
+        let mut ended = false;
+        if !self.is_over() {
+            self.schedule.execute(&mut self.world, &mut self.resources);
+            ended = self.is_over();
+        }
+        for command in world_commands {
+            if let GameCommand::SaveGameCommand = command {
+                self.save();
+            } else {
+                self.load(tick);
+            }
+        }
+
+        let mut time = self
+            .resources
+            .get_mut::<TimeResource>()
+            .expect("Must have a time resource");
+        time.ticks += 1;
+        time.elapsed_seconds = TICK_DURATION.as_secs_f64();
+        ended
+    }
+
+    fn save(&self) {
+        match save_world(&self.save_file, &self.world, &self.resources) {
+            Ok(()) => println!("Saved world to {:?}", self.save_file),
+            Err(e) => eprintln!("error saving world: {}", e),
+        }
+    }
+
+    fn load(&mut self, tick: u64) {
+        // Replays load the world the match loaded, not whatever the save file holds now
+        let save = match &mut self.replay {
+            Some(replay) => replay.take_loaded_world().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the journal has no world for this load",
+                )
+            }),
+            None => read_save(&self.save_file),
+        };
+        let (journal, world, resources) = (&mut self.journal, &mut self.world, &mut self.resources);
+        let loaded = save.and_then(|save| {
+            if let Some(journal) = journal {
+                if let Err(e) = journal.record_load(tick, &save) {
+                    eprintln!("error recording command: {}", e);
+                }
+            }
+            restore_world(save, world, resources)
+        });
+        match loaded {
+            Ok(()) if self.replay.is_some() => println!("Loaded world from the replay"),
+            Ok(()) => println!("Loaded world from {:?}", self.save_file),
+            Err(e) => eprintln!("error loading world: {}", e),
+        }
+    }
+}
+
+/// Helpers for the tests of the systems, which run on a game with nothing on an open field.
+#[cfg(test)]
+impl Game {
+    pub fn empty() -> Game {
+        let game = Game::new(MapResource::default(), Vec::new(), PathBuf::new(), None)
+            .expect("An empty game starts from nothing");
+        // As it is after the first tick, so systems that scale with time do something
+        game.resources
+            .get_mut::<TimeResource>()
+            .expect("Must have a time resource")
+            .elapsed_seconds = TICK_DURATION.as_secs_f64();
+        game
+    }
+
+    pub fn spawn_unit(
+        &mut self,
+        id: &str,
+        unit_type: crate::game::components::UnitType,
+        position: (f32, f32),
+        owner: crate::game::components::PlayerId,
+    ) -> legion::Entity {
+        let unit = crate::game::game_state::Unit {
+            position,
+            destination: position,
+            id: id.to_string(),
+            owner: Some(owner),
+            unit_type,
+            ..Default::default()
+        };
+        let mut command_buffer = CommandBuffer::new(&self.world);
+        let entity = crate::game::spawn::spawn_unit(&mut command_buffer, &unit);
+        self.spawned(&mut command_buffer, id, entity)
+    }
+
+    pub fn spawn_building(
+        &mut self,
+        id: &str,
+        building_type: crate::game::components::BuildingType,
+        position: (f32, f32),
+        owner: crate::game::components::PlayerId,
+    ) -> legion::Entity {
+        let mut command_buffer = CommandBuffer::new(&self.world);
+        let entity = crate::game::spawn::spawn_building(
+            &mut command_buffer,
+            id,
+            building_type,
+            position,
+            Some(owner),
+            None,
+            true,
+        );
+        self.spawned(&mut command_buffer, id, entity)
+    }
+
+    fn spawned(
+        &mut self,
+        command_buffer: &mut CommandBuffer,
+        id: &str,
+        entity: legion::Entity,
+    ) -> legion::Entity {
+        command_buffer.flush(&mut self.world, &mut self.resources);
+        self.resources
+            .get_mut::<UidEntityMap>()
+            .expect("Must have a uid entity map")
+            .insert(id.to_string(), entity);
+        entity
+    }
+
+    /// Runs `system` alone, then moves time on a tick.
+    pub fn run<S: legion::systems::ParallelRunnable + 'static>(&mut self, system: S) {
+        Schedule::builder()
+            .add_system(system)
+            .build()
+            .execute(&mut self.world, &mut self.resources);
+        self.resources
+            .get_mut::<TimeResource>()
+            .expect("Must have a time resource")
+            .ticks += 1;
+    }
+
+    /// Runs `commands` through `handle_commands` alone.
+    pub fn handle(&mut self, commands: Vec<GameCommand>) {
+        for command in commands {
+            self.world.push((command, 1));
+        }
+        self.run(crate::game::systems::handle_commands_system(
+            Default::default(),
+        ));
+    }
+
+    /// The messages of the commands rejected since the last call.
+    pub fn rejections(&mut self) -> Vec<String> {
+        let mut events = self
+            .resources
+            .get_mut::<EventsResource>()
+            .expect("Must have an events resource");
+        events
+            .events
+            .drain(..)
+            .filter_map(|event| match event {
+                crate::game::events::GameEvent::CommandRejected { message, .. } => Some(message),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn component<T: legion::storage::Component + Clone>(&self, id: &str) -> Option<T> {
+        let id_map = self
+            .resources
+            .get::<UidEntityMap>()
+            .expect("Must have a uid entity map");
+        let entry = legion::EntityStore::entry_ref(&self.world, *id_map.get(id)?).ok()?;
+        entry.get_component::<T>().ok().cloned()
+    }
+}
+
+/// Puts the things on the map into a fresh world, they are not journaled as they are the same every time.
+fn push_map_commands(command_buffer: &mut CommandBuffer, map: &MapResource) {
+    // The extra 1 here, is to get around bug that you need 2 components when pushing to buffer
+    for command in map.definition.setup_commands() {
+        command_buffer.push((command, 1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use legion::IntoQuery;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::game::{
+        bots::{BotConfig, Difficulty},
+        components::{Health, Position, UnitId},
+        journal::{JournalHeader, JOURNAL_VERSION},
+        map_generator,
+        victory::VictoryCondition,
+    };
+
+    fn units(game: &Game) -> Vec<(String, (f32, f32), f32)> {
+        let mut units: Vec<_> = <(&UnitId, &Position, &Health)>::query()
+            .iter(&game.world)
+            .map(|(id, position, health)| (id.id.clone(), (position.x, position.y), health.current))
+            .collect();
+        units.sort_by(|a, b| a.0.cmp(&b.0));
+        units
+    }
+
+    #[test]
+    fn replays_reproduce_the_recorded_match() {
+        let dir = env::temp_dir().join(format!("thebsgame-replay-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let journal_path = dir.join("journal.jsonl");
+        let script_path = dir.join("script.rhai");
+        fs::write(
+            &script_path,
+            "at_tick(2, || { spawn_unit(\"Soldier\", 20, 20, 0); });
+             at_tick(4, || { for id in units_of(0) { move_unit(id, 40, 30); } });",
+        )
+        .unwrap();
+        let map = MapResource::new(map_generator::generate(7, 2)).unwrap();
+        let bot = BotConfig {
+            difficulty: Difficulty::Hard,
+            team: None,
+        };
+        let save_file = dir.join("save.json");
+
+        let mut game = Game::new(map.clone(), Vec::new(), save_file.clone(), None).unwrap();
+        game.bots = vec![Bot::new(0, bot, &map)];
+        game.script = Some(Script::load(&script_path, &map).unwrap());
+        let header = JournalHeader {
+            version: JOURNAL_VERSION,
+            map: map.definition.clone(),
+            players: 2,
+            start: None,
+            victory_conditions: Vec::new(),
+            bots: vec![bot],
+        };
+        game.journal = Some(CommandJournal::create(&journal_path, &header).unwrap());
+        for _ in 0..60 {
+            game.tick(Vec::new());
+        }
+        game.journal = None;
+        let recorded = units(&game);
+
+        let mut replayed = Game::new(map, Vec::new(), save_file, None).unwrap();
+        replayed.replay = Some(Replay::open(&journal_path).unwrap());
+        for _ in 0..60 {
+            replayed.tick(Vec::new());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+
+        // The bot builds a headquarters and workers, the script spawns a soldier
+        assert!(recorded.len() > 2);
+        assert_eq!(units(&replayed), recorded);
+    }
+
+    #[test]
+    fn bots_stop_once_the_match_is_over() {
+        let dir = env::temp_dir().join(format!("thebsgame-over-{}", Uuid::new_v4()));
+        let journal_path = dir.join("journal.jsonl");
+        let map = MapResource::new(map_generator::generate(7, 2)).unwrap();
+        let bot = BotConfig {
+            difficulty: Difficulty::Hard,
+            team: None,
+        };
+        let conditions = vec![VictoryCondition::TimeLimit { seconds: 0. }];
+        let mut game =
+            Game::new(map.clone(), conditions.clone(), dir.join("save.json"), None).unwrap();
+        game.bots = vec![Bot::new(0, bot, &map)];
+        let header = JournalHeader {
+            version: JOURNAL_VERSION,
+            map: map.definition.clone(),
+            players: 1,
+            start: None,
+            victory_conditions: conditions,
+            bots: vec![bot],
+        };
+        game.journal = Some(CommandJournal::create(&journal_path, &header).unwrap());
+
+        assert!(game.tick(Vec::new()));
+        let journaled = fs::read_to_string(&journal_path).unwrap().lines().count();
+        for _ in 0..10 {
+            assert!(!game.tick(Vec::new()));
+        }
+        let lines = fs::read_to_string(&journal_path).unwrap().lines().count();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // The header and the first moves of the bot, nothing after the match ended
+        assert!(journaled > 1);
+        assert_eq!(lines, journaled);
+    }
+}
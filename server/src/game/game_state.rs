@@ -1,15 +1,301 @@
 use std::collections::HashMap;
 
+use legion::{EntityStore, IntoQuery, Resources, World};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+use crate::game::{
+    components::{
+        AbilityCooldowns, AbilityType, BuildingType, Construction, DamageOverTime, Destination, Facing, Footprint, Health, Owner, PlayerId, Position, ProductionQueue,
+        RallyPoint, ResourceNode, Slow, Stun, Team, TeamId, UnitId, UnitType,
+    },
+    events::GameEvent,
+    resources::{
+        EconomyResource, EventsResource, MapResource, MatchResource, PlayerStats, StatsResource,
+        TimeResource, VisibilityResource,
+    },
+    status_effects::StatusEffect,
+    victory::MatchEnded,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Unit {
     pub position: (f32, f32),
-    pub destination: (f32, f32),    
+    pub destination: (f32, f32),
+    pub id: String,
+    /// The player controlling the unit, `None` for neutral units.
+    #[serde(default)]
+    pub owner: Option<PlayerId>,
+    /// The team of the owner, so clients can color allies.
+    #[serde(default)]
+    pub team: Option<TeamId>,
+    #[serde(default)]
+    pub unit_type: UnitType,
+    /// The direction the unit is heading in, in radians counter-clockwise from the x-axis.
+    #[serde(default)]
+    pub facing: f32,
+    /// The most health a unit can have is set by its `unit_type`.
+    #[serde(default)]
+    pub health: f32,
+    /// Ticks until each ability the unit used can be used again, only sent to the owner.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub cooldowns: HashMap<AbilityType, u64>,
+    /// The status effects on the unit, with the ticks they have left.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub status_effects: Vec<StatusEffect>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BuildingState {
+    pub position: (f32, f32),
     pub id: String,
+    pub building_type: BuildingType,
+    /// Width and height in meters, centered on the position.
+    pub footprint: (f32, f32),
+    pub owner: Option<PlayerId>,
+    pub team: Option<TeamId>,
+    pub health: f32,
+    pub max_health: f32,
+    /// How far construction is, in percent, the building works once it reaches 100.
+    pub completion: f32,
+    /// The units the building is producing, the first one is in production.
+    pub production_queue: Vec<UnitType>,
+    /// Seconds the first unit in the queue has been in production.
+    pub production_progress: f32,
+    pub rally_point: Option<(f32, f32)>,
+}
+
+impl BuildingState {
+    /// Reads the state of the building `entity` from the world, `None` if it is not a building.
+    pub fn read<S: EntityStore>(world: &S, entity: legion::Entity) -> Option<BuildingState> {
+        let entry = world.entry_ref(entity).ok()?;
+        let position = entry.get_component::<Position>().ok()?;
+        let footprint = entry.get_component::<Footprint>().ok()?;
+        let health = entry.get_component::<Health>().ok()?;
+        let queue = entry.get_component::<ProductionQueue>().ok();
+        Some(BuildingState {
+            position: (position.x, position.y),
+            id: entry.get_component::<UnitId>().ok()?.id.clone(),
+            building_type: *entry.get_component::<BuildingType>().ok()?,
+            footprint: (footprint.width, footprint.height),
+            owner: entry.get_component::<Owner>().ok().map(|o| o.player_id),
+            team: entry.get_component::<Team>().ok().map(|t| t.team_id),
+            health: health.current,
+            max_health: health.max,
+            completion: entry
+                .get_component::<Construction>()
+                .map(|construction| construction.progress * 100.)
+                .unwrap_or(100.),
+            production_queue: queue
+                .map(|q| q.queue.iter().copied().collect())
+                .unwrap_or_default(),
+            production_progress: queue.map(|q| q.progress).unwrap_or(0.),
+            rally_point: entry
+                .get_component::<RallyPoint>()
+                .ok()
+                .map(|rally| (rally.x, rally.y)),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ResourceNodeState {
+    pub position: (f32, f32),
+    pub amount: u32,
 }
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
 pub struct GameStateCache {
     //TODO Change this to https://docs.rs/chashmap/2.2.2/chashmap/
     pub units: HashMap<String, Unit>,
+    pub buildings: HashMap<String, BuildingState>,
+    /// Resource nodes are known to everyone, they are part of the map.
+    pub resource_nodes: HashMap<String, ResourceNodeState>,
+    /// The resources in each player's bank.
+    pub banks: HashMap<PlayerId, u32>,
+    /// The seed the map was generated from, `None` for maps loaded from a file.
+    pub map_seed: Option<u64>,
+    /// How the match ended, once it is over.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub match_result: Option<MatchEnded>,
+    /// What every player has done so far, served at `/stats` rather than sent with the state.
+    #[serde(skip)]
+    pub stats: HashMap<PlayerId, PlayerStats>,
+    /// Events of the last tick, sent to clients separately from the state.
+    #[serde(skip)]
+    pub events: Vec<GameEvent>,
+    /// What each player can see, used to filter the state before it is sent to a player.
+    #[serde(skip)]
+    pub visibility: VisibilityResource,
+}
+
+impl GameStateCache {
+    /// Builds the state sent to clients from the world, after a tick.
+    /// This takes the events of the tick out of the `EventsResource`.
+    pub fn from_world(world: &World, resources: &Resources) -> GameStateCache {
+        let mut game_state = GameStateCache::default();
+        <(
+            &Position,
+            Option<&Destination>,
+            &UnitId,
+            Option<&Owner>,
+            Option<&Team>,
+            &UnitType,
+            Option<&Facing>,
+            &Health,
+        )>::query()
+        .for_each(world, |(pos, des_op, id, owner, team, unit_type, facing, health)| {
+            let des = des_op.map(|s| (s.x, s.y)).unwrap_or((pos.x, pos.y));
+            game_state.units.insert(
+                id.id.clone(),
+                Unit {
+                    destination: des,
+                    position: (pos.x, pos.y),
+                    id: id.id.clone(),
+                    owner: owner.map(|o| o.player_id),
+                    team: team.map(|t| t.team_id),
+                    unit_type: *unit_type,
+                    facing: facing.map(|f| f.angle).unwrap_or(0.),
+                    health: health.current,
+                    cooldowns: HashMap::new(),
+                    status_effects: Vec::new(),
+                },
+            );
+        });
+        let tick = resources
+            .get::<TimeResource>()
+            .expect("Must have a time resource")
+            .ticks;
+        <(&UnitId, &AbilityCooldowns)>::query().for_each(world, |(id, cooldowns)| {
+            if let Some(unit) = game_state.units.get_mut(&id.id) {
+                unit.cooldowns = cooldowns
+                    .ready_at
+                    .keys()
+                    .map(|ability| (*ability, cooldowns.ticks_left(*ability, tick)))
+                    .filter(|(_, ticks_left)| *ticks_left > 0)
+                    .collect();
+            }
+        });
+        let mut add_status_effect = |id: &UnitId, effect: StatusEffect| {
+            if let Some(unit) = game_state.units.get_mut(&id.id) {
+                unit.status_effects.push(effect);
+            }
+        };
+        <(&UnitId, &Slow)>::query().for_each(world, |(id, slow)| {
+            add_status_effect(
+                id,
+                StatusEffect::Slow {
+                    factor: slow.factor,
+                    ticks: slow.ticks_left,
+                },
+            )
+        });
+        <(&UnitId, &Stun)>::query().for_each(world, |(id, stun)| {
+            add_status_effect(
+                id,
+                StatusEffect::Stun {
+                    ticks: stun.ticks_left,
+                },
+            )
+        });
+        <(&UnitId, &DamageOverTime)>::query().for_each(world, |(id, dot)| {
+            add_status_effect(
+                id,
+                StatusEffect::DamageOverTime {
+                    damage: dot.damage * dot.stacks as f32,
+                    ticks: dot.ticks_left,
+                },
+            )
+        });
+        <(legion::Entity, &BuildingType)>::query().for_each(world, |(entity, _)| {
+            if let Some(building) = BuildingState::read(world, *entity) {
+                game_state.buildings.insert(building.id.clone(), building);
+            }
+        });
+        <(&ResourceNode, &Position)>::query().for_each(world, |(node, pos)| {
+            game_state.resource_nodes.insert(
+                node.id.clone(),
+                ResourceNodeState {
+                    position: (pos.x, pos.y),
+                    amount: node.amount,
+                },
+            );
+        });
+        game_state.banks = resources
+            .get::<EconomyResource>()
+            .expect("Must have an economy resource")
+            .banks
+            .clone();
+        game_state.map_seed = resources
+            .get::<MapResource>()
+            .expect("Must have a map resource")
+            .definition
+            .seed;
+        let match_state = resources
+            .get::<MatchResource>()
+            .expect("Must have a match resource");
+        game_state.match_result = match_state.result.clone();
+        game_state.stats = resources
+            .get::<StatsResource>()
+            .expect("Must have a stats resource")
+            .summary(match_state.elapsed_seconds);
+        game_state.events = std::mem::take(
+            &mut resources
+                .get_mut::<EventsResource>()
+                .expect("Must have an events resource")
+                .events,
+        );
+        game_state.visibility = resources
+            .get::<VisibilityResource>()
+            .expect("Must have a visibility resource")
+            .clone();
+        game_state
+    }
+
+    /// The part of the game state `player_id` is allowed to see:
+    /// units inside the player's vision, structures where the player last saw them and the player's own bank.
+    /// Only the owner of a building sees what it is producing.
+    pub fn for_player(&self, player_id: PlayerId) -> GameStateCache {
+        let mut units = HashMap::new();
+        let mut buildings = HashMap::new();
+        if let Some(structures) = self.visibility.last_known_structures.get(&player_id) {
+            buildings.extend(structures.clone());
+        }
+        if let Some(visible) = self.visibility.visible.get(&player_id) {
+            for id in visible {
+                if let Some(unit) = self.units.get(id) {
+                    let mut unit = unit.clone();
+                    if unit.owner != Some(player_id) {
+                        unit.cooldowns.clear();
+                    }
+                    units.insert(id.clone(), unit);
+                }
+                if let Some(building) = self.buildings.get(id) {
+                    buildings.insert(id.clone(), building.clone());
+                }
+            }
+        }
+        for building in buildings.values_mut() {
+            if building.owner != Some(player_id) {
+                building.production_queue.clear();
+                building.production_progress = 0.;
+                building.rally_point = None;
+            }
+        }
+        let banks = self
+            .banks
+            .iter()
+            .filter(|(owner, _)| **owner == player_id)
+            .map(|(owner, bank)| (*owner, *bank))
+            .collect();
+        GameStateCache {
+            units,
+            buildings,
+            resource_nodes: self.resource_nodes.clone(),
+            banks,
+            map_seed: self.map_seed,
+            match_result: self.match_result.clone(),
+            stats: HashMap::new(),
+            events: Vec::new(),
+            visibility: VisibilityResource::default(),
+        }
+    }
 }
@@ -0,0 +1,160 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    iter::Peekable,
+    path::Path,
+    vec::IntoIter,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::{
+    bots::BotConfig, commands::GameCommand, map::MapDefinition, save_game::SaveFile,
+    victory::VictoryCondition,
+};
+
+/// Bump this whenever the layout of the journal changes in a way old replays can't be read.
+pub const JOURNAL_VERSION: u32 = 2;
+
+/// The first line of a journal file, with what the match started from so a replay starts from the same.
+#[derive(Serialize, Deserialize)]
+pub struct JournalHeader {
+    pub version: u32,
+    /// The map as it was played, whether it was loaded from a file or generated.
+    pub map: MapDefinition,
+    pub players: u32,
+    /// The world loaded with `--load`, the match starts from the map when there is none.
+    pub start: Option<SaveFile>,
+    pub victory_conditions: Vec<VictoryCondition>,
+    pub bots: Vec<BotConfig>,
+}
+
+/// A command and the tick it was drained from the command channel on.
+#[derive(Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub tick: u64,
+    pub command: GameCommand,
+    /// The world a `LoadGameCommand` loaded, so a replay loads the same one whatever the save file holds by then.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loaded: Option<SaveFile>,
+}
+
+/// Records every command the game loop handles, one json line per command,
+/// so a match can be replayed with `Replay`.
+pub struct CommandJournal {
+    writer: BufWriter<File>,
+}
+
+impl CommandJournal {
+    pub fn create(path: &Path, header: &JournalHeader) -> io::Result<CommandJournal> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut journal = CommandJournal {
+            writer: BufWriter::new(File::create(path)?),
+        };
+        journal.write_line(header)?;
+        Ok(journal)
+    }
+
+    pub fn record(&mut self, tick: u64, command: &GameCommand) -> io::Result<()> {
+        self.write_line(&JournalEntry {
+            tick,
+            command: command.clone(),
+            loaded: None,
+        })
+    }
+
+    /// Records a load with the world it loaded, loads are recorded once they are done rather than when they are asked for.
+    pub fn record_load(&mut self, tick: u64, save: &SaveFile) -> io::Result<()> {
+        self.write_line(&JournalEntry {
+            tick,
+            command: GameCommand::LoadGameCommand,
+            loaded: Some(save.clone()),
+        })?;
+        self.flush()
+    }
+
+    /// Writes the buffered commands to disk, called once per tick so a crash loses at most one tick.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, value)?;
+        self.writer.write_all(b"\n")
+    }
+}
+
+/// A journal read back from disk, handing out the commands of each tick in order.
+pub struct Replay {
+    /// What the recorded match started from.
+    pub header: JournalHeader,
+    entries: Peekable<IntoIter<JournalEntry>>,
+    /// The worlds of the loads handed out by `commands_for_tick`, for the game loop to take.
+    loaded_worlds: VecDeque<SaveFile>,
+}
+
+impl Replay {
+    pub fn open(path: &Path) -> io::Result<Replay> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        // The version is checked first, older headers don't have the fields of this one
+        let header: serde_json::Value = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)?,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "journal is empty",
+                ))
+            }
+        };
+        let version = header["version"].as_u64().unwrap_or_default();
+        if version != JOURNAL_VERSION as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "journal version {} is not supported, expected {}",
+                    version, JOURNAL_VERSION
+                ),
+            ));
+        }
+        let header: JournalHeader = serde_json::from_value(header)?;
+
+        let mut entries = Vec::new();
+        for line in lines {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push(serde_json::from_str::<JournalEntry>(&line)?);
+            }
+        }
+        Ok(Replay {
+            header,
+            entries: entries.into_iter().peekable(),
+            loaded_worlds: VecDeque::new(),
+        })
+    }
+
+    /// Returns the commands recorded on `tick`, assumes ticks are asked for in increasing order.
+    pub fn commands_for_tick(&mut self, tick: u64) -> Vec<GameCommand> {
+        let mut commands = Vec::new();
+        while let Some(entry) = self.entries.next_if(|entry| entry.tick <= tick) {
+            commands.push(entry.command);
+            // A loaded world has its own tick, the commands after the load are for the ticks that follow it
+            if let Some(save) = entry.loaded {
+                self.loaded_worlds.push_back(save);
+                break;
+            }
+        }
+        commands
+    }
+
+    /// The world to load for a `LoadGameCommand` handed out by `commands_for_tick`.
+    pub fn take_loaded_world(&mut self) -> Option<SaveFile> {
+        self.loaded_worlds.pop_front()
+    }
+
+    pub fn is_finished(&mut self) -> bool {
+        self.entries.peek().is_none()
+    }
+}
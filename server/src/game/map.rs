@@ -0,0 +1,224 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::{
+    commands::GameCommand,
+    components::{PlayerId, UnitType},
+    game_state::Unit,
+    tiled,
+};
+
+/// What a tile of the map is made of.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Terrain {
+    Grass,
+    /// Slows units down a lot.
+    Mud,
+    /// Slows units down a bit.
+    Forest,
+    Water,
+    Cliff,
+}
+
+impl Terrain {
+    /// The character a tile of this terrain is written as in `MapDefinition::tiles`.
+    pub fn from_char(c: char) -> Option<Terrain> {
+        match c {
+            '.' => Some(Terrain::Grass),
+            ',' => Some(Terrain::Mud),
+            '^' => Some(Terrain::Forest),
+            '~' => Some(Terrain::Water),
+            '#' => Some(Terrain::Cliff),
+            _ => None,
+        }
+    }
+
+    pub fn to_char(self) -> char {
+        match self {
+            Terrain::Grass => '.',
+            Terrain::Mud => ',',
+            Terrain::Forest => '^',
+            Terrain::Water => '~',
+            Terrain::Cliff => '#',
+        }
+    }
+
+    /// What the speed of units moving through the terrain is multiplied by, 0 where they can't go.
+    pub fn speed_multiplier(self) -> f32 {
+        match self {
+            Terrain::Grass => 1.,
+            Terrain::Mud => 0.5,
+            Terrain::Forest => 0.7,
+            Terrain::Water | Terrain::Cliff => 0.,
+        }
+    }
+
+    /// Whether units can stand on and buildings can be placed on the terrain.
+    pub fn is_passable(self) -> bool {
+        self.speed_multiplier() > 0.
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MapResourceNode {
+    pub position: (f32, f32),
+    pub amount: u32,
+}
+
+/// A unit that is on the map when the game starts, which its owner does not pay for.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MapUnit {
+    pub position: (f32, f32),
+    #[serde(default)]
+    pub unit_type: UnitType,
+    #[serde(default)]
+    pub owner: Option<PlayerId>,
+}
+
+/// A rectangle nothing can pass through or be placed on, centered on its position.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Obstacle {
+    pub position: (f32, f32),
+    pub width: f32,
+    pub height: f32,
+}
+
+/// A map as it is stored on disk and sent to clients.
+/// The map covers the meters from `(0, 0)` to `(width * tile_size, height * tile_size)`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MapDefinition {
+    pub name: String,
+    /// Size of the map in tiles.
+    pub width: u32,
+    pub height: u32,
+    /// Meters covered by the side of a tile.
+    pub tile_size: f32,
+    /// One string per row of tiles starting at `y = 0`, with one character per tile:
+    /// `.` is grass, `,` is mud, `^` is forest, `~` is water and `#` is a cliff.
+    pub tiles: Vec<String>,
+    /// Where players start, in meters.
+    #[serde(default)]
+    pub spawn_points: Vec<(f32, f32)>,
+    #[serde(default)]
+    pub resource_nodes: Vec<MapResourceNode>,
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
+    #[serde(default)]
+    pub units: Vec<MapUnit>,
+    /// The seed a generated map was made from, so the map can be made again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl MapDefinition {
+    /// A map of grass without anything on it.
+    pub fn open_field(width: u32, height: u32, tile_size: f32) -> MapDefinition {
+        MapDefinition {
+            name: "Open field".to_string(),
+            width,
+            height,
+            tile_size,
+            tiles: vec![
+                Terrain::Grass.to_char().to_string().repeat(width as usize);
+                height as usize
+            ],
+            spawn_points: Vec::new(),
+            resource_nodes: Vec::new(),
+            obstacles: Vec::new(),
+            units: Vec::new(),
+            seed: None,
+        }
+    }
+
+    /// Loads a map file, or a map saved by the Tiled editor as json or tmx.
+    pub fn load(path: &Path) -> io::Result<MapDefinition> {
+        let map = if path.extension().map(|e| e == "tmx").unwrap_or(false) {
+            tiled::load_tmx(path)?
+        } else {
+            let reader = BufReader::new(File::open(path)?);
+            let value: serde_json::Value = serde_json::from_reader(reader)?;
+            if tiled::is_tiled_json(&value) {
+                let name = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .unwrap_or("Tiled map");
+                tiled::from_json(value, name)?
+            } else {
+                serde_json::from_value(value)?
+            }
+        };
+        map.terrain()?;
+        Ok(map)
+    }
+
+    /// Parses `tiles` into one terrain per tile, row by row.
+    pub fn terrain(&self) -> io::Result<Vec<Terrain>> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        if self.width == 0 || self.height == 0 || self.tile_size <= 0. {
+            return Err(invalid("map must have at least one tile".to_string()));
+        }
+        if self.tiles.len() != self.height as usize {
+            return Err(invalid(format!(
+                "map has {} rows of tiles, expected {}",
+                self.tiles.len(),
+                self.height
+            )));
+        }
+        let mut terrain = Vec::with_capacity((self.width * self.height) as usize);
+        for (y, row) in self.tiles.iter().enumerate() {
+            if row.chars().count() != self.width as usize {
+                return Err(invalid(format!(
+                    "row {} of the map has {} tiles, expected {}",
+                    y,
+                    row.chars().count(),
+                    self.width
+                )));
+            }
+            for c in row.chars() {
+                terrain.push(
+                    Terrain::from_char(c)
+                        .ok_or_else(|| invalid(format!("unknown tile {:?} in row {}", c, y)))?,
+                );
+            }
+        }
+        Ok(terrain)
+    }
+
+    /// The commands that put the things on the map into a fresh world.
+    /// Ids are derived from the map, so they are the same every time the map is set up.
+    pub fn setup_commands(&self) -> Vec<GameCommand> {
+        let nodes = self.resource_nodes.iter().enumerate().map(|(i, node)| {
+            GameCommand::CreateResourceNodeCommand {
+                id: format!("map-node-{}", i),
+                position: node.position,
+                amount: node.amount,
+            }
+        });
+        let units = self
+            .units
+            .iter()
+            .enumerate()
+            .map(|(i, unit)| GameCommand::CreateUnitCommand {
+                unit: Unit {
+                    position: unit.position,
+                    destination: unit.position,
+                    id: format!("map-unit-{}", i),
+                    owner: unit.owner,
+                    team: None,
+                    unit_type: unit.unit_type,
+                    facing: 0.,
+                    health: unit.unit_type.definition().max_health,
+                    cooldowns: HashMap::new(),
+                    status_effects: Vec::new(),
+                },
+                free: true,
+            });
+        nodes.chain(units).collect()
+    }
+}
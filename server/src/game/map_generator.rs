@@ -0,0 +1,179 @@
+use std::f32::consts::TAU;
+
+use noise::{NoiseFn, Perlin};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::game::map::{MapDefinition, MapResourceNode, Terrain};
+
+const TILE_SIZE: f32 = 4.;
+/// How quickly the noise changes from tile to tile, lower gives bigger obstacle clusters.
+const NOISE_SCALE: f64 = 0.12;
+/// Noise above the cliff threshold is a cliff, with forest around it,
+/// and noise below the water threshold is water, with mud around it.
+const CLIFF_THRESHOLD: f64 = 0.45;
+const FOREST_THRESHOLD: f64 = 0.3;
+const WATER_THRESHOLD: f64 = -0.5;
+const MUD_THRESHOLD: f64 = -0.38;
+/// Radius in tiles kept clear around each spawn point, so there is room to build.
+const BASE_RADIUS: f32 = 7.;
+/// Width in tiles of the path cleared from each base to the center.
+const PATH_WIDTH: f32 = 2.;
+
+/// Generates a map for `players` from a seed, the same seed and player count always give the same map.
+///
+/// The map has rotational symmetry around its center, one slice per player,
+/// so every player has the same terrain and resources around their spawn point.
+pub fn generate(seed: u64, players: u32) -> MapDefinition {
+    let players = players.clamp(1, 8);
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let noise = Perlin::new(rng.gen());
+    let size = 40 + 8 * players;
+    let center = size as f32 / 2.;
+    let slice = TAU / players as f32;
+    let rotation: f32 = rng.gen_range(0. ..TAU);
+
+    // Every tile samples the noise at the same place in the first slice, which makes the slices equal
+    let mut terrain = vec![Terrain::Grass; (size * size) as usize];
+    for y in 0..size {
+        for x in 0..size {
+            let (dx, dy) = (x as f32 + 0.5 - center, y as f32 + 0.5 - center);
+            let angle = (dy.atan2(dx) - rotation).rem_euclid(slice);
+            let radius = (dx * dx + dy * dy).sqrt();
+            let value = noise.get([
+                (radius * angle.cos()) as f64 * NOISE_SCALE,
+                (radius * angle.sin()) as f64 * NOISE_SCALE,
+            ]);
+            terrain[(y * size + x) as usize] = if value > CLIFF_THRESHOLD {
+                Terrain::Cliff
+            } else if value > FOREST_THRESHOLD {
+                Terrain::Forest
+            } else if value < WATER_THRESHOLD {
+                Terrain::Water
+            } else if value < MUD_THRESHOLD {
+                Terrain::Mud
+            } else {
+                Terrain::Grass
+            };
+        }
+    }
+
+    // Where things are in the first slice, in tiles from the center, the other slices get them rotated
+    let spawn_distance = center * 0.7;
+    let spawn = (spawn_distance, slice / 2.);
+    let node_amount = rng.gen_range(10..=20) * 100;
+    let base_nodes = [
+        (spawn_distance + 5., slice / 2. + 0.25),
+        (spawn_distance + 5., slice / 2. - 0.25),
+    ];
+    let contested_node = (center * 0.5, 0.);
+
+    let to_tiles = |(distance, angle): (f32, f32), player: u32| {
+        let angle = angle + rotation + slice * player as f32;
+        (
+            center + distance * angle.cos(),
+            center + distance * angle.sin(),
+        )
+    };
+    let to_meters = |(x, y): (f32, f32)| (x * TILE_SIZE, y * TILE_SIZE);
+
+    let mut map = MapDefinition {
+        name: format!("Generated for {} players", players),
+        width: size,
+        height: size,
+        tile_size: TILE_SIZE,
+        tiles: Vec::new(),
+        spawn_points: Vec::new(),
+        resource_nodes: Vec::new(),
+        obstacles: Vec::new(),
+        units: Vec::new(),
+        seed: Some(seed),
+    };
+    for player in 0..players {
+        let spawn_tile = to_tiles(spawn, player);
+        clear_circle(&mut terrain, size, spawn_tile, BASE_RADIUS);
+        clear_line(&mut terrain, size, spawn_tile, (center, center), PATH_WIDTH);
+        map.spawn_points.push(to_meters(spawn_tile));
+
+        for node in base_nodes.iter().copied().chain([contested_node]) {
+            let node_tile = to_tiles(node, player);
+            clear_circle(&mut terrain, size, node_tile, 2.);
+            map.resource_nodes.push(MapResourceNode {
+                position: to_meters(node_tile),
+                amount: node_amount,
+            });
+        }
+    }
+    clear_circle(&mut terrain, size, (center, center), BASE_RADIUS / 2.);
+
+    map.tiles = terrain
+        .chunks(size as usize)
+        .map(|row| row.iter().map(|tile| tile.to_char()).collect())
+        .collect();
+    map
+}
+
+fn clear_circle(terrain: &mut [Terrain], size: u32, center: (f32, f32), radius: f32) {
+    for y in 0..size {
+        for x in 0..size {
+            let (dx, dy) = (x as f32 + 0.5 - center.0, y as f32 + 0.5 - center.1);
+            if dx * dx + dy * dy <= radius * radius {
+                terrain[(y * size + x) as usize] = Terrain::Grass;
+            }
+        }
+    }
+}
+
+fn clear_line(terrain: &mut [Terrain], size: u32, from: (f32, f32), to: (f32, f32), width: f32) {
+    let length = ((to.0 - from.0).powi(2) + (to.1 - from.1).powi(2)).sqrt();
+    let steps = length.ceil() as u32;
+    for step in 0..=steps {
+        let t = step as f32 / steps.max(1) as f32;
+        let point = (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+        clear_circle(terrain, size, point, width);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polar(map: &MapDefinition, position: (f32, f32)) -> (f32, f32) {
+        let center = map.width as f32 * map.tile_size / 2.;
+        let (dx, dy) = (position.0 - center, position.1 - center);
+        ((dx * dx + dy * dy).sqrt(), dy.atan2(dx))
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_map() {
+        let map = serde_json::to_string(&generate(42, 3)).unwrap();
+        assert_eq!(map, serde_json::to_string(&generate(42, 3)).unwrap());
+        assert_ne!(map, serde_json::to_string(&generate(43, 3)).unwrap());
+    }
+
+    #[test]
+    fn spawn_points_are_rotations_of_each_other() {
+        for players in 2..=4 {
+            let map = generate(7, players);
+            assert_eq!(map.spawn_points.len(), players as usize);
+            let slice = TAU / players as f32;
+            let (distance, first_angle) = polar(&map, map.spawn_points[0]);
+            for (player, spawn) in map.spawn_points.iter().enumerate() {
+                let (spawn_distance, angle) = polar(&map, *spawn);
+                assert!((spawn_distance - distance).abs() < 0.01);
+                let turned = (angle - first_angle - slice * player as f32).rem_euclid(TAU);
+                assert!(
+                    turned < 0.001 || TAU - turned < 0.001,
+                    "{} players",
+                    players
+                );
+
+                let tile = (
+                    (spawn.0 / map.tile_size) as usize,
+                    (spawn.1 / map.tile_size) as usize,
+                );
+                assert_eq!(map.tiles[tile.1].as_bytes()[tile.0], b'.');
+            }
+        }
+    }
+}
@@ -3,4 +3,22 @@ pub mod components;
 pub mod resources;
 pub mod systems;
 pub mod schedule;
-pub mod commands;
\ No newline at end of file
+pub mod commands;
+pub mod save_game;
+pub mod journal;
+pub mod unit_types;
+pub mod events;
+pub mod building_types;
+pub mod spawn;
+pub mod placement;
+pub mod map;
+pub mod tiled;
+pub mod map_generator;
+pub mod pathfinding;
+pub mod formation;
+pub mod combat;
+pub mod abilities;
+pub mod status_effects;
+pub mod victory;
+pub mod bots;
+pub mod scripting;pub mod game_loop;
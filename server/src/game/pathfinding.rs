@@ -0,0 +1,209 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, VecDeque},
+};
+
+use crate::game::{components::Route, resources::MapResource};
+
+/// A tile waiting to be looked at, the one with the lowest estimate is looked at first.
+struct Open {
+    estimate: f32,
+    tile: usize,
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for Open {}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed, as the binary heap pops the largest first
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+const NEIGHBOURS: [(i32, i32); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+impl Route {
+    /// Finds the quickest route from `from` to `to` over the tiles of the map with A*,
+    /// where moving onto a tile costs more the slower its terrain is.
+    /// Returns `None` when `to` can't be reached.
+    pub fn find(map: &MapResource, from: (f32, f32), to: (f32, f32)) -> Option<Route> {
+        let width = map.definition.width as usize;
+        let height = map.definition.height as usize;
+        let start = map.tile_at(map.clamp(from))?;
+        let goal = map.tile_at(to)?;
+        if map.tile_speed(goal) <= 0. {
+            return None;
+        }
+        let index = |tile: (usize, usize)| tile.1 * width + tile.0;
+        let tile_of = |index: usize| (index % width, index / width);
+        let tile_size = map.definition.tile_size;
+        let heuristic = |tile: (usize, usize)| {
+            let dx = tile.0 as f32 - goal.0 as f32;
+            let dy = tile.1 as f32 - goal.1 as f32;
+            (dx * dx + dy * dy).sqrt() * tile_size
+        };
+
+        let mut costs = vec![f32::INFINITY; width * height];
+        let mut came_from: Vec<Option<usize>> = vec![None; width * height];
+        let mut open = BinaryHeap::new();
+        costs[index(start)] = 0.;
+        open.push(Open {
+            estimate: heuristic(start),
+            tile: index(start),
+        });
+        while let Some(Open { tile: current, .. }) = open.pop() {
+            if current == index(goal) {
+                break;
+            }
+            let (x, y) = tile_of(current);
+            for (dx, dy) in NEIGHBOURS {
+                let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                    continue;
+                }
+                let next = (nx as usize, ny as usize);
+                let speed = map.tile_speed(next);
+                if speed <= 0. {
+                    continue;
+                }
+                // Moving diagonally past a corner would clip the tiles beside it
+                let diagonal = dx != 0 && dy != 0;
+                if diagonal
+                    && (map.tile_speed((nx as usize, y)) <= 0.
+                        || map.tile_speed((x, ny as usize)) <= 0.)
+                {
+                    continue;
+                }
+                let distance = if diagonal {
+                    std::f32::consts::SQRT_2
+                } else {
+                    1.
+                };
+                let cost = costs[current] + distance * tile_size / speed;
+                if cost < costs[index(next)] {
+                    costs[index(next)] = cost;
+                    came_from[index(next)] = Some(current);
+                    open.push(Open {
+                        estimate: cost + heuristic(next),
+                        tile: index(next),
+                    });
+                }
+            }
+        }
+        if start != goal && came_from[index(goal)].is_none() {
+            return None;
+        }
+
+        let mut tiles = vec![goal];
+        while let Some(previous) = came_from[index(*tiles.last().expect("Starts with the goal"))] {
+            tiles.push(tile_of(previous));
+        }
+        tiles.reverse();
+        // Only the tiles where the route turns are needed, it goes straight between them
+        let mut waypoints: VecDeque<(f32, f32)> = VecDeque::new();
+        for (i, tile) in tiles.iter().enumerate() {
+            let turns = match (i.checked_sub(1).map(|p| tiles[p]), tiles.get(i + 1)) {
+                (Some(previous), Some(next)) => {
+                    (tile.0 as i32 - previous.0 as i32, tile.1 as i32 - previous.1 as i32)
+                        != (next.0 as i32 - tile.0 as i32, next.1 as i32 - tile.1 as i32)
+                }
+                _ => true,
+            };
+            if turns {
+                waypoints.push_back(map.tile_center(*tile));
+            }
+        }
+        waypoints.push_back(to);
+        Some(Route {
+            waypoints,
+            destination: to,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::map::MapDefinition;
+
+    fn map(tiles: &[&str]) -> MapResource {
+        let (width, height) = (tiles[0].len() as u32, tiles.len() as u32);
+        let mut definition = MapDefinition::open_field(width, height, 1.);
+        definition.tiles = tiles.iter().map(|row| row.to_string()).collect();
+        MapResource::new(definition).expect("Map should be valid")
+    }
+
+    /// Whether a unit walking the route from `from` stays on tiles it can stand on.
+    fn walkable(map: &MapResource, from: (f32, f32), route: &Route) -> bool {
+        let mut position = from;
+        route.waypoints.iter().all(|waypoint| {
+            let steps = 20;
+            let clear = (0..=steps).all(|step| {
+                let t = step as f32 / steps as f32;
+                map.is_passable((
+                    position.0 + (waypoint.0 - position.0) * t,
+                    position.1 + (waypoint.1 - position.1) * t,
+                ))
+            });
+            position = *waypoint;
+            clear
+        })
+    }
+
+    #[test]
+    fn goes_straight_over_open_ground() {
+        let map = map(&["......"]);
+        let route = Route::find(&map, (0.5, 0.5), (5.2, 0.5)).expect("Should find a route");
+        assert_eq!(route.waypoints, vec![(0.5, 0.5), (5.5, 0.5), (5.2, 0.5)]);
+        assert_eq!(route.destination, (5.2, 0.5));
+    }
+
+    #[test]
+    fn goes_around_walls() {
+        let map = map(&["..#..", "..#..", "..#..", "..#..", "....."]);
+        let route = Route::find(&map, (0.5, 0.5), (4.5, 0.5)).expect("Should find a route");
+        assert!(walkable(&map, (0.5, 0.5), &route));
+        assert!(route.waypoints.iter().any(|waypoint| waypoint.1 > 4.));
+        assert_eq!(route.waypoints.back(), Some(&(4.5, 0.5)));
+    }
+
+    #[test]
+    fn goes_around_mud_when_it_is_quicker() {
+        let map = map(&[".......", ".,,,,,."]);
+        let route = Route::find(&map, (0.5, 1.5), (6.5, 1.5)).expect("Should find a route");
+        // Without the start, the center of the goal tile and the destination
+        let turns = route.waypoints.len() - 3;
+        let between: Vec<&(f32, f32)> = route.waypoints.iter().skip(1).take(turns).collect();
+        assert!(!between.is_empty());
+        assert!(between.iter().all(|waypoint| waypoint.1 == 0.5), "{:?}", route.waypoints);
+    }
+
+    #[test]
+    fn finds_no_route_to_unreachable_tiles() {
+        let map = map(&["..~..", "..~..", "..~.."]);
+        assert!(Route::find(&map, (0.5, 0.5), (4.5, 1.5)).is_none());
+        assert!(Route::find(&map, (0.5, 0.5), (2.5, 1.5)).is_none());
+        assert!(Route::find(&map, (0.5, 0.5), (10., 1.5)).is_none());
+    }
+}
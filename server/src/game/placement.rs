@@ -0,0 +1,50 @@
+use legion::{EntityStore, IntoQuery};
+
+use crate::game::{
+    components::{BuildingType, Footprint, Position, ResourceNode, UnitType},
+    resources::MapResource,
+};
+
+/// Checks that a building of `building_type` fits at `position`,
+/// returns why it doesn't as a message for the player.
+/// `placed` holds the buildings that are placed but not in the world yet.
+pub fn check_placement<W: EntityStore>(
+    world: &W,
+    map: &MapResource,
+    building_type: BuildingType,
+    position: (f32, f32),
+    placed: &[(Position, Footprint)],
+) -> Result<(), String> {
+    let (width, height) = building_type.definition().footprint;
+    let overlaps = |x: f32, y: f32, other_width: f32, other_height: f32| {
+        (position.0 - x).abs() * 2. < width + other_width
+            && (position.1 - y).abs() * 2. < height + other_height
+    };
+
+    if !map.is_area_passable(position, (width, height)) {
+        return Err(format!(
+            "A {:?} has to be placed on open ground inside the map",
+            building_type
+        ));
+    }
+    if <(&Footprint, &Position)>::query()
+        .iter(world)
+        .chain(placed.iter().map(|(pos, footprint)| (footprint, pos)))
+        .any(|(footprint, pos)| overlaps(pos.x, pos.y, footprint.width, footprint.height))
+    {
+        return Err(format!("A {:?} there would overlap another building", building_type));
+    }
+    if <(&UnitType, &Position)>::query()
+        .iter(world)
+        .any(|(_, pos)| overlaps(pos.x, pos.y, 0., 0.))
+    {
+        return Err(format!("Units are in the way of the {:?}", building_type));
+    }
+    if <(&ResourceNode, &Position)>::query()
+        .iter(world)
+        .any(|(_, pos)| overlaps(pos.x, pos.y, 0., 0.))
+    {
+        return Err(format!("A {:?} can not be placed on a resource node", building_type));
+    }
+    Ok(())
+}
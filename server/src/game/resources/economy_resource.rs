@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::PlayerId;
+
+/// Resources a player has before gathering anything.
+pub const STARTING_RESOURCES: u32 = 500;
+
+/// Resource with the bank of every player, filled by harvesters and spent on new units.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct EconomyResource {
+    pub banks: HashMap<PlayerId, u32>,
+}
+
+impl EconomyResource {
+    pub fn balance(&self, player_id: PlayerId) -> u32 {
+        self.banks
+            .get(&player_id)
+            .copied()
+            .unwrap_or(STARTING_RESOURCES)
+    }
+
+    pub fn deposit(&mut self, player_id: PlayerId, amount: u32) {
+        *self.banks.entry(player_id).or_insert(STARTING_RESOURCES) += amount;
+    }
+
+    /// Takes `amount` from the player's bank, returns false and takes nothing if the player can't afford it.
+    pub fn try_spend(&mut self, player_id: PlayerId, amount: u32) -> bool {
+        let bank = self.banks.entry(player_id).or_insert(STARTING_RESOURCES);
+        if *bank < amount {
+            return false;
+        }
+        *bank -= amount;
+        true
+    }
+}
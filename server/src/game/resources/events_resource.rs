@@ -0,0 +1,7 @@
+use crate::game::events::GameEvent;
+
+/// Resource systems push events to during a tick, the game loop sends them to clients after the tick.
+#[derive(Default, Clone, Debug)]
+pub struct EventsResource {
+    pub events: Vec<GameEvent>,
+}
@@ -0,0 +1,185 @@
+use std::io;
+
+use crate::game::map::{MapDefinition, Terrain};
+
+/// Resource with the map the game is played on, it does not change during a game.
+#[derive(Clone, Debug)]
+pub struct MapResource {
+    pub definition: MapDefinition,
+    /// The terrain of every tile, row by row.
+    terrain: Vec<Terrain>,
+    /// The speed multiplier of every tile, row by row, 0 for tiles covered by an obstacle.
+    tile_speeds: Vec<f32>,
+}
+
+impl Default for MapResource {
+    fn default() -> Self {
+        MapResource::new(MapDefinition::open_field(64, 64, 4.)).expect("Open field is a valid map")
+    }
+}
+
+impl MapResource {
+    pub fn new(definition: MapDefinition) -> io::Result<MapResource> {
+        let terrain = definition.terrain()?;
+        let tile_size = definition.tile_size;
+        let tile_speeds = terrain
+            .iter()
+            .enumerate()
+            .map(|(i, terrain)| {
+                let x = (i % definition.width as usize) as f32 * tile_size;
+                let y = (i / definition.width as usize) as f32 * tile_size;
+                let blocked = definition.obstacles.iter().any(|obstacle| {
+                    (x + tile_size / 2. - obstacle.position.0).abs() * 2. < tile_size + obstacle.width
+                        && (y + tile_size / 2. - obstacle.position.1).abs() * 2.
+                            < tile_size + obstacle.height
+                });
+                if blocked {
+                    0.
+                } else {
+                    terrain.speed_multiplier()
+                }
+            })
+            .collect();
+        Ok(MapResource {
+            definition,
+            terrain,
+            tile_speeds,
+        })
+    }
+
+    /// The tile a position is on, `None` outside the map.
+    pub fn tile_at(&self, position: (f32, f32)) -> Option<(usize, usize)> {
+        let (width, height) = self.size();
+        if !(0. ..width).contains(&position.0) || !(0. ..height).contains(&position.1) {
+            return None;
+        }
+        Some((
+            (position.0 / self.definition.tile_size) as usize,
+            (position.1 / self.definition.tile_size) as usize,
+        ))
+    }
+
+    /// The position of the center of a tile.
+    pub fn tile_center(&self, tile: (usize, usize)) -> (f32, f32) {
+        (
+            (tile.0 as f32 + 0.5) * self.definition.tile_size,
+            (tile.1 as f32 + 0.5) * self.definition.tile_size,
+        )
+    }
+
+    /// What the speed of units on the tile is multiplied by, 0 if they can't go there or it is outside the map.
+    pub fn tile_speed(&self, tile: (usize, usize)) -> f32 {
+        if tile.0 >= self.definition.width as usize || tile.1 >= self.definition.height as usize {
+            return 0.;
+        }
+        self.tile_speeds[tile.1 * self.definition.width as usize + tile.0]
+    }
+
+    /// What the speed of units at the position is multiplied by, 0 if they can't go there.
+    pub fn speed_multiplier_at(&self, position: (f32, f32)) -> f32 {
+        self.tile_at(position)
+            .map(|tile| self.tile_speed(tile))
+            .unwrap_or(0.)
+    }
+
+    /// Width and height of the map in meters.
+    pub fn size(&self) -> (f32, f32) {
+        (
+            self.definition.width as f32 * self.definition.tile_size,
+            self.definition.height as f32 * self.definition.tile_size,
+        )
+    }
+
+    /// Moves a position outside the map to the closest position on the map.
+    pub fn clamp(&self, position: (f32, f32)) -> (f32, f32) {
+        let (width, height) = self.size();
+        // Just inside the far edges, as they belong to the tile past the end of the map
+        let max_x = (width - 0.001).max(0.);
+        let max_y = (height - 0.001).max(0.);
+        (position.0.clamp(0., max_x), position.1.clamp(0., max_y))
+    }
+
+    /// The position on the map closest to `position` that a unit can stand at,
+    /// searching the tiles around it ring by ring. `None` when no tile on the map is passable.
+    pub fn nearest_passable(&self, position: (f32, f32)) -> Option<(f32, f32)> {
+        let position = self.clamp(position);
+        if self.is_passable(position) {
+            return Some(position);
+        }
+        let (x, y) = self.tile_at(position)?;
+        let (x, y) = (x as i64, y as i64);
+        let (width, height) = (self.definition.width as i64, self.definition.height as i64);
+        let distance = |p: (f32, f32)| (p.0 - position.0).powi(2) + (p.1 - position.1).powi(2);
+        for ring in 1..=width.max(height) {
+            let nearest = (y - ring..=y + ring)
+                .flat_map(|ty| (x - ring..=x + ring).map(move |tx| (tx, ty)))
+                .filter(|(tx, ty)| (tx - x).abs() == ring || (ty - y).abs() == ring)
+                .filter(|(tx, ty)| (0..width).contains(tx) && (0..height).contains(ty))
+                .map(|(tx, ty)| self.tile_center((tx as usize, ty as usize)))
+                .filter(|center| self.is_passable(*center))
+                .min_by(|a, b| distance(*a).total_cmp(&distance(*b)));
+            if nearest.is_some() {
+                return nearest;
+            }
+        }
+        None
+    }
+
+    /// Whether a unit can stand at the position.
+    pub fn is_passable(&self, position: (f32, f32)) -> bool {
+        self.is_area_passable(position, (0., 0.))
+    }
+
+    /// Whether all of the rectangle of `size` centered on `position` is on the map,
+    /// on passable terrain and clear of obstacles.
+    pub fn is_area_passable(&self, position: (f32, f32), size: (f32, f32)) -> bool {
+        let min = (position.0 - size.0 / 2., position.1 - size.1 / 2.);
+        let max = (position.0 + size.0 / 2., position.1 + size.1 / 2.);
+        let (width, height) = self.size();
+        if min.0 < 0. || min.1 < 0. || max.0 > width || max.1 > height {
+            return false;
+        }
+
+        let tile_size = self.definition.tile_size;
+        let last_x = self.definition.width as usize - 1;
+        let last_y = self.definition.height as usize - 1;
+        let tiles_x = (min.0 / tile_size) as usize..=((max.0 / tile_size) as usize).min(last_x);
+        for y in (min.1 / tile_size) as usize..=((max.1 / tile_size) as usize).min(last_y) {
+            for x in tiles_x.clone() {
+                if !self.terrain[y * self.definition.width as usize + x].is_passable() {
+                    return false;
+                }
+            }
+        }
+
+        !self.definition.obstacles.iter().any(|obstacle| {
+            (position.0 - obstacle.position.0).abs() * 2. <= size.0 + obstacle.width
+                && (position.1 - obstacle.position.1).abs() * 2. <= size.1 + obstacle.height
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_passable_moves_off_water_and_onto_the_map() {
+        let mut definition = MapDefinition::open_field(4, 1, 4.);
+        definition.tiles = vec!["..~~".to_string()];
+        let map = MapResource::new(definition).expect("Map should be valid");
+
+        assert_eq!(map.nearest_passable((2., 2.)), Some((2., 2.)));
+        assert_eq!(map.nearest_passable((13., 2.)), Some((6., 2.)));
+        assert_eq!(map.nearest_passable((-5., 2.)), Some((0., 2.)));
+    }
+
+    #[test]
+    fn nearest_passable_is_none_without_passable_tiles() {
+        let mut definition = MapDefinition::open_field(2, 1, 4.);
+        definition.tiles = vec!["~~".to_string()];
+        let map = MapResource::new(definition).expect("Map should be valid");
+
+        assert_eq!(map.nearest_passable((3., 3.)), None);
+    }
+}
@@ -0,0 +1,38 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::{
+    components::PlayerId,
+    victory::{MatchEnded, Side, VictoryCondition},
+};
+
+/// Resource with how the match can end and how close it is to ending.
+/// The conditions come from the server settings, saves only keep the progress toward them.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct MatchResource {
+    #[serde(skip)]
+    pub conditions: Vec<VictoryCondition>,
+    /// Every player that has owned a unit or building during the match.
+    pub players: HashSet<PlayerId>,
+    pub elapsed_seconds: f64,
+    /// The side holding each control point and for how many seconds, indexed like `conditions`.
+    /// Conditions that aren't control points are always `None`.
+    pub control: Vec<Option<(Side, f64)>>,
+    /// Set once the match is over, no more commands are taken after that.
+    pub result: Option<MatchEnded>,
+}
+
+impl MatchResource {
+    pub fn new(conditions: Vec<VictoryCondition>) -> MatchResource {
+        MatchResource {
+            control: vec![None; conditions.len()],
+            conditions,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.result.is_some()
+    }
+}
@@ -1,2 +1,23 @@
 pub mod time_resource;
-pub use time_resource::*;
\ No newline at end of file
+pub use time_resource::*;
+
+pub mod visibility_resource;
+pub use visibility_resource::*;
+
+pub mod teams_resource;
+pub use teams_resource::*;
+
+pub mod economy_resource;
+pub use economy_resource::*;
+
+pub mod events_resource;
+pub use events_resource::*;
+
+pub mod map_resource;
+pub use map_resource::*;
+
+pub mod match_resource;
+pub use match_resource::*;
+
+pub mod stats_resource;
+pub use stats_resource::*;
\ No newline at end of file
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::PlayerId;
+
+/// What a player has done during the match.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct PlayerStats {
+    pub units_created: u32,
+    pub units_lost: u32,
+    pub buildings_lost: u32,
+    /// Health taken from enemy units and buildings, by weapons, projectiles and abilities.
+    pub damage_dealt: f32,
+    /// Resources harvesters brought back to a drop-off.
+    pub resources_gathered: u32,
+    pub commands: u32,
+    /// Commands per minute of the match so far, filled in by `StatsResource::summary`.
+    #[serde(default)]
+    pub commands_per_minute: f32,
+}
+
+/// Resource with the stats of every player, kept up to date by the command handler and the systems.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct StatsResource {
+    pub players: HashMap<PlayerId, PlayerStats>,
+}
+
+impl StatsResource {
+    pub fn player(&mut self, player_id: PlayerId) -> &mut PlayerStats {
+        self.players.entry(player_id).or_default()
+    }
+
+    /// The stats of every player, with the commands per minute over `elapsed_seconds` of the match.
+    pub fn summary(&self, elapsed_seconds: f64) -> HashMap<PlayerId, PlayerStats> {
+        let minutes = (elapsed_seconds / 60.) as f32;
+        self.players
+            .iter()
+            .map(|(player_id, stats)| {
+                let mut stats = stats.clone();
+                stats.commands_per_minute = if minutes > 0. {
+                    stats.commands as f32 / minutes
+                } else {
+                    0.
+                };
+                (*player_id, stats)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summaries_work_out_commands_per_minute() {
+        let mut stats = StatsResource::default();
+        stats.player(1).commands = 120;
+        stats.player(2).units_lost = 3;
+
+        let summary = stats.summary(90.);
+        assert_eq!(summary[&1].commands_per_minute, 80.);
+        assert_eq!(summary[&2].units_lost, 3);
+        assert_eq!(stats.summary(0.)[&1].commands_per_minute, 0.);
+    }
+}
@@ -0,0 +1,28 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::{PlayerId, TeamId};
+
+/// Resource with the team of every player that has joined one.
+/// Players that haven't joined a team only ally with themselves.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct TeamsResource {
+    pub player_teams: HashMap<PlayerId, TeamId>,
+}
+
+impl TeamsResource {
+    pub fn team_of(&self, player_id: PlayerId) -> Option<TeamId> {
+        self.player_teams.get(&player_id).copied()
+    }
+
+    pub fn are_allies(&self, player_id: PlayerId, other_player_id: PlayerId) -> bool {
+        if player_id == other_player_id {
+            return true;
+        }
+        match (self.team_of(player_id), self.team_of(other_player_id)) {
+            (Some(team), Some(other_team)) => team == other_team,
+            _ => false,
+        }
+    }
+}
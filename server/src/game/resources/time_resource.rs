@@ -1,6 +1,13 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// How much game time passes each tick.
+/// The simulation always steps by this amount, so a replay of the same commands ends up in the same state.
+pub const TICK_DURATION: Duration = Duration::from_secs(1);
 
 /// Resource that contains information about the ellapsed time of the game.
-#[derive(Default)]
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
 pub struct TimeResource{
     /// Time elapsed since the last tick
     pub elapsed_seconds: f64,
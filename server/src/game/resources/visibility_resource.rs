@@ -0,0 +1,12 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::game::{components::PlayerId, game_state::BuildingState};
+
+/// Resource with what each player can see, updated every tick by the `compute_visibility` system.
+#[derive(Default, Clone, Debug)]
+pub struct VisibilityResource {
+    /// Ids of the units each player can currently see.
+    pub visible: HashMap<PlayerId, HashSet<String>>,
+    /// Structures each player has seen, as they were when last seen.
+    pub last_known_structures: HashMap<PlayerId, HashMap<String, BuildingState>>,
+}
@@ -0,0 +1,447 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use legion::{
+    storage::Component,
+    world::{Entry, EntryRef},
+    Entity, EntityStore, IntoQuery, Resources, World,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::{
+        components::{
+            AbilityCooldowns, BuildingType, Construction, DamageOverTime, Destination, DropOff,
+            Facing, Footprint, GroupSpeed, Harvester, Health, MovementProfile, Order, Owner,
+            Position, ProductionQueue, Projectile, RallyPoint, ResourceNode, Slow, Structure, Stun,
+            Target, Team, UnitId, UnitType, Velocity, Vision, Weapon,
+        },
+        resources::{
+            EconomyResource, MatchResource, StatsResource, TeamsResource, TimeResource,
+            VisibilityResource,
+        },
+    },
+    UidEntityMap,
+};
+
+/// Bump this whenever the layout of `SaveFile` changes in a way old saves can't be read.
+pub const SAVE_FILE_VERSION: u32 = 2;
+
+/// Everything needed to rebuild a world, written to disk as json.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SaveFile {
+    pub version: u32,
+    pub time: TimeResource,
+    #[serde(default)]
+    pub teams: TeamsResource,
+    #[serde(default)]
+    pub economy: EconomyResource,
+    #[serde(default)]
+    pub match_state: MatchResource,
+    #[serde(default)]
+    pub stats: StatsResource,
+    pub units: Vec<SavedUnit>,
+    #[serde(default)]
+    pub resource_nodes: Vec<SavedResourceNode>,
+    #[serde(default)]
+    pub projectiles: Vec<SavedProjectile>,
+}
+
+/// A single unit or building entity and the components it had when saved.
+/// Optional components are skipped when missing, so new ones can be added
+/// with `#[serde(default)]` without breaking older saves.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SavedUnit {
+    pub id: UnitId,
+    pub position: Position,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit_type: Option<UnitType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub building_type: Option<BuildingType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<Destination>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub velocity: Option<Velocity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Owner>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team: Option<Team>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vision: Option<Vision>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub harvester: Option<Harvester>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub footprint: Option<Footprint>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<Health>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub production_queue: Option<ProductionQueue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rally_point: Option<RallyPoint>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub construction: Option<Construction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub facing: Option<Facing>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub movement: Option<MovementProfile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_speed: Option<GroupSpeed>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<Order>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weapon: Option<Weapon>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<Target>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ability_cooldowns: Option<AbilityCooldowns>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slow: Option<Slow>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stun: Option<Stun>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub damage_over_time: Option<DamageOverTime>,
+    #[serde(default)]
+    pub structure: bool,
+    #[serde(default)]
+    pub drop_off: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SavedResourceNode {
+    pub node: ResourceNode,
+    pub position: Position,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SavedProjectile {
+    pub projectile: Projectile,
+    pub position: Position,
+    pub velocity: Velocity,
+}
+
+/// Writes all units, buildings, resource nodes, projectiles and the resources of the game to `path`.
+pub fn save_world(path: &Path, world: &World, resources: &Resources) -> io::Result<()> {
+    let entities: Vec<Entity> = <(Entity, &UnitId)>::query()
+        .iter(world)
+        .map(|(entity, _)| *entity)
+        .collect();
+    let units = entities
+        .into_iter()
+        .map(|entity| {
+            let entry = world.entry_ref(entity).expect("Entity was just queried");
+            SavedUnit {
+                id: cloned::<UnitId>(&entry).expect("Entity was queried with a unit id"),
+                position: cloned::<Position>(&entry).unwrap_or(Position { x: 0., y: 0. }),
+                unit_type: cloned(&entry),
+                building_type: cloned(&entry),
+                destination: cloned(&entry),
+                velocity: cloned(&entry),
+                owner: cloned(&entry),
+                team: cloned(&entry),
+                vision: cloned(&entry),
+                harvester: cloned(&entry),
+                footprint: cloned(&entry),
+                health: cloned(&entry),
+                production_queue: cloned(&entry),
+                rally_point: cloned(&entry),
+                construction: cloned(&entry),
+                facing: cloned(&entry),
+                movement: cloned(&entry),
+                group_speed: cloned(&entry),
+                order: cloned(&entry),
+                weapon: cloned(&entry),
+                target: cloned(&entry),
+                ability_cooldowns: cloned(&entry),
+                slow: cloned(&entry),
+                stun: cloned(&entry),
+                damage_over_time: cloned(&entry),
+                structure: cloned::<Structure>(&entry).is_some(),
+                drop_off: cloned::<DropOff>(&entry).is_some(),
+            }
+        })
+        .collect();
+    let resource_nodes = <(&ResourceNode, &Position)>::query()
+        .iter(world)
+        .map(|(node, position)| SavedResourceNode {
+            node: node.clone(),
+            position: position.clone(),
+        })
+        .collect();
+    let projectiles = <(&Projectile, &Position, &Velocity)>::query()
+        .iter(world)
+        .map(|(projectile, position, velocity)| SavedProjectile {
+            projectile: projectile.clone(),
+            position: position.clone(),
+            velocity: velocity.clone(),
+        })
+        .collect();
+
+    let save = SaveFile {
+        version: SAVE_FILE_VERSION,
+        time: cloned_resource::<TimeResource>(resources),
+        teams: cloned_resource::<TeamsResource>(resources),
+        economy: cloned_resource::<EconomyResource>(resources),
+        match_state: cloned_resource::<MatchResource>(resources),
+        stats: cloned_resource::<StatsResource>(resources),
+        units,
+        resource_nodes,
+        projectiles,
+    };
+    // Written next to the save and moved over it once complete, so a crash while saving keeps the last save
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    serde_json::to_writer_pretty(&mut writer, &save)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    fs::rename(&temp_path, path)
+}
+
+/// Reads the save stored in `path`, without touching the world.
+pub fn read_save(path: &Path) -> io::Result<SaveFile> {
+    let reader = BufReader::new(File::open(path)?);
+    let save: SaveFile = serde_json::from_reader(reader)?;
+    check_version(&save)?;
+    Ok(save)
+}
+
+/// Whether this version of the server can restore `save`.
+pub fn check_version(save: &SaveFile) -> io::Result<()> {
+    if save.version != SAVE_FILE_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "save file version {} is not supported, expected {}",
+                save.version, SAVE_FILE_VERSION
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Clears `world` and fills it with the units, buildings, resource nodes and projectiles of `save`,
+/// and restores the resources of the game and the `UidEntityMap`.
+/// What players remember seeing is not saved, so fog of war starts over.
+pub fn restore_world(
+    mut save: SaveFile,
+    world: &mut World,
+    resources: &mut Resources,
+) -> io::Result<()> {
+    check_version(&save)?;
+
+    world.clear();
+    let mut id_map = UidEntityMap::default();
+    for unit in save.units {
+        let uuid = unit.id.id.clone();
+        let entity = world.push((unit.id, unit.position));
+        let mut entry = world.entry(entity).expect("Entity was just pushed");
+        add_optional(&mut entry, unit.unit_type);
+        add_optional(&mut entry, unit.building_type);
+        add_optional(&mut entry, unit.destination);
+        add_optional(&mut entry, unit.velocity);
+        add_optional(&mut entry, unit.owner);
+        add_optional(&mut entry, unit.team);
+        add_optional(&mut entry, unit.vision);
+        add_optional(&mut entry, unit.harvester);
+        add_optional(&mut entry, unit.footprint);
+        add_optional(&mut entry, unit.health);
+        add_optional(&mut entry, unit.production_queue);
+        add_optional(&mut entry, unit.rally_point);
+        add_optional(&mut entry, unit.construction);
+        add_optional(&mut entry, unit.facing);
+        add_optional(&mut entry, unit.movement);
+        add_optional(&mut entry, unit.group_speed);
+        add_optional(&mut entry, unit.order);
+        add_optional(&mut entry, unit.weapon);
+        add_optional(&mut entry, unit.target);
+        // Saves from before units were spawned with their cooldowns don't have them
+        let unit_type = unit.unit_type;
+        let cooldowns = unit
+            .ability_cooldowns
+            .or_else(|| unit_type.map(|_| AbilityCooldowns::default()));
+        add_optional(&mut entry, cooldowns);
+        add_optional(&mut entry, unit.slow);
+        add_optional(&mut entry, unit.stun);
+        add_optional(&mut entry, unit.damage_over_time);
+        if unit.structure {
+            entry.add_component(Structure);
+        }
+        if unit.drop_off {
+            entry.add_component(DropOff);
+        }
+        id_map.insert(uuid, entity);
+    }
+    for SavedResourceNode { node, position } in save.resource_nodes {
+        let id = node.id.clone();
+        id_map.insert(id, world.push((node, position)));
+    }
+    for SavedProjectile {
+        projectile,
+        position,
+        velocity,
+    } in save.projectiles
+    {
+        world.push((projectile, position, velocity));
+    }
+
+    resources.insert(id_map);
+    resources.insert(save.time);
+    resources.insert(save.teams);
+    resources.insert(save.economy);
+    // The victory conditions are those the server runs with, not those of the saved match
+    save.match_state.conditions = cloned_resource::<MatchResource>(resources).conditions;
+    resources.insert(save.match_state);
+    resources.insert(save.stats);
+    resources.insert(VisibilityResource::default());
+    Ok(())
+}
+
+fn cloned<T: Component + Clone>(entry: &EntryRef) -> Option<T> {
+    entry.get_component::<T>().ok().cloned()
+}
+
+fn cloned_resource<T: 'static + Clone + Default>(resources: &Resources) -> T {
+    resources
+        .get::<T>()
+        .map(|resource| resource.clone())
+        .unwrap_or_default()
+}
+
+fn add_optional<T: Component>(entry: &mut Entry, component: Option<T>) {
+    if let Some(component) = component {
+        entry.add_component(component);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, env};
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::game::components::AbilityType;
+
+    fn entry<'a>(world: &'a World, resources: &Resources, id: &str) -> EntryRef<'a> {
+        let id_map = resources.get::<UidEntityMap>().unwrap();
+        world.entry_ref(id_map[id]).unwrap()
+    }
+
+    #[test]
+    fn restores_what_was_saved() {
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        let soldier = world.push((
+            UnitId {
+                id: "soldier".to_string(),
+            },
+            Position { x: 12.5, y: 40. },
+            UnitType::Soldier,
+            Owner { player_id: 1 },
+            Health {
+                current: 30.,
+                max: 100.,
+            },
+        ));
+        let mut soldier = world.entry(soldier).unwrap();
+        let mut cooldowns = AbilityCooldowns::default();
+        cooldowns.ready_at.insert(AbilityType::Grenade, 42);
+        soldier.add_component(cooldowns);
+        soldier.add_component(Slow {
+            factor: 0.5,
+            ticks_left: 3,
+        });
+        soldier.add_component(Stun { ticks_left: 2 });
+        soldier.add_component(DamageOverTime {
+            damage: 4.,
+            stacks: 2,
+            ticks_left: 5,
+            source: Some(2),
+        });
+        let barracks = world.push((
+            UnitId {
+                id: "barracks".to_string(),
+            },
+            Position { x: 64., y: 64. },
+            BuildingType::Barracks,
+            Owner { player_id: 1 },
+            Structure,
+            ProductionQueue {
+                queue: VecDeque::from(vec![UnitType::Soldier, UnitType::Worker]),
+                progress: 2.5,
+                produced: 3,
+            },
+            RallyPoint { x: 80., y: 70. },
+        ));
+        world
+            .entry(barracks)
+            .unwrap()
+            .add_component(Construction { progress: 0.75 });
+        world.push((
+            ResourceNode {
+                id: "node".to_string(),
+                amount: 250,
+            },
+            Position { x: 100., y: 20. },
+        ));
+        resources.insert(TimeResource {
+            ticks: 30,
+            ..Default::default()
+        });
+        let mut economy = EconomyResource::default();
+        assert!(economy.try_spend(1, 120));
+        resources.insert(economy);
+        resources.insert(MatchResource::default());
+
+        let path = env::temp_dir().join(format!("thebsgame-save-{}.json", Uuid::new_v4()));
+        save_world(&path, &world, &resources).unwrap();
+        let save = read_save(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut world = World::default();
+        let mut resources = Resources::default();
+        resources.insert(MatchResource::default());
+        restore_world(save, &mut world, &mut resources).unwrap();
+
+        assert_eq!(resources.get::<EconomyResource>().unwrap().balance(1), 380);
+        assert_eq!(resources.get::<TimeResource>().unwrap().ticks, 30);
+
+        let soldier = entry(&world, &resources, "soldier");
+        let position = soldier.get_component::<Position>().unwrap();
+        assert_eq!((position.x, position.y), (12.5, 40.));
+        assert_eq!(soldier.get_component::<Health>().unwrap().current, 30.);
+        assert_eq!(
+            soldier.get_component::<Owner>().unwrap(),
+            &Owner { player_id: 1 }
+        );
+        let cooldowns = soldier.get_component::<AbilityCooldowns>().unwrap();
+        assert_eq!(cooldowns.ticks_left(AbilityType::Grenade, 30), 12);
+        assert_eq!(soldier.get_component::<Slow>().unwrap().ticks_left, 3);
+        assert_eq!(soldier.get_component::<Stun>().unwrap().ticks_left, 2);
+        let damage = soldier.get_component::<DamageOverTime>().unwrap();
+        assert_eq!(
+            (damage.stacks, damage.ticks_left, damage.source),
+            (2, 5, Some(2))
+        );
+
+        let barracks = entry(&world, &resources, "barracks");
+        assert!(barracks.get_component::<Structure>().is_ok());
+        let queue = barracks.get_component::<ProductionQueue>().unwrap();
+        assert_eq!(
+            queue.queue,
+            VecDeque::from(vec![UnitType::Soldier, UnitType::Worker])
+        );
+        assert_eq!((queue.progress, queue.produced), (2.5, 3));
+        let rally_point = barracks.get_component::<RallyPoint>().unwrap();
+        assert_eq!((rally_point.x, rally_point.y), (80., 70.));
+        assert_eq!(
+            barracks.get_component::<Construction>().unwrap().progress,
+            0.75
+        );
+
+        let node = entry(&world, &resources, "node");
+        assert_eq!(node.get_component::<ResourceNode>().unwrap().amount, 250);
+    }
+}
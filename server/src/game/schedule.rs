@@ -4,12 +4,36 @@ use crate::game::systems::*;
 
 pub fn create_schedule() -> Schedule{
     Schedule::builder()
-    .add_system(handle_commands_system())
+    .add_system(handle_commands_system(PlacedThisTick::default()))
+    .flush()
+    .add_system(cast_abilities_system())
+    .flush()
+    .add_system(construct_buildings_system())
+    .flush()
+    .add_system(produce_units_system())
+    .flush()
+    .add_system(harvest_system())
+    .flush()
+    .add_system(attack_system())
     .flush()
     .add_system(destination_to_velocity_system())
     .flush()
+    .add_system(move_projectiles_system())
+    .flush()
     .add_system(velocity_to_position_system())    
     .flush()
     .add_system(remove_destination_on_arrival_system())    
+    .flush()
+    .add_system(projectile_impact_system())
+    .flush()
+    .add_system(tick_status_effects_system())
+    .flush()
+    .add_system(remove_dead_system())
+    .flush()
+    .add_system(check_victory_system())
+    .flush()
+    .add_system(follow_orders_system())
+    .flush()
+    .add_system(compute_visibility_system())
     .build()
 }
\ No newline at end of file
@@ -0,0 +1,516 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+    rc::Rc,
+};
+
+use legion::{IntoQuery, Resources, World};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, FnPtr, AST, FLOAT, INT};
+use uuid::Uuid;
+
+use crate::game::{
+    commands::GameCommand,
+    components::{Owner, PlayerId, Position, UnitId, UnitType},
+    game_state::Unit,
+    resources::{MapResource, TimeResource},
+};
+
+/// How many operations a script can run at once, so a script stuck in a loop can't stall the game.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Something that makes the script call one of its functions.
+enum Trigger {
+    /// Calls `callback()` once the script has run for `tick` ticks.
+    AtTick { tick: u64, callback: FnPtr },
+    /// Calls `callback()` every `interval` ticks.
+    Every { interval: u64, callback: FnPtr },
+    /// Calls `callback(unit_id)` when a unit of `player_id`, or of anyone if it is `None`,
+    /// comes within `radius` of `position`.
+    EnterRegion {
+        position: (f32, f32),
+        radius: f32,
+        player_id: Option<PlayerId>,
+        callback: FnPtr,
+        /// The units that were in the region last tick.
+        inside: HashSet<String>,
+    },
+}
+
+struct ScriptUnit {
+    position: (f32, f32),
+    owner: Option<PlayerId>,
+}
+
+/// What the functions the script calls work with, shared between the engine and the script.
+struct ScriptState {
+    /// Ticks since the script started.
+    tick: u64,
+    /// The units as the last tick left them.
+    units: HashMap<String, ScriptUnit>,
+    map: MapResource,
+    /// Commands given by the script this tick.
+    commands: Vec<GameCommand>,
+    triggers: Vec<Trigger>,
+}
+
+/// A scenario script, run on the server to spawn units, give orders and react to triggers.
+/// Its commands are given like those of players, and are journaled with theirs.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    state: Rc<RefCell<ScriptState>>,
+    /// The tick the script started at, `None` until it has run.
+    started_at: Option<u64>,
+}
+
+impl Script {
+    /// Compiles the script at `path`, it starts running on the next call to `commands`.
+    pub fn load(path: &Path, map: &MapResource) -> Result<Script, String> {
+        let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let state = Rc::new(RefCell::new(ScriptState {
+            tick: 0,
+            units: HashMap::new(),
+            map: map.clone(),
+            commands: Vec::new(),
+            triggers: Vec::new(),
+        }));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        register_functions(&mut engine, &state);
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        Ok(Script {
+            engine,
+            ast,
+            state,
+            started_at: None,
+        })
+    }
+
+    /// Starts the script over, for when the game is reset.
+    pub fn restart(&mut self) {
+        self.started_at = None;
+        self.state.borrow_mut().triggers.clear();
+    }
+
+    /// The commands the script gives this tick, read from the world as the last tick left it.
+    pub fn commands(&mut self, world: &World, resources: &Resources) -> Vec<GameCommand> {
+        let tick = resources
+            .get::<TimeResource>()
+            .expect("Must have a time resource")
+            .ticks;
+        let starting = self.started_at.is_none();
+        let started_at = *self.started_at.get_or_insert(tick);
+        {
+            let mut state = self.state.borrow_mut();
+            state.tick = tick - started_at;
+            state.units = <(&UnitId, &UnitType, &Position, Option<&Owner>)>::query()
+                .iter(world)
+                .map(|(id, _, pos, owner)| {
+                    let unit = ScriptUnit {
+                        position: (pos.x, pos.y),
+                        owner: owner.map(|owner| owner.player_id),
+                    };
+                    (id.id.clone(), unit)
+                })
+                .collect();
+        }
+
+        // The top level of the script runs once, to set up the scenario and register its triggers
+        if starting {
+            if let Err(e) = self.engine.run_ast(&self.ast) {
+                eprintln!("Script error: {}", e);
+            }
+        }
+
+        // Callbacks can register triggers themselves, so they are called once the triggers are checked
+        let calls = self.state.borrow_mut().fired_triggers();
+        for (callback, args) in calls {
+            if let Err(e) = callback.call::<Dynamic>(&self.engine, &self.ast, args) {
+                let name = if callback.is_anonymous() {
+                    "a closure"
+                } else {
+                    callback.fn_name()
+                };
+                eprintln!("Script error in {}: {}", name, e);
+            }
+        }
+        std::mem::take(&mut self.state.borrow_mut().commands)
+    }
+}
+
+impl ScriptState {
+    /// Scripts give orders as the owner of the unit they order around.
+    fn owner_of(&self, id: &str) -> ScriptResult<PlayerId> {
+        match self.units.get(id) {
+            Some(ScriptUnit {
+                owner: Some(owner), ..
+            }) => Ok(*owner),
+            Some(_) => Err(format!("Unit {} has no owner to take orders from", id).into()),
+            None => Err(format!("Unit {} does not exist", id).into()),
+        }
+    }
+
+    /// The callbacks of the triggers that fire this tick, with their arguments.
+    fn fired_triggers(&mut self) -> Vec<(FnPtr, Vec<Dynamic>)> {
+        let ScriptState {
+            tick,
+            units,
+            triggers,
+            ..
+        } = self;
+        let mut calls = Vec::new();
+        triggers.retain_mut(|trigger| match trigger {
+            Trigger::AtTick { tick: at, callback } => {
+                if *at > *tick {
+                    return true;
+                }
+                calls.push((callback.clone(), Vec::new()));
+                false
+            }
+            Trigger::Every { interval, callback } => {
+                if *tick > 0 && tick.is_multiple_of(*interval) {
+                    calls.push((callback.clone(), Vec::new()));
+                }
+                true
+            }
+            Trigger::EnterRegion {
+                position,
+                radius,
+                player_id,
+                callback,
+                inside,
+            } => {
+                let now = units_in_region(units, *position, *radius, *player_id);
+                for id in now.difference(inside) {
+                    calls.push((callback.clone(), vec![Dynamic::from(id.clone())]));
+                }
+                *inside = now;
+                true
+            }
+        });
+        calls
+    }
+}
+
+/// The functions scripts can call.
+fn register_functions(engine: &mut Engine, state: &Rc<RefCell<ScriptState>>) {
+    let s = state.clone();
+    engine.register_fn(
+        "spawn_unit",
+        move |unit_type: &str, x: Dynamic, y: Dynamic, owner: INT| -> ScriptResult<String> {
+            let unit_type: UnitType = serde_json::from_value(serde_json::json!(unit_type))
+                .map_err(|_| format!("Unknown unit type {}", unit_type))?;
+            if owner < 0 {
+                return Err(format!("{} is not a player", owner).into());
+            }
+            let mut state = s.borrow_mut();
+            let position = state.map.clamp((number(x)?, number(y)?));
+            if !state.map.is_passable(position) {
+                return Err("Units can't be created on impassable terrain".into());
+            }
+            let id = Uuid::new_v4().to_string();
+            state.commands.push(GameCommand::CreateUnitCommand {
+                unit: Unit {
+                    position,
+                    destination: position,
+                    id: id.clone(),
+                    owner: Some(owner as PlayerId),
+                    unit_type,
+                    health: unit_type.definition().max_health,
+                    ..Default::default()
+                },
+                // Units the scenario gives a player are not paid for
+                free: true,
+            });
+            Ok(id)
+        },
+    );
+    let s = state.clone();
+    engine.register_fn(
+        "move_unit",
+        move |id: &str, x: Dynamic, y: Dynamic| -> ScriptResult<()> {
+            let mut state = s.borrow_mut();
+            let position = state.map.clamp((number(x)?, number(y)?));
+            if !state.map.is_passable(position) {
+                return Err("Units can't move onto impassable terrain".into());
+            }
+            let player_id = state.owner_of(id)?;
+            state.commands.push(GameCommand::SetUnitDestinationCommand {
+                position,
+                uuid: id.to_string(),
+                player_id,
+            });
+            Ok(())
+        },
+    );
+    let s = state.clone();
+    engine.register_fn(
+        "attack_move",
+        move |id: &str, x: Dynamic, y: Dynamic| -> ScriptResult<()> {
+            let mut state = s.borrow_mut();
+            let position = state.map.clamp((number(x)?, number(y)?));
+            if !state.map.is_passable(position) {
+                return Err("Units can't move onto impassable terrain".into());
+            }
+            let player_id = state.owner_of(id)?;
+            state.commands.push(GameCommand::AttackMoveCommand {
+                uuid: id.to_string(),
+                position,
+                player_id,
+            });
+            Ok(())
+        },
+    );
+    let s = state.clone();
+    engine.register_fn("unit_position", move |id: &str| -> Dynamic {
+        match s.borrow().units.get(id) {
+            Some(unit) => {
+                let position: Array = vec![
+                    Dynamic::from(unit.position.0 as FLOAT),
+                    Dynamic::from(unit.position.1 as FLOAT),
+                ];
+                Dynamic::from(position)
+            }
+            None => Dynamic::UNIT,
+        }
+    });
+    let s = state.clone();
+    engine.register_fn("units_of", move |player_id: INT| -> Array {
+        let state = s.borrow();
+        let mut ids: Vec<&String> = state
+            .units
+            .iter()
+            .filter(|(_, unit)| unit.owner == Some(player_id as PlayerId))
+            .map(|(id, _)| id)
+            .collect();
+        ids.sort();
+        ids.into_iter()
+            .map(|id| Dynamic::from(id.clone()))
+            .collect()
+    });
+    let s = state.clone();
+    engine.register_fn("tick", move || -> INT { s.borrow().tick as INT });
+    let s = state.clone();
+    engine.register_fn("at_tick", move |tick: INT, callback: FnPtr| {
+        s.borrow_mut().triggers.push(Trigger::AtTick {
+            tick: tick.max(0) as u64,
+            callback,
+        });
+    });
+    let s = state.clone();
+    engine.register_fn(
+        "every",
+        move |interval: INT, callback: FnPtr| -> ScriptResult<()> {
+            if interval <= 0 {
+                return Err("every expects a positive number of ticks".into());
+            }
+            s.borrow_mut().triggers.push(Trigger::Every {
+                interval: interval as u64,
+                callback,
+            });
+            Ok(())
+        },
+    );
+    let s = state.clone();
+    engine.register_fn(
+        "on_enter",
+        move |x: Dynamic, y: Dynamic, radius: Dynamic, callback: FnPtr| -> ScriptResult<()> {
+            let mut state = s.borrow_mut();
+            let trigger = enter_region(&state.units, x, y, radius, None, callback)?;
+            state.triggers.push(trigger);
+            Ok(())
+        },
+    );
+    let s = state.clone();
+    engine.register_fn(
+        "on_enter",
+        move |x: Dynamic,
+              y: Dynamic,
+              radius: Dynamic,
+              player_id: INT,
+              callback: FnPtr|
+              -> ScriptResult<()> {
+            if player_id < 0 {
+                return Err(format!("{} is not a player", player_id).into());
+            }
+            let mut state = s.borrow_mut();
+            let trigger = enter_region(
+                &state.units,
+                x,
+                y,
+                radius,
+                Some(player_id as PlayerId),
+                callback,
+            )?;
+            state.triggers.push(trigger);
+            Ok(())
+        },
+    );
+}
+
+/// Units already in the region when the trigger is registered don't set it off, only ones coming in later do.
+fn enter_region(
+    units: &HashMap<String, ScriptUnit>,
+    x: Dynamic,
+    y: Dynamic,
+    radius: Dynamic,
+    player_id: Option<PlayerId>,
+    callback: FnPtr,
+) -> ScriptResult<Trigger> {
+    let position = (number(x)?, number(y)?);
+    let radius = number(radius)?;
+    Ok(Trigger::EnterRegion {
+        position,
+        radius,
+        player_id,
+        callback,
+        inside: units_in_region(units, position, radius, player_id),
+    })
+}
+
+/// The units of `player_id`, or of anyone if it is `None`, within `radius` of `position`.
+fn units_in_region(
+    units: &HashMap<String, ScriptUnit>,
+    position: (f32, f32),
+    radius: f32,
+    player_id: Option<PlayerId>,
+) -> HashSet<String> {
+    units
+        .iter()
+        .filter(|(_, unit)| player_id.is_none() || unit.owner == player_id)
+        .filter(|(_, unit)| {
+            (unit.position.0 - position.0).powi(2) + (unit.position.1 - position.1).powi(2)
+                <= radius * radius
+        })
+        .map(|(id, _)| id.clone())
+        .collect()
+}
+
+/// Scripts can write positions as whole numbers or with decimals.
+fn number(value: Dynamic) -> ScriptResult<f32> {
+    if let Ok(value) = value.as_float() {
+        return Ok(value as f32);
+    }
+    match value.as_int() {
+        Ok(value) => Ok(value as f32),
+        Err(type_name) => Err(format!("Expected a number, got {}", type_name).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::game::game_loop::Game;
+
+    fn script(source: &str) -> Script {
+        let path = env::temp_dir().join(format!("thebsgame-script-{}.rhai", Uuid::new_v4()));
+        fs::write(&path, source).unwrap();
+        let script = Script::load(&path, &MapResource::default()).unwrap();
+        fs::remove_file(&path).unwrap();
+        script
+    }
+
+    /// The commands the script gives this tick, then moves time on a tick.
+    fn tick(game: &mut Game, script: &mut Script) -> Vec<GameCommand> {
+        let commands = script.commands(&game.world, &game.resources);
+        game.resources.get_mut::<TimeResource>().unwrap().ticks += 1;
+        commands
+    }
+
+    fn spawned(commands: &[GameCommand]) -> Vec<UnitType> {
+        commands
+            .iter()
+            .filter_map(|command| match command {
+                GameCommand::CreateUnitCommand { unit, .. } => Some(unit.unit_type),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn calls_timed_triggers_on_their_ticks() {
+        let mut game = Game::empty();
+        // Scripts count ticks from when they start, not from the start of the match
+        game.resources.get_mut::<TimeResource>().unwrap().ticks = 10;
+        let mut script = script(
+            "at_tick(2, || { spawn_unit(\"Soldier\", 10, 10, 0); });
+             every(3, || { spawn_unit(\"Worker\", 10, 10, 0); });",
+        );
+
+        let spawned: Vec<Vec<UnitType>> = (0..7)
+            .map(|_| spawned(&tick(&mut game, &mut script)))
+            .collect();
+        assert_eq!(
+            spawned,
+            vec![
+                vec![],
+                vec![],
+                vec![UnitType::Soldier],
+                vec![UnitType::Worker],
+                vec![],
+                vec![],
+                vec![UnitType::Worker],
+            ]
+        );
+    }
+
+    #[test]
+    fn enter_region_fires_for_units_coming_in_only() {
+        let mut game = Game::empty();
+        game.spawn_unit("inside", UnitType::Soldier, (10., 10.), 0);
+        game.spawn_unit("outside", UnitType::Soldier, (30., 10.), 0);
+        game.spawn_unit("enemy", UnitType::Soldier, (30., 12.), 1);
+        let mut script = script("on_enter(10, 10, 5, 0, |id| { move_unit(id, 50, 50); });");
+        let moved = |commands: Vec<GameCommand>| -> Vec<String> {
+            commands
+                .into_iter()
+                .filter_map(|command| match command {
+                    GameCommand::SetUnitDestinationCommand { uuid, .. } => Some(uuid),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert!(moved(tick(&mut game, &mut script)).is_empty());
+        assert!(moved(tick(&mut game, &mut script)).is_empty());
+
+        // Only units of the player set it off, once for each time they come in
+        for id in ["outside", "enemy"].iter().copied() {
+            let entity = *game
+                .resources
+                .get::<crate::UidEntityMap>()
+                .unwrap()
+                .get(id)
+                .unwrap();
+            *game
+                .world
+                .entry(entity)
+                .unwrap()
+                .get_component_mut::<Position>()
+                .unwrap() = Position { x: 12., y: 10. };
+        }
+        assert_eq!(moved(tick(&mut game, &mut script)), vec!["outside"]);
+        assert!(moved(tick(&mut game, &mut script)).is_empty());
+    }
+
+    #[test]
+    fn rejects_negative_player_ids() {
+        let script = script("");
+        let error = script
+            .engine
+            .eval::<()>("on_enter(10, 10, 5, -1, |id| {});")
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("-1 is not a player"),
+            "{}",
+            error
+        );
+        assert!(script.state.borrow().triggers.is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+use legion::{systems::CommandBuffer, Entity};
+
+use crate::game::{
+    components::{
+        AbilityCooldowns, BuildingType, Construction, DropOff, Facing, Footprint, Harvester, Health, Owner, PlayerId, Position,
+        ProductionQueue, Structure, Team, TeamId, UnitId, Vision, CONSTRUCTION_START_HEALTH,
+    },
+    game_state::Unit,
+};
+
+/// Adds a unit with the stats of its `unit_type` and full health to the world, returns the new entity.
+pub fn spawn_unit(command_buffer: &mut CommandBuffer, unit: &Unit) -> Entity {
+    let definition = unit.unit_type.definition();
+    let entity = command_buffer.push((
+        Position {
+            x: unit.position.0,
+            y: unit.position.1,
+        },
+        UnitId {
+            id: unit.id.clone(),
+        },
+        unit.unit_type,
+        Vision {
+            sight_radius: definition.sight_radius,
+        },
+        Facing {
+            angle: unit.facing,
+        },
+        definition.movement,
+        Health::full(definition.max_health),
+        definition.weapon,
+    ));
+    // Added up front so handle_commands sees a cooldown started by an earlier command of the same tick
+    command_buffer.add_component(entity, AbilityCooldowns::default());
+    if let Some((capacity, gather_rate)) = definition.harvester {
+        command_buffer.add_component(entity, Harvester::new(capacity, gather_rate));
+    }
+    add_owner(command_buffer, entity, unit.owner, unit.team);
+    entity
+}
+
+/// Adds a building with the stats of its `building_type` to the world, returns the new entity.
+/// The building starts under construction unless `constructed` is set.
+pub fn spawn_building(
+    command_buffer: &mut CommandBuffer,
+    id: &str,
+    building_type: BuildingType,
+    position: (f32, f32),
+    owner: Option<PlayerId>,
+    team: Option<TeamId>,
+    constructed: bool,
+) -> Entity {
+    let definition = building_type.definition();
+    let mut health = Health::full(definition.max_health);
+    if !constructed {
+        health.current = definition.max_health * CONSTRUCTION_START_HEALTH;
+    }
+    let entity = command_buffer.push((
+        Position {
+            x: position.0,
+            y: position.1,
+        },
+        UnitId { id: id.to_string() },
+        building_type,
+        Vision {
+            sight_radius: definition.sight_radius,
+        },
+        Structure,
+        Footprint {
+            width: definition.footprint.0,
+            height: definition.footprint.1,
+        },
+        health,
+    ));
+    if !constructed {
+        command_buffer.add_component(entity, Construction::default());
+    }
+    if !definition.produces.is_empty() {
+        command_buffer.add_component(entity, ProductionQueue::default());
+    }
+    if definition.drop_off {
+        command_buffer.add_component(entity, DropOff);
+    }
+    add_owner(command_buffer, entity, owner, team);
+    entity
+}
+
+fn add_owner(
+    command_buffer: &mut CommandBuffer,
+    entity: Entity,
+    owner: Option<PlayerId>,
+    team: Option<TeamId>,
+) {
+    if let Some(player_id) = owner {
+        command_buffer.add_component(entity, Owner { player_id });
+    }
+    if let Some(team_id) = team {
+        command_buffer.add_component(entity, Team { team_id });
+    }
+}
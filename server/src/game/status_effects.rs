@@ -0,0 +1,135 @@
+use legion::{systems::CommandBuffer, world::SubWorld, Entity, EntityStore};
+use serde::{Deserialize, Serialize};
+
+use crate::game::components::{DamageOverTime, PlayerId, Slow, Stun, MAX_DAMAGE_OVER_TIME_STACKS};
+
+/// A temporary effect on a unit, lasting `ticks`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum StatusEffect {
+    /// Multiplies the speed of the unit by `factor`.
+    Slow {
+        factor: f32,
+        ticks: u64,
+    },
+    Stun {
+        ticks: u64,
+    },
+    /// Takes `damage` health every tick.
+    DamageOverTime {
+        damage: f32,
+        ticks: u64,
+    },
+}
+
+/// Puts a status effect on `entity`, combining it with the effect of the same kind the unit already has:
+/// a slow keeps the strongest factor and the longest duration, a stun keeps the longest duration,
+/// and damage over time stacks up to `MAX_DAMAGE_OVER_TIME_STACKS` times, restarting its duration.
+/// The damage of damage over time is counted for `source`, the player that applied it last.
+/// The world needs write access to `Slow`, `Stun` and `DamageOverTime`.
+pub fn apply_status_effect(
+    world: &mut SubWorld,
+    command_buffer: &mut CommandBuffer,
+    entity: Entity,
+    effect: StatusEffect,
+    source: PlayerId,
+) {
+    let mut entry = match world.entry_mut(entity) {
+        Ok(entry) => entry,
+        Err(_) => return,
+    };
+    match effect {
+        StatusEffect::Slow { factor, ticks } => match entry.get_component_mut::<Slow>() {
+            Ok(slow) => {
+                slow.factor = slow.factor.min(factor);
+                slow.ticks_left = slow.ticks_left.max(ticks);
+            }
+            Err(_) => command_buffer.add_component(
+                entity,
+                Slow {
+                    factor,
+                    ticks_left: ticks,
+                },
+            ),
+        },
+        StatusEffect::Stun { ticks } => match entry.get_component_mut::<Stun>() {
+            Ok(stun) => stun.ticks_left = stun.ticks_left.max(ticks),
+            Err(_) => command_buffer.add_component(entity, Stun { ticks_left: ticks }),
+        },
+        StatusEffect::DamageOverTime { damage, ticks } => {
+            match entry.get_component_mut::<DamageOverTime>() {
+                Ok(dot) => {
+                    dot.damage = damage;
+                    dot.stacks = (dot.stacks + 1).min(MAX_DAMAGE_OVER_TIME_STACKS);
+                    dot.ticks_left = ticks;
+                    dot.source = Some(source);
+                }
+                Err(_) => command_buffer.add_component(
+                    entity,
+                    DamageOverTime {
+                        damage,
+                        stacks: 1,
+                        ticks_left: ticks,
+                        source: Some(source),
+                    },
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        components::{AbilityType, CastAbility, UnitType},
+        game_loop::Game,
+        systems::cast_abilities_system,
+    };
+
+    /// Has `soldier` throw `ability` at where the enemy stands.
+    fn throw(game: &mut Game, soldier: Entity, ability: AbilityType) {
+        game.world
+            .entry(soldier)
+            .unwrap()
+            .add_component(CastAbility {
+                ability,
+                position: (20., 10.),
+                target: None,
+            });
+        game.run(cast_abilities_system());
+    }
+
+    #[test]
+    fn damage_over_time_stacks_up_to_the_maximum() {
+        let mut game = Game::empty();
+        let soldier = game.spawn_unit("soldier", UnitType::Soldier, (10., 10.), 1);
+        game.spawn_unit("enemy", UnitType::Soldier, (20., 10.), 2);
+
+        for stacks in 1..=MAX_DAMAGE_OVER_TIME_STACKS + 1 {
+            throw(&mut game, soldier, AbilityType::Grenade);
+            let dot = game.component::<DamageOverTime>("enemy").unwrap();
+            assert_eq!(dot.stacks, stacks.min(MAX_DAMAGE_OVER_TIME_STACKS));
+            assert_eq!(dot.source, Some(1));
+        }
+    }
+
+    #[test]
+    fn stuns_and_slows_keep_the_longest_and_strongest() {
+        let mut game = Game::empty();
+        let soldier = game.spawn_unit("soldier", UnitType::Soldier, (10., 10.), 1);
+        let enemy = game.spawn_unit("enemy", UnitType::Soldier, (20., 10.), 2);
+        throw(&mut game, soldier, AbilityType::Flashbang);
+        {
+            let mut entry = game.world.entry(enemy).unwrap();
+            let slow = entry.get_component_mut::<Slow>().unwrap();
+            slow.factor = 0.8;
+            slow.ticks_left = 20;
+            entry.get_component_mut::<Stun>().unwrap().ticks_left = 1;
+        }
+
+        throw(&mut game, soldier, AbilityType::Flashbang);
+        let slow = game.component::<Slow>("enemy").unwrap();
+        assert_eq!((slow.factor, slow.ticks_left), (0.5, 20));
+        assert_eq!(game.component::<Stun>("enemy").unwrap().ticks_left, 2);
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashSet;
+
+use legion::{system, systems::CommandBuffer, world::SubWorld, Entity, IntoQuery};
+use vector2d::Vector2D;
+
+use crate::game::{
+    combat::{attackables, deal_damage, enemies_within},
+    components::{
+        Destination, Facing, Footprint, HarvestState, Harvester, Health, Order, Owner, PlayerId,
+        Position, Projectile, Route, Stun, Target, UnitId, Velocity, Weapon,
+    },
+    events::{GameEvent, ProjectileFired},
+    resources::{EventsResource, StatsResource, TeamsResource, TimeResource},
+};
+
+/// Makes units with a weapon attack the closest enemy around them, as far as their order allows.
+/// Idle units and units holding their position attack enemies within range of their weapon,
+/// units on an attack-move or patrol also go after enemies within their acquisition range.
+/// Units on a plain move order, harvesting units and stunned units don't fight.
+/// Weapons that fire projectiles spawn them, the projectiles do the damage once they hit.
+#[system]
+#[read_component(UnitId)]
+#[read_component(Position)]
+#[read_component(Owner)]
+#[read_component(Footprint)]
+#[read_component(Order)]
+#[read_component(Harvester)]
+#[read_component(Target)]
+#[read_component(Stun)]
+#[write_component(Weapon)]
+#[write_component(Facing)]
+#[write_component(Health)]
+pub fn attack(
+    world: &mut SubWorld,
+    command_buffer: &mut CommandBuffer,
+    #[resource] teams: &TeamsResource,
+    #[resource] time: &TimeResource,
+    #[resource] events: &mut EventsResource,
+    #[resource] stats: &mut StatsResource,
+) {
+    let attackables = attackables(world);
+    let harvesting: HashSet<Entity> = <(Entity, &Harvester)>::query()
+        .iter(world)
+        .filter(|(_, harvester)| harvester.state != HarvestState::Idle)
+        .map(|(entity, _)| *entity)
+        .collect();
+    let stunned: HashSet<Entity> = <(Entity, &Stun)>::query()
+        .iter(world)
+        .map(|(entity, _)| *entity)
+        .collect();
+
+    let elapsed = time.elapsed_seconds as f32;
+    let mut hits: Vec<(Entity, f32, PlayerId)> = Vec::new();
+    <(
+        Entity,
+        &UnitId,
+        &Position,
+        &Owner,
+        &mut Weapon,
+        Option<&Order>,
+        Option<&Target>,
+        Option<&mut Facing>,
+    )>::query()
+    .for_each_mut(
+        world,
+        |(entity, id, pos, owner, weapon, order, target, facing)| {
+            weapon.ready_in = (weapon.ready_in - elapsed).max(0.);
+            let order = order.unwrap_or(&Order::Idle);
+            let scan_range = match order {
+                Order::Move { .. } => None,
+                _ if harvesting.contains(entity) || stunned.contains(entity) => None,
+                Order::AttackMove { .. } | Order::Patrol { .. } => Some(weapon.acquisition_range),
+                Order::Idle | Order::HoldPosition => Some(weapon.range),
+            };
+            let pos = Vector2D::new(pos.x, pos.y);
+            let closest = scan_range.and_then(|scan_range| {
+                enemies_within(&attackables, teams, owner.player_id, pos, scan_range)
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+            });
+
+            match closest {
+                Some((enemy, distance)) => {
+                    if distance <= weapon.range {
+                        command_buffer.remove_component::<Destination>(*entity);
+                        command_buffer.remove_component::<Velocity>(*entity);
+                        command_buffer.remove_component::<Route>(*entity);
+                        let direction = enemy.position - pos;
+                        if let (Some(facing), true) = (facing, direction.length_squared() > 0.) {
+                            facing.angle = direction.y.atan2(direction.x);
+                        }
+                        if weapon.ready_in <= 0. {
+                            weapon.ready_in = weapon.cooldown;
+                            match weapon.projectile {
+                                Some(stats) => {
+                                    let projectile = Projectile {
+                                        id: format!("{}-shot-{}", id.id, time.ticks),
+                                        attacker: id.id.clone(),
+                                        owner: owner.player_id,
+                                        target: enemy.id.clone(),
+                                        damage: weapon.damage,
+                                        stats,
+                                        impact: (enemy.position.x, enemy.position.y),
+                                    };
+                                    events.events.push(GameEvent::ProjectileFired(
+                                        ProjectileFired {
+                                            id: projectile.id.clone(),
+                                            attacker: projectile.attacker.clone(),
+                                            target: projectile.target.clone(),
+                                            owner: projectile.owner,
+                                            position: (pos.x, pos.y),
+                                            impact: projectile.impact,
+                                            speed: stats.speed,
+                                            homing: stats.homing,
+                                        },
+                                    ));
+                                    command_buffer.push((
+                                        Position { x: pos.x, y: pos.y },
+                                        Velocity { dx: 0., dy: 0. },
+                                        projectile,
+                                    ));
+                                }
+                                None => hits.push((enemy.entity, weapon.damage, owner.player_id)),
+                            }
+                        }
+                    } else {
+                        command_buffer.add_component(
+                            *entity,
+                            Destination {
+                                x: enemy.position.x,
+                                y: enemy.position.y,
+                            },
+                        );
+                    }
+                    if target.map(|t| t.id != enemy.id).unwrap_or(true) {
+                        command_buffer.add_component(
+                            *entity,
+                            Target {
+                                id: enemy.id.clone(),
+                            },
+                        );
+                    }
+                }
+                // The fight is over, back to the order
+                None if target.is_some() => {
+                    command_buffer.remove_component::<Target>(*entity);
+                    if let Some((x, y)) = order.destination() {
+                        command_buffer.add_component(*entity, Destination { x, y });
+                    }
+                }
+                None => {}
+            }
+        },
+    );
+
+    for (entity, damage, player_id) in hits {
+        stats.player(player_id).damage_dealt += deal_damage(world, entity, damage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{commands::GameCommand, components::UnitType, game_loop::Game};
+
+    fn give_order(game: &mut Game, unit: Entity, order: Order) {
+        game.world.entry(unit).unwrap().add_component(order);
+    }
+
+    /// The targets `attacker` fired at since the last call.
+    fn fired_at(game: &mut Game, attacker: &str) -> Vec<String> {
+        let mut events = game.resources.get_mut::<EventsResource>().unwrap();
+        events
+            .events
+            .drain(..)
+            .filter_map(|event| match event {
+                GameEvent::ProjectileFired(fired) if fired.attacker == attacker => {
+                    Some(fired.target)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn melee_weapons_hit_once_per_cooldown() {
+        let mut game = Game::empty();
+        game.spawn_unit("worker", UnitType::Worker, (10., 10.), 1);
+        game.spawn_unit("enemy", UnitType::Worker, (11., 10.), 2);
+        let health = |game: &Game| game.component::<Health>("enemy").unwrap().current;
+
+        // Workers hit for 5 every 1.5 seconds, and both fight back
+        game.run(attack_system());
+        assert_eq!(health(&game), 35.);
+        game.run(attack_system());
+        assert_eq!(health(&game), 35.);
+        game.run(attack_system());
+        assert_eq!(health(&game), 30.);
+        let mut stats = game.resources.get_mut::<StatsResource>().unwrap();
+        assert_eq!(stats.player(1).damage_dealt, 10.);
+        let target = game.component::<Target>("worker").unwrap();
+        assert_eq!(target.id, "enemy");
+    }
+
+    #[test]
+    fn fires_at_the_closest_enemy_in_range() {
+        let mut game = Game::empty();
+        game.spawn_unit("soldier", UnitType::Soldier, (10., 10.), 1);
+        game.spawn_unit("far", UnitType::Soldier, (18., 10.), 2);
+        game.spawn_unit("close", UnitType::Soldier, (10., 15.), 2);
+        game.spawn_unit("ally", UnitType::Soldier, (10., 11.), 3);
+        game.handle(vec![
+            GameCommand::JoinTeamCommand {
+                player_id: 1,
+                team_id: 1,
+            },
+            GameCommand::JoinTeamCommand {
+                player_id: 3,
+                team_id: 1,
+            },
+        ]);
+
+        game.run(attack_system());
+        assert_eq!(fired_at(&mut game, "soldier"), vec!["close"]);
+        assert_eq!(game.component::<Target>("soldier").unwrap().id, "close");
+        // Projectiles do the damage once they hit
+        assert_eq!(game.component::<Health>("close").unwrap().current, 100.);
+    }
+
+    #[test]
+    fn orders_decide_how_far_units_go_after_enemies() {
+        let mut game = Game::empty();
+        let attacker = game.spawn_unit("attack-mover", UnitType::Soldier, (10., 10.), 1);
+        give_order(
+            &mut game,
+            attacker,
+            Order::AttackMove {
+                position: (60., 10.),
+            },
+        );
+        let mover = game.spawn_unit("mover", UnitType::Soldier, (10., 12.), 1);
+        give_order(
+            &mut game,
+            mover,
+            Order::Move {
+                position: (60., 10.),
+            },
+        );
+        let holder = game.spawn_unit("holder", UnitType::Soldier, (10., 14.), 1);
+        give_order(&mut game, holder, Order::HoldPosition);
+        // Beyond the range of soldiers, within their acquisition range
+        game.spawn_unit("enemy", UnitType::Worker, (24., 12.), 2);
+
+        game.run(attack_system());
+        let destination = game.component::<Destination>("attack-mover").unwrap();
+        assert_eq!((destination.x, destination.y), (24., 12.));
+        assert!(game.component::<Target>("mover").is_none());
+        assert!(game.component::<Target>("holder").is_none());
+        assert!(game.component::<Destination>("holder").is_none());
+    }
+
+    #[test]
+    fn stunned_units_dont_fight() {
+        let mut game = Game::empty();
+        let soldier = game.spawn_unit("soldier", UnitType::Soldier, (10., 10.), 1);
+        game.world
+            .entry(soldier)
+            .unwrap()
+            .add_component(Stun { ticks_left: 2 });
+        game.spawn_unit("enemy", UnitType::Worker, (12., 10.), 2);
+
+        game.run(attack_system());
+        assert!(fired_at(&mut game, "soldier").is_empty());
+        assert!(game.component::<Target>("soldier").is_none());
+    }
+
+    #[test]
+    fn units_go_back_to_their_order_once_the_fight_is_over() {
+        let mut game = Game::empty();
+        let soldier = game.spawn_unit("soldier", UnitType::Soldier, (10., 10.), 1);
+        give_order(
+            &mut game,
+            soldier,
+            Order::AttackMove {
+                position: (60., 10.),
+            },
+        );
+        let enemy = game.spawn_unit("enemy", UnitType::Worker, (12., 10.), 2);
+        game.run(attack_system());
+        assert!(game.component::<Target>("soldier").is_some());
+
+        game.world.remove(enemy);
+        game.run(attack_system());
+        assert!(game.component::<Target>("soldier").is_none());
+        let destination = game.component::<Destination>("soldier").unwrap();
+        assert_eq!((destination.x, destination.y), (60., 10.));
+    }
+}
@@ -0,0 +1,149 @@
+use legion::{system, systems::CommandBuffer, world::SubWorld, Entity, IntoQuery};
+use vector2d::Vector2D;
+
+use crate::game::{
+    abilities::AbilityEffect,
+    combat::{attackables, deal_damage, heal, within},
+    components::{
+        CastAbility, DamageOverTime, Footprint, Health, Owner, PlayerId, Position, Slow, Stun,
+        UnitId,
+    },
+    events::{AbilityUsed, GameEvent},
+    resources::{EventsResource, StatsResource, TeamsResource},
+    status_effects::apply_status_effect,
+};
+
+/// Applies the effect of the abilities units are using to everything within the radius of the ability,
+/// abilities that heal only affect allies and other abilities only affect enemies.
+#[system]
+#[read_component(UnitId)]
+#[read_component(Position)]
+#[read_component(Owner)]
+#[read_component(Footprint)]
+#[read_component(CastAbility)]
+#[write_component(Health)]
+#[write_component(Slow)]
+#[write_component(Stun)]
+#[write_component(DamageOverTime)]
+pub fn cast_abilities(
+    world: &mut SubWorld,
+    command_buffer: &mut CommandBuffer,
+    #[resource] teams: &TeamsResource,
+    #[resource] events: &mut EventsResource,
+    #[resource] stats: &mut StatsResource,
+) {
+    let casts: Vec<(Entity, String, PlayerId, CastAbility)> =
+        <(Entity, &UnitId, &Owner, &CastAbility)>::query()
+            .iter(world)
+            .map(|(entity, id, owner, cast)| {
+                (*entity, id.id.clone(), owner.player_id, cast.clone())
+            })
+            .collect();
+    if casts.is_empty() {
+        return;
+    }
+
+    let attackables = attackables(world);
+    for (entity, caster, owner, cast) in casts {
+        let definition = cast.ability.definition();
+        let position = Vector2D::new(cast.position.0, cast.position.1);
+        let hit: Vec<(Entity, String)> = within(&attackables, position, definition.radius)
+            .map(|(other, _)| other)
+            // Abilities without a radius only affect the unit they are used on
+            .filter(|other| {
+                definition.radius > 0. || cast.target.as_ref().is_none_or(|t| *t == other.id)
+            })
+            .filter(|other| teams.are_allies(owner, other.owner) == definition.helps_allies())
+            .map(|other| (other.entity, other.id.clone()))
+            .collect();
+        for (target, _) in &hit {
+            for effect in definition.effects {
+                match *effect {
+                    AbilityEffect::Damage(damage) => {
+                        stats.player(owner).damage_dealt += deal_damage(world, *target, damage)
+                    }
+                    AbilityEffect::Heal(amount) => heal(world, *target, amount),
+                    AbilityEffect::Status(status) => {
+                        apply_status_effect(world, command_buffer, *target, status, owner)
+                    }
+                }
+            }
+        }
+        events.events.push(GameEvent::AbilityUsed(AbilityUsed {
+            caster,
+            owner,
+            ability: cast.ability,
+            position: cast.position,
+            hit: hit.into_iter().map(|(_, id)| id).collect(),
+        }));
+        command_buffer.remove_component::<CastAbility>(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        commands::GameCommand,
+        components::{AbilityType, UnitType},
+        game_loop::Game,
+    };
+
+    fn cast(game: &mut Game, caster: Entity, ability: AbilityType, position: (f32, f32)) {
+        game.world
+            .entry(caster)
+            .unwrap()
+            .add_component(CastAbility {
+                ability,
+                position,
+                target: None,
+            });
+    }
+
+    #[test]
+    fn damaging_abilities_hit_enemies_within_their_radius() {
+        let mut game = Game::empty();
+        let soldier = game.spawn_unit("soldier", UnitType::Soldier, (10., 10.), 1);
+        game.spawn_unit("enemy", UnitType::Soldier, (20., 10.), 2);
+        game.spawn_unit("far", UnitType::Soldier, (25., 10.), 2);
+        game.spawn_unit("own", UnitType::Soldier, (20., 11.), 1);
+        cast(&mut game, soldier, AbilityType::Grenade, (20., 10.));
+
+        game.run(cast_abilities_system());
+        let health = |game: &Game, id| game.component::<Health>(id).unwrap().current;
+        assert_eq!(health(&game, "enemy"), 80.);
+        assert_eq!(health(&game, "far"), 100.);
+        assert_eq!(health(&game, "own"), 100.);
+        assert!(game.component::<DamageOverTime>("enemy").is_some());
+        assert!(game.component::<CastAbility>("soldier").is_none());
+    }
+
+    #[test]
+    fn healing_abilities_only_heal_allies() {
+        let mut game = Game::empty();
+        let soldier = game.spawn_unit("soldier", UnitType::Soldier, (10., 10.), 1);
+        let ally = game.spawn_unit("ally", UnitType::Soldier, (12., 10.), 3);
+        let enemy = game.spawn_unit("enemy", UnitType::Soldier, (10., 12.), 2);
+        game.handle(vec![
+            GameCommand::JoinTeamCommand {
+                player_id: 1,
+                team_id: 1,
+            },
+            GameCommand::JoinTeamCommand {
+                player_id: 3,
+                team_id: 1,
+            },
+        ]);
+        for unit in [soldier, ally, enemy].iter().copied() {
+            let mut entry = game.world.entry(unit).unwrap();
+            entry.get_component_mut::<Health>().unwrap().current = 50.;
+        }
+        cast(&mut game, soldier, AbilityType::Rally, (10., 10.));
+
+        game.run(cast_abilities_system());
+        let health = |game: &Game, id| game.component::<Health>(id).unwrap().current;
+        assert_eq!(health(&game, "soldier"), 65.);
+        assert_eq!(health(&game, "ally"), 65.);
+        assert_eq!(health(&game, "enemy"), 50.);
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use legion::{system, world::SubWorld, IntoQuery};
+
+use crate::game::{
+    components::{BuildingType, Owner, PlayerId, Position, UnitType},
+    events::GameEvent,
+    resources::{
+        EconomyResource, EventsResource, MatchResource, StatsResource, TeamsResource, TimeResource,
+    },
+    victory::{MatchEnded, PlayerResult, Side, VictoryCondition},
+};
+
+/// Checks the victory conditions of the match after every tick, in the order they were set,
+/// and ends the match with `GameEvent::MatchEnded` once one of them is met.
+#[system]
+#[read_component(Owner)]
+#[read_component(Position)]
+#[read_component(UnitType)]
+#[read_component(BuildingType)]
+pub fn check_victory(
+    world: &mut SubWorld,
+    #[resource] match_state: &mut MatchResource,
+    #[resource] teams: &TeamsResource,
+    #[resource] economy: &EconomyResource,
+    #[resource] stats: &StatsResource,
+    #[resource] time: &TimeResource,
+    #[resource] events: &mut EventsResource,
+) {
+    if match_state.is_over() {
+        return;
+    }
+    match_state.elapsed_seconds += time.elapsed_seconds;
+    // A save of a match with other conditions has progress for those
+    let conditions = match_state.conditions.len();
+    match_state.control.resize(conditions, None);
+
+    let mut results: HashMap<PlayerId, PlayerResult> = HashMap::new();
+    <(&Owner, Option<&UnitType>, Option<&BuildingType>)>::query().for_each(
+        world,
+        |(owner, unit_type, building_type)| {
+            let result = results.entry(owner.player_id).or_default();
+            if let Some(unit_type) = unit_type {
+                result.units += 1;
+                result.score += unit_type.definition().cost;
+            }
+            if let Some(building_type) = building_type {
+                result.buildings += 1;
+                result.score += building_type.definition().cost;
+            }
+        },
+    );
+    match_state.players.extend(results.keys().copied());
+    for player_id in &match_state.players {
+        results.entry(*player_id).or_default().score += economy.balance(*player_id);
+    }
+
+    let winner = match_state
+        .conditions
+        .clone()
+        .into_iter()
+        .enumerate()
+        .find_map(|(index, condition)| match condition {
+            VictoryCondition::Elimination => {
+                let sides: BTreeSet<Side> = match_state
+                    .players
+                    .iter()
+                    .map(|player_id| Side::of(teams, *player_id))
+                    .collect();
+                let alive: Vec<Side> = results
+                    .iter()
+                    .filter(|(_, result)| result.units + result.buildings > 0)
+                    .map(|(player_id, _)| Side::of(teams, *player_id))
+                    .collect::<BTreeSet<Side>>()
+                    .into_iter()
+                    .collect();
+                // A match needs two sides before one of them can be eliminated
+                if sides.len() >= 2 && alive.len() <= 1 {
+                    Some((condition, alive.first().copied()))
+                } else {
+                    None
+                }
+            }
+            VictoryCondition::ControlPoint {
+                position,
+                radius,
+                seconds,
+            } => {
+                let holders: BTreeSet<Side> = <(&Owner, &Position, &UnitType)>::query()
+                    .iter(world)
+                    .filter(|(_, pos, _)| {
+                        (pos.x - position.0).powi(2) + (pos.y - position.1).powi(2)
+                            <= radius * radius
+                    })
+                    .map(|(owner, _, _)| Side::of(teams, owner.player_id))
+                    .collect();
+                // A contested point keeps the progress of the side holding it
+                let control = &mut match_state.control[index];
+                match (holders.len(), *control) {
+                    (0, _) => *control = None,
+                    (1, Some((side, held))) if holders.contains(&side) => {
+                        *control = Some((side, held + time.elapsed_seconds))
+                    }
+                    (1, _) => {
+                        let side = *holders.iter().next().expect("There is one holder");
+                        *control = Some((side, time.elapsed_seconds));
+                    }
+                    _ => {}
+                }
+                match *control {
+                    Some((side, held)) if held >= seconds => Some((condition, Some(side))),
+                    _ => None,
+                }
+            }
+            VictoryCondition::TimeLimit { seconds } => {
+                if match_state.elapsed_seconds < seconds {
+                    return None;
+                }
+                let mut scores: BTreeMap<Side, u32> = BTreeMap::new();
+                for (player_id, result) in &results {
+                    *scores.entry(Side::of(teams, *player_id)).or_insert(0) += result.score;
+                }
+                let best = scores.values().copied().max();
+                let mut leaders = scores.iter().filter(|(_, score)| Some(**score) == best);
+                // A tie for the highest score is a draw
+                let winner = match (leaders.next(), leaders.next()) {
+                    (Some((side, _)), None) => Some(*side),
+                    _ => None,
+                };
+                Some((condition, winner))
+            }
+        });
+
+    if let Some((condition, winner)) = winner {
+        for (player_id, stats) in stats.summary(match_state.elapsed_seconds) {
+            results.entry(player_id).or_default().stats = stats;
+        }
+        let result = MatchEnded {
+            winner,
+            condition,
+            ticks: time.ticks,
+            stats: results,
+        };
+        match_state.result = Some(result.clone());
+        events.events.push(GameEvent::MatchEnded(result));
+    }
+}
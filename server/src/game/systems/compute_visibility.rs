@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use legion::{system, world::SubWorld, Entity, IntoQuery};
+use vector2d::Vector2D;
+
+use crate::game::{
+    components::{
+        BuildingType, Construction, Footprint, Health, Owner, PlayerId, Position, ProductionQueue, RallyPoint,
+        Structure, Team, UnitId, Vision,
+    },
+    game_state::BuildingState,
+    resources::{TeamsResource, VisibilityResource},
+};
+
+#[system]
+#[read_component(Position)]
+#[read_component(Vision)]
+#[read_component(Owner)]
+#[read_component(Team)]
+#[read_component(UnitId)]
+#[read_component(Structure)]
+#[read_component(BuildingType)]
+#[read_component(Construction)]
+#[read_component(Footprint)]
+#[read_component(Health)]
+#[read_component(ProductionQueue)]
+#[read_component(RallyPoint)]
+pub fn compute_visibility(
+    world: &SubWorld,
+    #[resource] teams: &TeamsResource,
+    #[resource] visibility: &mut VisibilityResource,
+) {
+    // Every player owning something, with the position and sight radius of everything they can see with
+    let mut player_eyes: HashMap<PlayerId, Vec<(Vector2D<f32>, f32)>> = HashMap::new();
+    <(&Owner, Option<&Position>, Option<&Vision>)>::query().for_each(
+        world,
+        |(owner, pos, vision)| {
+            let eyes = player_eyes.entry(owner.player_id).or_default();
+            if let (Some(pos), Some(vision)) = (pos, vision) {
+                eyes.push((Vector2D::new(pos.x, pos.y), vision.sight_radius));
+            }
+        },
+    );
+    // Allies share vision, so each player sees with the eyes of their whole team
+    let mut eyes: HashMap<PlayerId, Vec<(Vector2D<f32>, f32)>> = HashMap::new();
+    for player_id in player_eyes.keys() {
+        let shared_eyes = player_eyes
+            .iter()
+            .filter(|(ally, _)| teams.are_allies(*player_id, **ally))
+            .flat_map(|(_, ally_eyes)| ally_eyes.iter().copied())
+            .collect();
+        eyes.insert(*player_id, shared_eyes);
+    }
+    let can_see = |player_id: &PlayerId, x: f32, y: f32| {
+        let target = Vector2D::new(x, y);
+        eyes[player_id]
+            .iter()
+            .any(|(eye, radius)| (target - *eye).length_squared() <= radius * radius)
+    };
+
+    visibility.visible.clear();
+    <(
+        Entity,
+        &UnitId,
+        &Position,
+        Option<&Owner>,
+        Option<&Structure>,
+    )>::query()
+    .for_each(world, |(entity, id, pos, owner, structure)| {
+        for player_id in eyes.keys() {
+            let allied = owner
+                .map(|o| teams.are_allies(*player_id, o.player_id))
+                .unwrap_or(false);
+            if !allied && !can_see(player_id, pos.x, pos.y) {
+                continue;
+            }
+            visibility
+                .visible
+                .entry(*player_id)
+                .or_default()
+                .insert(id.id.clone());
+            if structure.is_some() {
+                if let Some(building) = BuildingState::read(world, *entity) {
+                    visibility
+                        .last_known_structures
+                        .entry(*player_id)
+                        .or_default()
+                        .insert(id.id.clone(), building);
+                }
+            }
+        }
+    });
+
+    // A remembered structure is forgotten once the player sees its position again and it is gone
+    let visible = &visibility.visible;
+    for (player_id, structures) in visibility.last_known_structures.iter_mut() {
+        let player_visible = visible.get(player_id);
+        structures.retain(|id, structure| {
+            player_visible.map(|v| v.contains(id)).unwrap_or(false)
+                || !eyes.contains_key(player_id)
+                || !can_see(player_id, structure.position.0, structure.position.1)
+        });
+    }
+}
@@ -0,0 +1,26 @@
+use legion::{system, systems::CommandBuffer, Entity};
+
+use crate::game::{
+    components::{BuildingType, Construction, Health, CONSTRUCTION_START_HEALTH},
+    resources::TimeResource,
+};
+
+/// Advances the construction of buildings, they gain health as they are built and become functional when done.
+#[system(for_each)]
+pub fn construct_buildings(
+    entity: &Entity,
+    construction: &mut Construction,
+    health: &mut Health,
+    building_type: &BuildingType,
+    #[resource] time: &TimeResource,
+    command_buffer: &mut CommandBuffer,
+) {
+    let step = (time.elapsed_seconds as f32 / building_type.definition().build_time)
+        .min(1. - construction.progress);
+    construction.progress += step;
+    health.current = (health.current + health.max * (1. - CONSTRUCTION_START_HEALTH) * step)
+        .min(health.max);
+    if construction.progress >= 1. {
+        command_buffer.remove_component::<Construction>(*entity);
+    }
+}
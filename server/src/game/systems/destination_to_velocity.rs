@@ -1,29 +1,152 @@
-use crate::game::components::{Destination, Position, Velocity};
-use crate::game::resources::TimeResource;
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::{PI, TAU},
+};
+
+use crate::game::components::{
+    Destination, Facing, GroupSpeed, MovementProfile, Position, Route, Slow, Stun, Velocity,
+};
+use crate::game::resources::{MapResource, TimeResource};
 use legion::*;
 use legion::{system, systems::CommandBuffer, world::SubWorld, Entity};
 use vector2d::Vector2D;
 
+/// Steers units along the route to their destination, turning and changing speed as fast as their `MovementProfile` allows.
 #[system]
 #[read_component(Position)]
+#[read_component(MovementProfile)]
+#[read_component(GroupSpeed)]
+#[read_component(Slow)]
+#[read_component(Stun)]
 #[write_component(Destination)]
 #[write_component(Velocity)]
+#[write_component(Route)]
+#[write_component(Facing)]
 pub fn destination_to_velocity(
     world: &mut SubWorld,
     command_buffer: &mut CommandBuffer,
     #[resource] time: &TimeResource,
+    #[resource] map: &MapResource,
 ) {
+    let stunned: HashSet<Entity> = <(Entity, &Stun)>::query()
+        .iter(world)
+        .map(|(entity, _)| *entity)
+        .collect();
+    let slowed: HashMap<Entity, f32> = <(Entity, &Slow)>::query()
+        .iter(world)
+        .map(|(entity, slow)| (*entity, slow.factor))
+        .collect();
+
     //The maybe_changed<Destination> should filter out most destinations that have not changed, but not all
-    let mut qeury = <(&Position, &Destination, Option<&mut Velocity>, Entity)>::query();
-    qeury.for_each_mut(world, |(pos, des, vel_op, entity)| {
+    let mut qeury = <(
+        &Position,
+        &Destination,
+        Option<&mut Velocity>,
+        Option<&mut Route>,
+        Option<&mut Facing>,
+        Option<&MovementProfile>,
+        Option<&GroupSpeed>,
+        Entity,
+    )>::query();
+    qeury.for_each_mut(world, |(pos, des, vel_op, route_op, facing_op, profile, group_speed, entity)| {
+        let destination = (des.x, des.y);
+        let mut new_route = None;
+        let route = match route_op {
+            Some(route) if route.destination == destination => route,
+            _ => match Route::find(map, (pos.x, pos.y), destination) {
+                Some(route) => new_route.insert(route),
+                // There is no way to get there, so the unit stays where it is
+                None => {
+                    command_buffer.remove_component::<Destination>(*entity);
+                    command_buffer.remove_component::<Velocity>(*entity);
+                    command_buffer.remove_component::<Route>(*entity);
+                    command_buffer.remove_component::<GroupSpeed>(*entity);
+                    return;
+                }
+            },
+        };
+
+        // Stunned units stop right away, and keep their destination for when the stun wears off
+        if stunned.contains(entity) {
+            if let Some(vel) = vel_op {
+                vel.dx = 0.;
+                vel.dy = 0.;
+            }
+            if let Some(route) = new_route {
+                command_buffer.add_component(*entity, route);
+            }
+            return;
+        }
+
         let pos_vec = Vector2D { x: pos.x, y: pos.y };
-        let des_vec = Vector2D { x: des.x, y: des.y };
+        while route.waypoints.len() > 1 {
+            let (x, y) = route.waypoints[0];
+            if (Vector2D::new(x, y) - pos_vec).length_squared() >= 0.1 {
+                break;
+            }
+            route.waypoints.pop_front();
+        }
+        let (x, y) = route.waypoints[0];
+        let des_vec = Vector2D { x, y };
         let direction = des_vec - pos_vec;
-        let mut velocity = direction.clone().normalise() * 5.; //todo make a unit have variable velocity
-        let velocity_timed = velocity * time.elapsed_seconds as f32;
-        //we don't want to overshoot the target
-        if direction.clone().length_squared() < velocity_timed.length_squared() {
-            velocity = direction;
+        let elapsed = time.elapsed_seconds as f32;
+        let default_profile = MovementProfile::default();
+        let profile = profile.unwrap_or(&default_profile);
+        // Units moving with a group keep to the speed of its slowest unit
+        let max_speed = group_speed
+            .map(|group| group.max_speed.min(profile.max_speed))
+            .unwrap_or(profile.max_speed)
+            * slowed.get(entity).copied().unwrap_or(1.);
+
+        // Turn toward the next waypoint, no further than the turn rate allows
+        let mut new_facing = None;
+        let facing = match facing_op {
+            Some(facing) => facing,
+            None => new_facing.insert(Facing {
+                angle: direction.y.atan2(direction.x),
+            }),
+        };
+        let wanted_angle = if direction.length_squared() > 0. {
+            direction.y.atan2(direction.x)
+        } else {
+            facing.angle
+        };
+        let turn = angle_between(facing.angle, wanted_angle);
+        let max_turn = profile.turn_rate * elapsed;
+        facing.angle = (facing.angle + turn.clamp(-max_turn, max_turn)).rem_euclid(TAU);
+        let remaining_turn = angle_between(facing.angle, wanted_angle);
+
+        // Units that somehow ended up on impassable ground can still walk off it
+        let terrain_multiplier = match map.speed_multiplier_at((pos.x, pos.y)) {
+            multiplier if multiplier > 0. => multiplier,
+            _ => 1.,
+        };
+        // Slow down while turning, and in time to stop at the destination
+        let distance_left: f32 = direction.length()
+            + route
+                .waypoints
+                .iter()
+                .zip(route.waypoints.iter().skip(1))
+                .map(|(a, b)| ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt())
+                .sum::<f32>();
+        let target_speed = (max_speed * terrain_multiplier * remaining_turn.cos().max(0.))
+            .min((2. * profile.deceleration * distance_left).sqrt());
+        let speed = vel_op
+            .as_ref()
+            .map(|vel| Vector2D::new(vel.dx, vel.dy).length())
+            .unwrap_or(0.);
+        let speed = if target_speed > speed {
+            (speed + profile.acceleration * elapsed).min(target_speed)
+        } else {
+            (speed - profile.deceleration * elapsed).max(target_speed)
+        };
+
+        let mut velocity = Vector2D::new(facing.angle.cos(), facing.angle.sin()) * speed;
+        //we don't want to overshoot the target, or the corners of the route
+        if remaining_turn.abs() < 0.01
+            && direction.length_squared() < (velocity * elapsed).length_squared()
+        {
+            velocity = direction / elapsed;
         }
         match vel_op {
             Some(vel) => {
@@ -38,5 +161,16 @@ pub fn destination_to_velocity(
                 },
             ),
         }
+        if let Some(route) = new_route {
+            command_buffer.add_component(*entity, route);
+        }
+        if let Some(facing) = new_facing {
+            command_buffer.add_component(*entity, facing);
+        }
     });
 }
+
+/// The smallest angle to turn from `from` to face `to`, negative when turning clockwise.
+fn angle_between(from: f32, to: f32) -> f32 {
+    (to - from + PI).rem_euclid(TAU) - PI
+}
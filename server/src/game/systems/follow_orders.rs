@@ -0,0 +1,147 @@
+use legion::{component, system, systems::CommandBuffer, Entity};
+use vector2d::Vector2D;
+
+use crate::game::components::{Destination, Order, Position, Target};
+
+/// Moves units that got where their order sent them on to the next step of the order.
+/// A unit that lost its destination without getting there could not reach it, and gives up the order.
+/// Units that are fighting are left alone, they go back to their order once the fight is over.
+#[system(for_each)]
+#[filter(!component::<Destination>() & !component::<Target>())]
+pub fn follow_orders(
+    order: &mut Order,
+    pos: &Position,
+    command_buffer: &mut CommandBuffer,
+    entity: &Entity,
+) {
+    let arrived = order
+        .destination()
+        .map(|(x, y)| (Vector2D::new(x, y) - Vector2D::new(pos.x, pos.y)).length_squared() < 0.1)
+        .unwrap_or(false);
+    match order {
+        Order::Patrol { towards_b, .. } if arrived => {
+            *towards_b = !*towards_b;
+            if let Some((x, y)) = order.destination() {
+                command_buffer.add_component(*entity, Destination { x, y });
+            }
+        }
+        Order::Move { .. } | Order::AttackMove { .. } | Order::Patrol { .. } => {
+            *order = Order::Idle
+        }
+        Order::Idle | Order::HoldPosition => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{components::UnitType, game_loop::Game};
+
+    fn order(game: &mut Game, id: &str, order: Order, destination: Option<(f32, f32)>) -> Entity {
+        let unit = game.spawn_unit(id, UnitType::Soldier, (10., 10.), 1);
+        let mut entry = game.world.entry(unit).unwrap();
+        entry.add_component(order);
+        if let Some((x, y)) = destination {
+            entry.add_component(Destination { x, y });
+        }
+        unit
+    }
+
+    #[test]
+    fn patrols_turn_around_at_each_end() {
+        let mut game = Game::empty();
+        let a = (10., 10.);
+        let b = (40., 10.);
+        order(
+            &mut game,
+            "patroller",
+            Order::Patrol {
+                a,
+                b,
+                towards_b: false,
+            },
+            None,
+        );
+
+        game.run(follow_orders_system());
+        assert_eq!(
+            game.component::<Order>("patroller"),
+            Some(Order::Patrol {
+                a,
+                b,
+                towards_b: true
+            })
+        );
+        let destination = game.component::<Destination>("patroller").unwrap();
+        assert_eq!((destination.x, destination.y), b);
+    }
+
+    #[test]
+    fn units_that_arrived_or_gave_up_become_idle() {
+        let mut game = Game::empty();
+        order(
+            &mut game,
+            "arrived",
+            Order::Move {
+                position: (10., 10.),
+            },
+            None,
+        );
+        order(
+            &mut game,
+            "unreachable",
+            Order::AttackMove {
+                position: (50., 50.),
+            },
+            None,
+        );
+        order(
+            &mut game,
+            "on the way",
+            Order::Move {
+                position: (50., 50.),
+            },
+            Some((50., 50.)),
+        );
+        order(&mut game, "holding", Order::HoldPosition, None);
+
+        game.run(follow_orders_system());
+        assert_eq!(game.component::<Order>("arrived"), Some(Order::Idle));
+        assert_eq!(game.component::<Order>("unreachable"), Some(Order::Idle));
+        assert_eq!(
+            game.component::<Order>("on the way"),
+            Some(Order::Move {
+                position: (50., 50.)
+            })
+        );
+        assert_eq!(
+            game.component::<Order>("holding"),
+            Some(Order::HoldPosition)
+        );
+    }
+
+    #[test]
+    fn fighting_units_keep_their_order() {
+        let mut game = Game::empty();
+        game.spawn_unit("enemy", UnitType::Soldier, (12., 10.), 2);
+        let unit = order(
+            &mut game,
+            "fighter",
+            Order::AttackMove {
+                position: (50., 50.),
+            },
+            None,
+        );
+        game.world.entry(unit).unwrap().add_component(Target {
+            id: "enemy".to_string(),
+        });
+
+        game.run(follow_orders_system());
+        assert_eq!(
+            game.component::<Order>("fighter"),
+            Some(Order::AttackMove {
+                position: (50., 50.)
+            })
+        );
+    }
+}
@@ -1,48 +1,618 @@
-use legion::{system, systems::CommandBuffer, world::SubWorld, Entity, EntityStore};
+use legion::{system, systems::CommandBuffer, world::SubWorld, Entity, EntityStore, IntoQuery};
 
-use crate::{UidEntityMap, game::{commands::GameCommand, components::{Destination, Position, UnitId}, game_state::Unit}};
+use crate::{UidEntityMap, game::{commands::GameCommand, abilities::check_ability, components::{AbilityCooldowns, BuildingType, Construction, Destination, Footprint, GroupSpeed, Health, HarvestState, Harvester, MAX_QUEUE_LENGTH, MovementProfile, Order, Owner, PlayerId, Position, ProductionQueue, RallyPoint, ResourceNode, Route, Structure, Stun, Target, Team, UnitId, UnitType, Velocity}, placement::check_placement, events::GameEvent, game_state::Unit, resources::{EconomyResource, EventsResource, MapResource, StatsResource, TeamsResource, TimeResource}, spawn::{spawn_building, spawn_unit}}};
+
+/// The buildings placed during a tick, they are only in the world once the command buffer is flushed after it.
+#[derive(Default)]
+pub struct PlacedThisTick {
+    tick: u64,
+    footprints: Vec<(Position, Footprint)>,
+}
 
 #[system(for_each)]
 #[write_component(Destination)]
+#[write_component(Harvester)]
+#[write_component(ProductionQueue)]
+#[write_component(RallyPoint)]
+#[write_component(AbilityCooldowns)]
+#[read_component(Owner)]
+#[read_component(BuildingType)]
+#[read_component(Construction)]
+#[read_component(Footprint)]
+#[read_component(Health)]
+#[read_component(UnitId)]
+#[read_component(MovementProfile)]
+#[read_component(Position)]
+#[read_component(UnitType)]
+#[read_component(Structure)]
+#[read_component(Stun)]
+#[read_component(ResourceNode)]
+#[allow(clippy::too_many_arguments)]
 pub fn handle_commands(
     world: &mut SubWorld,
     game_command: &GameCommand,
     entity: &Entity,
     #[resource] id_map: &mut UidEntityMap,
+    #[resource] teams: &mut TeamsResource,
+    #[resource] economy: &mut EconomyResource,
+    #[resource] events: &mut EventsResource,
+    #[resource] map: &MapResource,
+    #[resource] time: &TimeResource,
+    #[resource] stats: &mut StatsResource,
     command_buffer: &mut CommandBuffer,
+    #[state] placed: &mut PlacedThisTick,
 ) {
+    if placed.tick != time.ticks {
+        placed.tick = time.ticks;
+        placed.footprints.clear();
+    }
+    if let Some(player_id) = command_player(game_command) {
+        stats.player(player_id).commands += 1;
+    }
     match game_command {
-        GameCommand::CreateUnitCommand { unit:Unit{ position, id,..}} => {
-            let new_entity = command_buffer.push((
+        GameCommand::CreateUnitCommand { unit, free } => {
+            let Unit{ id, owner, unit_type, ..} = unit;
+            let definition = unit_type.definition();
+            if let (Some(player_id), false) = (owner, free) {
+                if !economy.try_spend(*player_id, definition.cost) {
+                    events.events.push(GameEvent::CommandRejected {
+                        player_id: *player_id,
+                        message: format!(
+                            "Not enough resources for a {:?}, it costs {} and you have {}",
+                            unit_type,
+                            definition.cost,
+                            economy.balance(*player_id)
+                        ),
+                    });
+                    command_buffer.remove(*entity);
+                    return;
+                }
+            }
+            let team = owner.and_then(|player_id| teams.team_of(player_id));
+            let unit = Unit { team, ..unit.clone() };
+            id_map.insert(id.clone(), spawn_unit(command_buffer, &unit));
+            if let (Some(player_id), false) = (owner, free) {
+                stats.player(*player_id).units_created += 1;
+            }
+            events.events.push(GameEvent::UnitCreated { unit });
+        }
+        GameCommand::SetUnitDestinationCommand { position, uuid, player_id } => {
+            if let Some(entity) = owned_unit(world, id_map, events, uuid, *player_id) {
+                give_order(world, command_buffer, events, entity, *player_id, Order::Move { position: *position });
+            }
+        }
+        GameCommand::StopCommand { uuid, player_id } => {
+            if let Some(entity) = owned_unit(world, id_map, events, uuid, *player_id) {
+                give_order(world, command_buffer, events, entity, *player_id, Order::Idle);
+            }
+        }
+        GameCommand::HoldPositionCommand { uuid, player_id } => {
+            if let Some(entity) = owned_unit(world, id_map, events, uuid, *player_id) {
+                give_order(world, command_buffer, events, entity, *player_id, Order::HoldPosition);
+            }
+        }
+        GameCommand::PatrolCommand { uuid, a, b, player_id } => {
+            if let Some(entity) = owned_unit(world, id_map, events, uuid, *player_id) {
+                give_order(world, command_buffer, events, entity, *player_id, Order::Patrol { a: *a, b: *b, towards_b: false });
+            }
+        }
+        GameCommand::AttackMoveCommand { uuid, position, player_id } => {
+            if let Some(entity) = owned_unit(world, id_map, events, uuid, *player_id) {
+                give_order(world, command_buffer, events, entity, *player_id, Order::AttackMove { position: *position });
+            }
+        }
+        GameCommand::UseAbilityCommand { uuid, ability, position, target_id, player_id } => {
+            let target = target_id.as_ref().and_then(|id| id_map.get(id)).copied();
+            let cast = match id_map.get(uuid) {
+                Some(caster) => check_ability(world, teams, *caster, *ability, *position, target, *player_id, time.ticks)
+                    .map(|cast| (*caster, cast)),
+                None => Err("That unit does not exist".to_string()),
+            };
+            // Units are spawned with their cooldowns, so a second use in the same tick sees the cooldown of the first
+            let cast = cast.and_then(|(caster, cast)| {
+                let mut entry = world.entry_mut(caster).map_err(|_| "That unit does not exist".to_string())?;
+                let cooldowns = entry
+                    .get_component_mut::<AbilityCooldowns>()
+                    .map_err(|_| format!("That unit can't use {:?}", ability))?;
+                cooldowns.ready_at.insert(*ability, time.ticks + ability.definition().cooldown);
+                Ok((caster, cast))
+            });
+            match cast {
+                Ok((caster, cast)) => command_buffer.add_component(caster, cast),
+                Err(message) => events.events.push(GameEvent::CommandRejected { player_id: *player_id, message }),
+            }
+        }
+        GameCommand::MoveGroupCommand { ids, destination, formation, player_id } => {
+            let mut units: Vec<(Entity, (f32, f32), f32)> = Vec::new();
+            for id in ids {
+                // Units of other players are left out and the player is told, the rest of the group still moves
+                let unit = owned_unit(world, id_map, events, id, *player_id).and_then(|unit| {
+                    let entry = world.entry_ref(unit).ok()?;
+                    // Structures can't move, and stunned units ignore orders
+                    if entry.get_component::<Structure>().is_ok() || entry.get_component::<Stun>().is_ok() {
+                        return None;
+                    }
+                    let position = entry.get_component::<Position>().ok()?;
+                    let max_speed = entry
+                        .get_component::<MovementProfile>()
+                        .map(|profile| profile.max_speed)
+                        .unwrap_or_else(|_| MovementProfile::default().max_speed);
+                    Some((unit, (position.x, position.y), max_speed))
+                });
+                if let Some(unit) = unit.filter(|unit| !units.iter().any(|other| other.0 == unit.0)) {
+                    units.push(unit);
+                }
+            }
+            let positions: Vec<(f32, f32)> = units.iter().map(|unit| unit.1).collect();
+            let slots = formation.slots(&positions, *destination);
+            let max_speed = units.iter().map(|unit| unit.2).fold(f32::INFINITY, f32::min);
+            for ((unit, _, _), slot) in units.iter().zip(slots) {
+                // Slots that fall off the map or on impassable ground are moved to the destination itself
+                let slot = Some(map.clamp(slot))
+                    .filter(|slot| map.is_passable(*slot))
+                    .unwrap_or(*destination);
+                give_order(world, command_buffer, events, *unit, *player_id, Order::Move { position: slot });
+                command_buffer.add_component(*unit, GroupSpeed { max_speed });
+            }
+        }
+        GameCommand::JoinTeamCommand { player_id, team_id } => {
+            teams.player_teams.insert(*player_id, *team_id);
+            <(Entity, &Owner)>::query().for_each(world, |(unit, owner)| {
+                if owner.player_id == *player_id {
+                    command_buffer.add_component(*unit, Team { team_id: *team_id });
+                }
+            });
+        }
+        GameCommand::CreateResourceNodeCommand { id, position, amount } => {
+            let node = command_buffer.push((
                 Position {
                     x: position.0,
                     y: position.1,
                 },
-                UnitId { id: id.clone() },
+                ResourceNode {
+                    id: id.clone(),
+                    amount: *amount,
+                },
             ));
-            id_map.insert(id.clone(), new_entity);
-        }
-        GameCommand::SetUnitDestinationCommand { position, uuid } => {
-            if let Some(entity) = id_map.get(uuid) {
-                let entry_result = world.entry_mut(*entity);
-                if let Ok(mut entry) = entry_result {
-                    if let Ok(destination) = entry.get_component_mut::<Destination>() {
-                        destination.x = position.0;
-                        destination.y = position.1;
+            id_map.insert(id.clone(), node);
+        }
+        GameCommand::HarvestCommand { uuid, node_id, player_id } => {
+            let node_exists = id_map
+                .get(node_id)
+                .and_then(|node| world.entry_ref(*node).ok())
+                .map(|node| node.get_component::<ResourceNode>().is_ok())
+                .unwrap_or(false);
+            if !node_exists {
+                events.events.push(GameEvent::CommandRejected { player_id: *player_id, message: "That resource node does not exist".to_string() });
+            } else if let Some(unit) = owned_unit(world, id_map, events, uuid, *player_id) {
+                let mut entry = world.entry_mut(unit).expect("Owned units are in the world");
+                // Stunned units ignore orders, harvesting ones too
+                let stunned = entry.get_component::<Stun>().is_ok();
+                let rejection = match entry.get_component_mut::<Harvester>() {
+                    _ if stunned => Some("Stunned units can't be given orders"),
+                    Ok(harvester) => {
+                        harvester.node = Some(node_id.clone());
+                        harvester.state = HarvestState::MovingToNode;
+                        // Harvesting takes the place of any other order
+                        command_buffer.add_component(unit, Order::Idle);
+                        command_buffer.remove_component::<GroupSpeed>(unit);
+                        None
+                    }
+                    Err(_) => Some("That unit can't harvest"),
+                };
+                if let Some(message) = rejection {
+                    events.events.push(GameEvent::CommandRejected { player_id: *player_id, message: message.to_string() });
+                }
+            }
+        }
+        GameCommand::PlaceBuildingCommand { id, building_type, position, owner } => {
+            let definition = building_type.definition();
+            if let Err(message) = check_placement(world, map, *building_type, *position, &placed.footprints) {
+                events.events.push(GameEvent::CommandRejected { player_id: *owner, message });
+            } else if !economy.try_spend(*owner, definition.cost) {
+                events.events.push(GameEvent::CommandRejected {
+                    player_id: *owner,
+                    message: format!(
+                        "Not enough resources for a {:?}, it costs {} and you have {}",
+                        building_type,
+                        definition.cost,
+                        economy.balance(*owner)
+                    ),
+                });
+            } else {
+                let team = teams.team_of(*owner);
+                let building = spawn_building(command_buffer, id, *building_type, *position, Some(*owner), team, false);
+                id_map.insert(id.clone(), building);
+                let (width, height) = definition.footprint;
+                placed.footprints.push((Position { x: position.0, y: position.1 }, Footprint { width, height }));
+            }
+        }
+        GameCommand::QueueProductionCommand { building_id, unit_type, player_id } => {
+            let rejection = match id_map.get(building_id).and_then(|building| world.entry_mut(*building).ok()) {
+                None => Some("That building does not exist".to_string()),
+                Some(mut entry) => {
+                    let owned = entry.get_component::<Owner>().map(|o| o.player_id == *player_id).unwrap_or(false);
+                    let produces = entry
+                        .get_component::<BuildingType>()
+                        .map(|building_type| building_type.definition().produces.contains(unit_type))
+                        .unwrap_or(false);
+                    let constructed = entry.get_component::<Construction>().is_err();
+                    let cost = unit_type.definition().cost;
+                    match entry.get_component_mut::<ProductionQueue>() {
+                        Ok(production) if owned && produces => {
+                            if !constructed {
+                                Some("The building is still under construction".to_string())
+                            } else if production.queue.len() >= MAX_QUEUE_LENGTH {
+                                Some(format!("The production queue is full, it holds {} units", MAX_QUEUE_LENGTH))
+                            } else if !economy.try_spend(*player_id, cost) {
+                                Some(format!(
+                                    "Not enough resources for a {:?}, it costs {} and you have {}",
+                                    unit_type,
+                                    cost,
+                                    economy.balance(*player_id)
+                                ))
+                            } else {
+                                production.queue.push_back(*unit_type);
+                                None
+                            }
+                        }
+                        _ if !owned => Some("You can only produce units in your own buildings".to_string()),
+                        _ => Some(format!("That building can not produce a {:?}", unit_type)),
+                    }
+                }
+            };
+            if let Some(message) = rejection {
+                events.events.push(GameEvent::CommandRejected { player_id: *player_id, message });
+            }
+        }
+        GameCommand::SetRallyPointCommand { building_id, position, player_id } => {
+            let building = id_map.get(building_id).and_then(|building| Some((*building, world.entry_mut(*building).ok()?)));
+            let rejection = match building {
+                None => Some("That building does not exist"),
+                Some((building, mut entry)) => {
+                    let owned = entry.get_component::<Owner>().map(|o| o.player_id == *player_id).unwrap_or(false);
+                    if !owned {
+                        Some("You can only set the rally point of your own buildings")
+                    } else if entry.get_component::<ProductionQueue>().is_err() {
+                        Some("That building does not produce units")
                     } else {
-                        command_buffer.add_component(
-                            *entity,
-                            Destination {
-                                x: position.0,
-                                y: position.1,
-                            },
-                        )
+                        if let Ok(rally_point) = entry.get_component_mut::<RallyPoint>() {
+                            rally_point.x = position.0;
+                            rally_point.y = position.1;
+                        } else {
+                            command_buffer.add_component(
+                                building,
+                                RallyPoint {
+                                    x: position.0,
+                                    y: position.1,
+                                },
+                            );
+                        }
+                        None
                     }
                 }
+            };
+            if let Some(message) = rejection {
+                events.events.push(GameEvent::CommandRejected { player_id: *player_id, message: message.to_string() });
             }
         }
-        // This command has to be handled in the main game loop, as this system does not have access to wipe the world,
-        GameCommand::ResetGameCommand => {}
+        // These commands have to be handled in the main game loop, as this system does not have access to wipe the world,
+        GameCommand::ResetGameCommand
+        | GameCommand::SaveGameCommand
+        | GameCommand::LoadGameCommand => {}
     };
     command_buffer.remove(*entity);
 }
+
+/// The player that gave a command, `None` for commands that don't come from a player.
+fn command_player(game_command: &GameCommand) -> Option<PlayerId> {
+    match game_command {
+        GameCommand::CreateUnitCommand { unit, free: false } => unit.owner,
+        GameCommand::SetUnitDestinationCommand { player_id, .. }
+        | GameCommand::MoveGroupCommand { player_id, .. }
+        | GameCommand::HarvestCommand { player_id, .. }
+        | GameCommand::StopCommand { player_id, .. }
+        | GameCommand::HoldPositionCommand { player_id, .. }
+        | GameCommand::PatrolCommand { player_id, .. }
+        | GameCommand::AttackMoveCommand { player_id, .. }
+        | GameCommand::UseAbilityCommand { player_id, .. }
+        | GameCommand::QueueProductionCommand { player_id, .. }
+        | GameCommand::SetRallyPointCommand { player_id, .. } => Some(*player_id),
+        GameCommand::PlaceBuildingCommand { owner, .. } => Some(*owner),
+        _ => None,
+    }
+}
+
+/// The unit with `id` if `player_id` owns it, otherwise the player is told why their order was rejected.
+fn owned_unit(world: &SubWorld, id_map: &UidEntityMap, events: &mut EventsResource, id: &String, player_id: PlayerId) -> Option<Entity> {
+    let unit = id_map.get(id).copied();
+    let owner = unit
+        .and_then(|unit| world.entry_ref(unit).ok())
+        .map(|entry| entry.get_component::<Owner>().map(|owner| owner.player_id).ok());
+    let message = match owner {
+        None => "That unit does not exist",
+        Some(Some(owner)) if owner == player_id => return unit,
+        Some(_) => "You can only give orders to your own units",
+    };
+    events.events.push(GameEvent::CommandRejected { player_id, message: message.to_string() });
+    None
+}
+
+/// Gives a unit a new order in place of the one it had, and points it at where the order sends it.
+/// Structures can't be given orders, and stunned units ignore them, the player is told so.
+fn give_order(world: &mut SubWorld, command_buffer: &mut CommandBuffer, events: &mut EventsResource, entity: Entity, player_id: PlayerId, order: Order) {
+    let mut entry = match world.entry_mut(entity) {
+        Ok(entry) => entry,
+        Err(_) => return,
+    };
+    let rejection = if entry.get_component::<Structure>().is_ok() {
+        Some("Buildings can't be given orders")
+    } else if entry.get_component::<Stun>().is_ok() {
+        Some("Stunned units can't be given orders")
+    } else {
+        None
+    };
+    if let Some(message) = rejection {
+        events.events.push(GameEvent::CommandRejected { player_id, message: message.to_string() });
+        return;
+    }
+    // A player ordering a harvester around takes it off its harvesting job
+    if let Ok(harvester) = entry.get_component_mut::<Harvester>() {
+        harvester.state = HarvestState::Idle;
+    }
+    match order.destination() {
+        Some((x, y)) => {
+            if let Ok(destination) = entry.get_component_mut::<Destination>() {
+                destination.x = x;
+                destination.y = y;
+            } else {
+                command_buffer.add_component(entity, Destination { x, y })
+            }
+        }
+        None => {
+            command_buffer.remove_component::<Destination>(entity);
+            command_buffer.remove_component::<Velocity>(entity);
+            command_buffer.remove_component::<Route>(entity);
+        }
+    }
+    // The unit leaves any group it was moving with, and any fight it was in
+    command_buffer.remove_component::<GroupSpeed>(entity);
+    command_buffer.remove_component::<Target>(entity);
+    command_buffer.add_component(entity, order);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{components::{AbilityType, CastAbility}, formation::Formation, game_loop::Game, resources::STARTING_RESOURCES};
+
+    fn harvest(uuid: &str, player_id: PlayerId) -> GameCommand {
+        GameCommand::HarvestCommand { uuid: uuid.to_string(), node_id: "node".to_string(), player_id }
+    }
+
+    fn stun(game: &mut Game, unit: Entity) {
+        game.world.entry(unit).unwrap().add_component(Stun { ticks_left: 2 });
+    }
+
+    #[test]
+    fn sends_harvesters_to_a_node() {
+        let mut game = Game::empty();
+        game.handle(vec![GameCommand::CreateResourceNodeCommand { id: "node".to_string(), position: (20., 20.), amount: 100 }]);
+        game.spawn_unit("worker", UnitType::Worker, (10., 10.), 1);
+
+        game.handle(vec![harvest("worker", 1)]);
+        assert!(game.rejections().is_empty());
+        let harvester = game.component::<Harvester>("worker").unwrap();
+        assert_eq!(harvester.node.as_deref(), Some("node"));
+        assert_eq!(harvester.state, HarvestState::MovingToNode);
+    }
+
+    #[test]
+    fn rejects_harvesting_by_units_that_cant() {
+        let mut game = Game::empty();
+        game.handle(vec![GameCommand::CreateResourceNodeCommand { id: "node".to_string(), position: (20., 20.), amount: 100 }]);
+        game.spawn_unit("soldier", UnitType::Soldier, (10., 10.), 1);
+        let stunned = game.spawn_unit("stunned", UnitType::Worker, (10., 10.), 1);
+        stun(&mut game, stunned);
+        game.spawn_unit("enemy", UnitType::Worker, (10., 10.), 2);
+
+        game.handle(vec![harvest("soldier", 1), harvest("stunned", 1), harvest("enemy", 1), harvest("missing", 1)]);
+        assert_eq!(game.rejections(), vec![
+            "That unit can't harvest",
+            "Stunned units can't be given orders",
+            "You can only give orders to your own units",
+            "That unit does not exist",
+        ]);
+        assert_eq!(game.component::<Harvester>("stunned").unwrap().state, HarvestState::Idle);
+
+        game.handle(vec![GameCommand::HarvestCommand { uuid: "soldier".to_string(), node_id: "missing".to_string(), player_id: 1 }]);
+        assert_eq!(game.rejections(), vec!["That resource node does not exist"]);
+    }
+
+    fn queue(building_id: &str, unit_type: UnitType) -> GameCommand {
+        GameCommand::QueueProductionCommand { building_id: building_id.to_string(), unit_type, player_id: 1 }
+    }
+
+    fn rally(building_id: &str, position: (f32, f32)) -> GameCommand {
+        GameCommand::SetRallyPointCommand { building_id: building_id.to_string(), position, player_id: 1 }
+    }
+
+    #[test]
+    fn queues_production_the_player_can_pay_for() {
+        let mut game = Game::empty();
+        game.spawn_building("barracks", BuildingType::Barracks, (20., 20.), 1);
+
+        // Players start with 500, soldiers cost 100
+        game.handle((0..6).map(|_| queue("barracks", UnitType::Soldier)).collect());
+        assert_eq!(game.rejections(), vec![format!("The production queue is full, it holds {} units", MAX_QUEUE_LENGTH)]);
+        assert_eq!(game.component::<ProductionQueue>("barracks").unwrap().queue.len(), MAX_QUEUE_LENGTH);
+        assert_eq!(game.resources.get::<EconomyResource>().unwrap().balance(1), 0);
+
+        game.spawn_building("other", BuildingType::Barracks, (40., 20.), 1);
+        game.handle(vec![queue("other", UnitType::Soldier)]);
+        assert_eq!(game.rejections(), vec!["Not enough resources for a Soldier, it costs 100 and you have 0"]);
+    }
+
+    #[test]
+    fn rejects_production_buildings_cant_do() {
+        let mut game = Game::empty();
+        game.spawn_building("barracks", BuildingType::Barracks, (20., 20.), 1);
+        game.spawn_building("enemy", BuildingType::Barracks, (40., 20.), 2);
+        let building = game.spawn_building("unfinished", BuildingType::Barracks, (60., 20.), 1);
+        game.world.entry(building).unwrap().add_component(Construction::default());
+
+        game.handle(vec![
+            queue("barracks", UnitType::Worker),
+            queue("enemy", UnitType::Soldier),
+            queue("unfinished", UnitType::Soldier),
+            queue("missing", UnitType::Soldier),
+        ]);
+        assert_eq!(game.rejections(), vec![
+            "That building can not produce a Worker",
+            "You can only produce units in your own buildings",
+            "The building is still under construction",
+            "That building does not exist",
+        ]);
+        assert_eq!(game.resources.get::<EconomyResource>().unwrap().balance(1), STARTING_RESOURCES);
+    }
+
+    #[test]
+    fn sets_rally_points_of_own_buildings() {
+        let mut game = Game::empty();
+        game.spawn_building("barracks", BuildingType::Barracks, (20., 20.), 1);
+        game.spawn_building("enemy", BuildingType::Barracks, (40., 20.), 2);
+        game.spawn_unit("worker", UnitType::Worker, (10., 10.), 1);
+
+        game.handle(vec![rally("barracks", (30., 30.))]);
+        game.handle(vec![rally("barracks", (35., 30.)), rally("enemy", (30., 30.)), rally("worker", (30., 30.)), rally("missing", (30., 30.))]);
+        assert_eq!(game.rejections(), vec![
+            "You can only set the rally point of your own buildings",
+            "That building does not produce units",
+            "That building does not exist",
+        ]);
+        let rally_point = game.component::<RallyPoint>("barracks").unwrap();
+        assert_eq!((rally_point.x, rally_point.y), (35., 30.));
+        assert!(game.component::<RallyPoint>("enemy").is_none());
+    }
+
+    fn place(id: &str, position: (f32, f32)) -> GameCommand {
+        GameCommand::PlaceBuildingCommand { id: id.to_string(), building_type: BuildingType::Barracks, position, owner: 1 }
+    }
+
+    #[test]
+    fn rejects_buildings_overlapping_ones_placed_the_same_tick() {
+        let mut game = Game::empty();
+
+        // Barracks are 4 meters wide
+        game.handle(vec![place("first", (20., 20.)), place("second", (23., 20.)), place("third", (24., 20.))]);
+        assert_eq!(game.rejections(), vec!["A Barracks there would overlap another building"]);
+        assert!(game.component::<Structure>("first").is_some());
+        assert!(game.component::<Structure>("second").is_none());
+        assert!(game.component::<Structure>("third").is_some());
+        assert_eq!(game.resources.get::<EconomyResource>().unwrap().balance(1), STARTING_RESOURCES - 400);
+
+        game.handle(vec![place("fourth", (21., 20.))]);
+        assert_eq!(game.rejections(), vec!["A Barracks there would overlap another building"]);
+    }
+
+    #[test]
+    fn moves_the_players_units_of_a_group_at_the_speed_of_the_slowest() {
+        let mut game = Game::empty();
+        game.spawn_unit("worker", UnitType::Worker, (10., 10.), 1);
+        game.spawn_unit("soldier", UnitType::Soldier, (12., 10.), 1);
+        game.spawn_unit("enemy", UnitType::Soldier, (14., 10.), 2);
+        game.spawn_building("barracks", BuildingType::Barracks, (20., 20.), 1);
+
+        let ids = ["worker", "enemy", "soldier", "missing", "barracks"].iter().map(|id| id.to_string()).collect();
+        game.handle(vec![GameCommand::MoveGroupCommand { ids, destination: (40., 40.), formation: Formation::Line, player_id: 1 }]);
+        assert_eq!(game.rejections(), vec!["You can only give orders to your own units", "That unit does not exist"]);
+        let slot = |game: &Game, id| match game.component::<Order>(id) {
+            Some(Order::Move { position }) => position,
+            order => panic!("{} has order {:?}", id, order),
+        };
+        assert!(slot(&game, "worker") != slot(&game, "soldier"));
+        assert_eq!(game.component::<GroupSpeed>("worker").unwrap().max_speed, UnitType::Soldier.definition().movement.max_speed);
+        assert!(game.component::<Order>("enemy").is_none());
+        assert!(game.component::<Order>("barracks").is_none());
+    }
+
+    #[test]
+    fn gives_orders_to_the_players_units() {
+        let mut game = Game::empty();
+        game.spawn_unit("mover", UnitType::Soldier, (10., 10.), 1);
+        game.spawn_unit("patroller", UnitType::Soldier, (12., 10.), 1);
+
+        game.handle(vec![
+            GameCommand::SetUnitDestinationCommand { position: (30., 30.), uuid: "mover".to_string(), player_id: 1 },
+            GameCommand::PatrolCommand { uuid: "patroller".to_string(), a: (20., 10.), b: (40., 10.), player_id: 1 },
+        ]);
+        assert!(game.rejections().is_empty());
+        assert_eq!(game.component::<Order>("mover"), Some(Order::Move { position: (30., 30.) }));
+        let destination = game.component::<Destination>("patroller").unwrap();
+        assert_eq!((destination.x, destination.y), (20., 10.));
+
+        game.handle(vec![GameCommand::StopCommand { uuid: "mover".to_string(), player_id: 1 }]);
+        assert_eq!(game.component::<Order>("mover"), Some(Order::Idle));
+        assert!(game.component::<Destination>("mover").is_none());
+    }
+
+    #[test]
+    fn rejects_orders_to_buildings_stunned_and_other_players_units() {
+        let mut game = Game::empty();
+        let stunned = game.spawn_unit("stunned", UnitType::Soldier, (10., 10.), 1);
+        stun(&mut game, stunned);
+        game.spawn_unit("enemy", UnitType::Soldier, (12., 10.), 2);
+        game.spawn_building("barracks", BuildingType::Barracks, (20., 20.), 1);
+
+        let hold = |uuid: &str| GameCommand::HoldPositionCommand { uuid: uuid.to_string(), player_id: 1 };
+        game.handle(vec![hold("stunned"), hold("enemy"), hold("barracks"), hold("missing")]);
+        assert_eq!(game.rejections(), vec![
+            "Stunned units can't be given orders",
+            "You can only give orders to your own units",
+            "Buildings can't be given orders",
+            "That unit does not exist",
+        ]);
+        assert!(game.component::<Order>("stunned").is_none());
+        assert!(game.component::<Order>("enemy").is_none());
+    }
+
+    fn grenade(uuid: &str) -> GameCommand {
+        GameCommand::UseAbilityCommand { uuid: uuid.to_string(), ability: AbilityType::Grenade, position: Some((15., 10.)), target_id: None, player_id: 1 }
+    }
+
+    #[test]
+    fn abilities_cool_down_after_use() {
+        let mut game = Game::empty();
+        game.spawn_unit("soldier", UnitType::Soldier, (10., 10.), 1);
+        let cooldown = AbilityType::Grenade.definition().cooldown;
+
+        game.handle(vec![grenade("soldier"), grenade("soldier")]);
+        assert_eq!(game.rejections(), vec![format!("Grenade is ready again in {} ticks", cooldown)]);
+        assert!(game.component::<CastAbility>("soldier").is_some());
+        let cooldowns = game.component::<AbilityCooldowns>("soldier").unwrap();
+        assert_eq!(cooldowns.ticks_left(AbilityType::Grenade, game.ticks()), cooldown - 1);
+
+        for _ in 1..cooldown {
+            game.handle(vec![grenade("soldier")]);
+        }
+        assert_eq!(game.rejections().len() as u64, cooldown - 1);
+        game.handle(vec![grenade("soldier")]);
+        assert!(game.rejections().is_empty());
+    }
+
+    fn create(id: &str, free: bool) -> GameCommand {
+        let unit = Unit { id: id.to_string(), owner: Some(1), position: (10., 10.), destination: (10., 10.), ..Default::default() };
+        GameCommand::CreateUnitCommand { unit, free }
+    }
+
+    #[test]
+    fn counts_the_commands_and_units_of_players() {
+        let mut game = Game::empty();
+
+        game.handle(vec![create("paid", false), create("free", true), GameCommand::StopCommand { uuid: "missing".to_string(), player_id: 1 }]);
+        let mut stats = game.resources.get_mut::<StatsResource>().unwrap();
+        // Units the map or a script gives a player are not counted as theirs to have made
+        let stats = stats.player(1);
+        assert_eq!((stats.commands, stats.units_created), (2, 1));
+    }
+}
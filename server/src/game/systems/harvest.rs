@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+
+use legion::{component, system, systems::CommandBuffer, world::SubWorld, Entity, IntoQuery};
+use vector2d::Vector2D;
+
+use crate::{
+    game::{
+        components::{
+            Construction, Destination, DropOff, HarvestState, Harvester, Owner, PlayerId, Position,
+            ResourceNode, Stun,
+        },
+        resources::{EconomyResource, StatsResource, TimeResource},
+    },
+    UidEntityMap,
+};
+
+/// How close in meters a harvester has to be to a resource node or drop-off to use it.
+const INTERACTION_RANGE: f32 = 1.5;
+
+#[system]
+#[write_component(Harvester)]
+#[write_component(ResourceNode)]
+#[read_component(Position)]
+#[read_component(Destination)]
+#[read_component(DropOff)]
+#[read_component(Construction)]
+#[read_component(Owner)]
+#[read_component(Stun)]
+pub fn harvest(
+    world: &mut SubWorld,
+    command_buffer: &mut CommandBuffer,
+    #[resource] time: &TimeResource,
+    #[resource] economy: &mut EconomyResource,
+    #[resource] stats: &mut StatsResource,
+    #[resource] id_map: &mut UidEntityMap,
+) {
+    let nodes: HashMap<String, (Vector2D<f32>, u32)> = <(&ResourceNode, &Position)>::query()
+        .iter(world)
+        .map(|(node, pos)| (node.id.clone(), (Vector2D::new(pos.x, pos.y), node.amount)))
+        .collect();
+    let drop_offs: Vec<(PlayerId, Vector2D<f32>)> = <(&DropOff, &Owner, &Position)>::query()
+        .filter(!component::<Construction>())
+        .iter(world)
+        .map(|(_, owner, pos)| (owner.player_id, Vector2D::new(pos.x, pos.y)))
+        .collect();
+    // Resources taken from each node this tick
+    let mut gathered: HashMap<String, u32> = HashMap::new();
+
+    let in_range = |a: Vector2D<f32>, b: Vector2D<f32>| {
+        (a - b).length_squared() <= INTERACTION_RANGE * INTERACTION_RANGE
+    };
+    let mut move_to = |entity: Entity, destination: Option<&Destination>, target: Vector2D<f32>| {
+        let already_going = destination
+            .map(|des| des.x == target.x && des.y == target.y)
+            .unwrap_or(false);
+        if !already_going {
+            command_buffer.add_component(
+                entity,
+                Destination {
+                    x: target.x,
+                    y: target.y,
+                },
+            );
+        }
+    };
+
+    <(
+        Entity,
+        &mut Harvester,
+        &Position,
+        &Owner,
+        Option<&Destination>,
+    )>::query()
+    // Stunned harvesters stop where they are until the stun wears off
+    .filter(!component::<Stun>())
+    .for_each_mut(world, |(entity, harvester, pos, owner, destination)| {
+        let pos = Vector2D::new(pos.x, pos.y);
+        match harvester.state {
+            HarvestState::Idle => {}
+            HarvestState::MovingToNode | HarvestState::Gathering => {
+                let node = harvester
+                    .node
+                    .as_ref()
+                    .and_then(|id| nodes.get(id).map(|node| (id.clone(), *node)));
+                let (node_id, (node_pos, amount)) = match node {
+                    Some(node) => node,
+                    // The node ran out, or was never there
+                    None => {
+                        harvester.state = if harvester.cargo > 0 {
+                            HarvestState::Returning
+                        } else {
+                            HarvestState::Idle
+                        };
+                        return;
+                    }
+                };
+                if !in_range(pos, node_pos) {
+                    harvester.state = HarvestState::MovingToNode;
+                    move_to(*entity, destination, node_pos);
+                    return;
+                }
+
+                harvester.state = HarvestState::Gathering;
+                let taken = gathered.entry(node_id).or_insert(0);
+                let remaining = amount - *taken;
+                harvester.progress += harvester.gather_rate * time.elapsed_seconds as f32;
+                let gathered_now = (harvester.progress as u32)
+                    .min(harvester.capacity - harvester.cargo)
+                    .min(remaining);
+                harvester.progress -= gathered_now as f32;
+                harvester.cargo += gathered_now;
+                *taken += gathered_now;
+                if harvester.cargo >= harvester.capacity || remaining == gathered_now {
+                    harvester.state = HarvestState::Returning;
+                    harvester.progress = 0.;
+                }
+            }
+            HarvestState::Returning => {
+                if harvester.cargo == 0 {
+                    harvester.state = HarvestState::MovingToNode;
+                    return;
+                }
+                let closest = drop_offs
+                    .iter()
+                    .filter(|(player_id, _)| *player_id == owner.player_id)
+                    .map(|(_, drop_off)| *drop_off)
+                    .min_by(|a, b| {
+                        (*a - pos)
+                            .length_squared()
+                            .partial_cmp(&(*b - pos).length_squared())
+                            .expect("Positions should not be NaN")
+                    });
+                // Without a drop-off the harvester waits with its cargo until one is built
+                if let Some(drop_off) = closest {
+                    if in_range(pos, drop_off) {
+                        economy.deposit(owner.player_id, harvester.cargo);
+                        stats.player(owner.player_id).resources_gathered += harvester.cargo;
+                        harvester.cargo = 0;
+                        harvester.state = HarvestState::MovingToNode;
+                    } else {
+                        move_to(*entity, destination, drop_off);
+                    }
+                }
+            }
+        }
+    });
+
+    <(Entity, &mut ResourceNode)>::query().for_each_mut(world, |(entity, node)| {
+        if let Some(taken) = gathered.get(&node.id) {
+            node.amount -= taken;
+            if node.amount == 0 {
+                command_buffer.remove(*entity);
+                id_map.remove(&node.id);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        commands::GameCommand,
+        components::{BuildingType, UnitType},
+        game_loop::Game,
+    };
+
+    fn add_node(game: &mut Game, id: &str, position: (f32, f32), amount: u32) {
+        game.handle(vec![GameCommand::CreateResourceNodeCommand {
+            id: id.to_string(),
+            position,
+            amount,
+        }]);
+    }
+
+    fn send_to(game: &mut Game, worker: Entity, node: &str, state: HarvestState, cargo: u32) {
+        let mut entry = game.world.entry(worker).unwrap();
+        let harvester = entry.get_component_mut::<Harvester>().unwrap();
+        harvester.node = Some(node.to_string());
+        harvester.state = state;
+        harvester.cargo = cargo;
+    }
+
+    #[test]
+    fn gathers_until_full_then_returns_to_the_closest_drop_off() {
+        let mut game = Game::empty();
+        add_node(&mut game, "node", (10., 10.), 100);
+        game.spawn_building("near", BuildingType::Headquarters, (30., 10.), 1);
+        game.spawn_building("far", BuildingType::Headquarters, (100., 100.), 1);
+        game.spawn_building("enemy", BuildingType::Headquarters, (20., 10.), 2);
+        let worker = game.spawn_unit("worker", UnitType::Worker, (10., 10.), 1);
+        send_to(&mut game, worker, "node", HarvestState::MovingToNode, 0);
+
+        // Workers gather 2 a second and carry 10
+        for _ in 0..4 {
+            game.run(harvest_system());
+        }
+        let harvester = game.component::<Harvester>("worker").unwrap();
+        assert_eq!(
+            (harvester.state, harvester.cargo),
+            (HarvestState::Gathering, 8)
+        );
+        game.run(harvest_system());
+        let harvester = game.component::<Harvester>("worker").unwrap();
+        assert_eq!(
+            (harvester.state, harvester.cargo),
+            (HarvestState::Returning, 10)
+        );
+        assert_eq!(game.component::<ResourceNode>("node").unwrap().amount, 90);
+
+        game.run(harvest_system());
+        let destination = game.component::<Destination>("worker").unwrap();
+        assert_eq!((destination.x, destination.y), (30., 10.));
+    }
+
+    #[test]
+    fn deposits_cargo_at_a_drop_off() {
+        let mut game = Game::empty();
+        add_node(&mut game, "node", (10., 10.), 100);
+        game.spawn_building("headquarters", BuildingType::Headquarters, (30., 10.), 1);
+        let worker = game.spawn_unit("worker", UnitType::Worker, (30., 10.), 1);
+        send_to(&mut game, worker, "node", HarvestState::Returning, 10);
+        let before = game.resources.get::<EconomyResource>().unwrap().balance(1);
+
+        game.run(harvest_system());
+        let harvester = game.component::<Harvester>("worker").unwrap();
+        assert_eq!(
+            (harvester.state, harvester.cargo),
+            (HarvestState::MovingToNode, 0)
+        );
+        let economy = game.resources.get::<EconomyResource>().unwrap();
+        assert_eq!(economy.balance(1), before + 10);
+        let mut stats = game.resources.get_mut::<StatsResource>().unwrap();
+        assert_eq!(stats.player(1).resources_gathered, 10);
+    }
+
+    #[test]
+    fn waits_with_its_cargo_without_a_drop_off() {
+        let mut game = Game::empty();
+        add_node(&mut game, "node", (10., 10.), 100);
+        game.spawn_building("enemy", BuildingType::Headquarters, (30., 10.), 2);
+        let worker = game.spawn_unit("worker", UnitType::Worker, (10., 10.), 1);
+        send_to(&mut game, worker, "node", HarvestState::Returning, 10);
+
+        game.run(harvest_system());
+        let harvester = game.component::<Harvester>("worker").unwrap();
+        assert_eq!(
+            (harvester.state, harvester.cargo),
+            (HarvestState::Returning, 10)
+        );
+        assert!(game.component::<Destination>("worker").is_none());
+    }
+
+    #[test]
+    fn removes_nodes_once_they_run_out() {
+        let mut game = Game::empty();
+        add_node(&mut game, "node", (10., 10.), 3);
+        let first = game.spawn_unit("first", UnitType::Worker, (10., 10.), 1);
+        let second = game.spawn_unit("second", UnitType::Worker, (10.5, 10.), 1);
+        send_to(&mut game, first, "node", HarvestState::Gathering, 0);
+        send_to(&mut game, second, "node", HarvestState::Gathering, 0);
+
+        game.run(harvest_system());
+        let cargo = |game: &Game, id| game.component::<Harvester>(id).unwrap().cargo;
+        // The node only has 3 left, so the workers can't take 2 each
+        assert_eq!(cargo(&game, "first") + cargo(&game, "second"), 3);
+        assert!(game.component::<ResourceNode>("node").is_none());
+        assert!(!game
+            .resources
+            .get::<UidEntityMap>()
+            .unwrap()
+            .contains_key("node"));
+
+        game.run(harvest_system());
+        let state = game.component::<Harvester>("first").unwrap().state;
+        assert_eq!(state, HarvestState::Returning);
+    }
+
+    #[test]
+    fn stunned_harvesters_stop_gathering() {
+        let mut game = Game::empty();
+        add_node(&mut game, "node", (10., 10.), 100);
+        let worker = game.spawn_unit("worker", UnitType::Worker, (10., 10.), 1);
+        send_to(&mut game, worker, "node", HarvestState::Gathering, 0);
+        game.world
+            .entry(worker)
+            .unwrap()
+            .add_component(Stun { ticks_left: 2 });
+
+        game.run(harvest_system());
+        assert_eq!(game.component::<Harvester>("worker").unwrap().cargo, 0);
+        assert_eq!(game.component::<ResourceNode>("node").unwrap().amount, 100);
+    }
+}
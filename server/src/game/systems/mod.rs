@@ -8,4 +8,40 @@ pub mod destination_to_velocity;
 pub use destination_to_velocity::*;
 
 pub mod remove_destination_on_arrival;
-pub use remove_destination_on_arrival::*;
\ No newline at end of file
+pub use remove_destination_on_arrival::*;
+
+pub mod compute_visibility;
+pub use compute_visibility::*;
+
+pub mod harvest;
+pub use harvest::*;
+
+pub mod produce_units;
+pub use produce_units::*;
+
+pub mod construct_buildings;
+pub use construct_buildings::*;
+
+pub mod follow_orders;
+pub use follow_orders::*;
+
+pub mod attack;
+pub use attack::*;
+
+pub mod remove_dead;
+pub use remove_dead::*;
+
+pub mod move_projectiles;
+pub use move_projectiles::*;
+
+pub mod projectile_impact;
+pub use projectile_impact::*;
+
+pub mod cast_abilities;
+pub use cast_abilities::*;
+
+pub mod tick_status_effects;
+pub use tick_status_effects::*;
+
+pub mod check_victory;
+pub use check_victory::*;
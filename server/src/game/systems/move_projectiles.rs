@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use legion::{system, world::SubWorld, EntityStore, IntoQuery};
+use vector2d::Vector2D;
+
+use crate::{
+    game::{
+        components::{Position, Projectile, Velocity},
+        resources::{TimeResource, TICK_DURATION},
+    },
+    UidEntityMap,
+};
+
+/// Points projectiles at where they hit, homing projectiles follow their target for as long as it exists.
+#[system]
+#[read_component(Position)]
+#[write_component(Projectile)]
+#[write_component(Velocity)]
+pub fn move_projectiles(
+    world: &mut SubWorld,
+    #[resource] id_map: &UidEntityMap,
+    #[resource] time: &TimeResource,
+) {
+    let targets: HashMap<String, (f32, f32)> = <&Projectile>::query()
+        .iter(world)
+        .filter(|projectile| projectile.stats.homing)
+        .filter_map(|projectile| {
+            let entry = world.entry_ref(*id_map.get(&projectile.target)?).ok()?;
+            let pos = entry.get_component::<Position>().ok()?;
+            Some((projectile.target.clone(), (pos.x, pos.y)))
+        })
+        .collect();
+
+    // No time has passed on the first tick, projectiles are aimed as if a whole tick had
+    let elapsed = if time.elapsed_seconds > 0. {
+        time.elapsed_seconds as f32
+    } else {
+        TICK_DURATION.as_secs_f32()
+    };
+    <(&Position, &mut Velocity, &mut Projectile)>::query().for_each_mut(
+        world,
+        |(pos, vel, projectile)| {
+            if let (true, Some(target)) = (projectile.stats.homing, targets.get(&projectile.target))
+            {
+                projectile.impact = *target;
+            }
+            let direction = Vector2D::new(projectile.impact.0 - pos.x, projectile.impact.1 - pos.y);
+            // Land right on the impact point instead of flying past it
+            let velocity = if direction.length() <= projectile.stats.speed * elapsed {
+                direction / elapsed
+            } else {
+                direction.normalise() * projectile.stats.speed
+            };
+            vel.dx = velocity.x;
+            vel.dy = velocity.y;
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use legion::Entity;
+
+    use super::*;
+    use crate::game::{
+        components::{ProjectileStats, UnitType},
+        game_loop::Game,
+        systems::velocity_to_position_system,
+    };
+
+    fn fire(
+        game: &mut Game,
+        from: (f32, f32),
+        target: &str,
+        impact: (f32, f32),
+        homing: bool,
+    ) -> Entity {
+        let projectile = Projectile {
+            id: "shot".to_string(),
+            attacker: "attacker".to_string(),
+            owner: 1,
+            target: target.to_string(),
+            damage: 10.,
+            stats: ProjectileStats {
+                speed: 25.,
+                homing,
+                splash_radius: 3.,
+            },
+            impact,
+        };
+        game.world.push((
+            Position {
+                x: from.0,
+                y: from.1,
+            },
+            Velocity { dx: 0., dy: 0. },
+            projectile,
+        ))
+    }
+
+    fn velocity(game: &Game, projectile: Entity) -> (f32, f32) {
+        let entry = game.world.entry_ref(projectile).unwrap();
+        let velocity = entry.get_component::<Velocity>().unwrap();
+        (velocity.dx, velocity.dy)
+    }
+
+    #[test]
+    fn projectiles_fired_on_the_first_tick_have_a_velocity() {
+        let mut game = Game::empty();
+        game.resources
+            .get_mut::<TimeResource>()
+            .unwrap()
+            .elapsed_seconds = 0.;
+        let projectile = fire(&mut game, (10., 10.), "target", (20., 10.), false);
+
+        game.run(move_projectiles_system());
+        assert_eq!(velocity(&game, projectile), (10., 0.));
+    }
+
+    #[test]
+    fn projectiles_fly_at_their_speed_and_land_on_the_impact_point() {
+        let mut game = Game::empty();
+        let projectile = fire(&mut game, (0., 10.), "target", (40., 10.), false);
+
+        game.run(move_projectiles_system());
+        assert_eq!(velocity(&game, projectile), (25., 0.));
+        game.run(velocity_to_position_system());
+        game.run(move_projectiles_system());
+        assert_eq!(velocity(&game, projectile), (15., 0.));
+        game.run(velocity_to_position_system());
+        let entry = game.world.entry_ref(projectile).unwrap();
+        assert_eq!(entry.get_component::<Position>().unwrap().x, 40.);
+    }
+
+    #[test]
+    fn homing_projectiles_follow_their_target() {
+        let mut game = Game::empty();
+        game.spawn_unit("target", UnitType::Soldier, (10., 30.), 2);
+        let projectile = fire(&mut game, (10., 10.), "target", (10., 20.), true);
+
+        game.run(move_projectiles_system());
+        assert_eq!(velocity(&game, projectile), (0., 20.));
+        let entry = game.world.entry_ref(projectile).unwrap();
+        assert_eq!(
+            entry.get_component::<Projectile>().unwrap().impact,
+            (10., 30.)
+        );
+    }
+}
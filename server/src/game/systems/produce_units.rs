@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use legion::{component, system, systems::CommandBuffer};
+
+use crate::{
+    game::{
+        components::{Construction, Destination, Footprint, Owner, Position, ProductionQueue, RallyPoint, UnitId},
+        events::GameEvent,
+        game_state::Unit,
+        resources::{EventsResource, MapResource, StatsResource, TeamsResource, TimeResource},
+        spawn::spawn_unit,
+    },
+    UidEntityMap,
+};
+
+/// Space in meters between a building and the units it produces, and between those units.
+const SPAWN_SPACING: f32 = 1.;
+
+/// Advances the first unit in every production queue, and spawns it next to the building once it is done.
+/// Buildings under construction don't produce anything.
+#[system(for_each)]
+#[filter(!component::<Construction>())]
+#[allow(clippy::too_many_arguments)]
+pub fn produce_units(
+    production: &mut ProductionQueue,
+    id: &UnitId,
+    pos: &Position,
+    footprint: &Footprint,
+    owner: &Owner,
+    rally_point: Option<&RallyPoint>,
+    #[resource] time: &TimeResource,
+    #[resource] teams: &TeamsResource,
+    #[resource] map: &MapResource,
+    #[resource] id_map: &mut UidEntityMap,
+    #[resource] events: &mut EventsResource,
+    #[resource] stats: &mut StatsResource,
+    command_buffer: &mut CommandBuffer,
+) {
+    let unit_type = match production.queue.front() {
+        Some(unit_type) => *unit_type,
+        None => return,
+    };
+    production.progress += time.elapsed_seconds as f32;
+    if production.progress < unit_type.definition().build_time {
+        return;
+    }
+    production.queue.pop_front();
+    production.progress = 0.;
+
+    // Units come out of the right side of the building, spread out so they don't stack up,
+    // or as close to it as they can stand when it is against the edge of the map, water or a cliff
+    let row = (production.produced % 5) as f32 - 2.;
+    let position = map
+        .nearest_passable((
+            pos.x + footprint.width / 2. + SPAWN_SPACING,
+            pos.y + row * SPAWN_SPACING,
+        ))
+        .unwrap_or((pos.x, pos.y));
+    // Derived from the building, so a replay gives produced units the same ids as the match did
+    let unit_id = format!("{}-{}", id.id, production.produced);
+    production.produced += 1;
+
+    let unit = Unit {
+        position,
+        destination: rally_point.map(|r| (r.x, r.y)).unwrap_or(position),
+        id: unit_id.clone(),
+        owner: Some(owner.player_id),
+        team: teams.team_of(owner.player_id),
+        unit_type,
+        facing: 0.,
+        health: unit_type.definition().max_health,
+        cooldowns: HashMap::new(),
+        status_effects: Vec::new(),
+    };
+    let entity = spawn_unit(command_buffer, &unit);
+    if let Some(rally_point) = rally_point {
+        command_buffer.add_component(
+            entity,
+            Destination {
+                x: rally_point.x,
+                y: rally_point.y,
+            },
+        );
+    }
+    id_map.insert(unit_id, entity);
+    stats.player(owner.player_id).units_created += 1;
+    events.events.push(GameEvent::UnitCreated { unit });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{commands::GameCommand, components::{BuildingType, UnitType}, game_loop::Game};
+
+    fn queue(game: &mut Game, building_id: &str, unit_type: UnitType, count: usize) {
+        let command = GameCommand::QueueProductionCommand { building_id: building_id.to_string(), unit_type, player_id: 1 };
+        game.handle(vec![command; count]);
+    }
+
+    #[test]
+    fn produces_the_queue_in_order_next_to_the_building() {
+        let mut game = Game::empty();
+        game.spawn_building("headquarters", BuildingType::Headquarters, (20., 20.), 1);
+        queue(&mut game, "headquarters", UnitType::Worker, 2);
+
+        // Workers take 5 seconds
+        for _ in 0..4 {
+            game.run(produce_units_system());
+        }
+        assert!(game.component::<UnitId>("headquarters-0").is_none());
+        assert_eq!(game.component::<ProductionQueue>("headquarters").unwrap().progress, 4.);
+        game.run(produce_units_system());
+        let first = game.component::<Position>("headquarters-0").unwrap();
+        // Right of the 6 meter wide headquarters
+        assert_eq!(first.x, 24.);
+        assert!(game.component::<Destination>("headquarters-0").is_none());
+
+        for _ in 0..5 {
+            game.run(produce_units_system());
+        }
+        let second = game.component::<Position>("headquarters-1").unwrap();
+        assert!(second.y != first.y);
+        let production = game.component::<ProductionQueue>("headquarters").unwrap();
+        assert!(production.queue.is_empty());
+        assert_eq!(production.produced, 2);
+        let mut stats = game.resources.get_mut::<StatsResource>().unwrap();
+        assert_eq!(stats.player(1).units_created, 2);
+    }
+
+    #[test]
+    fn sends_produced_units_to_the_rally_point() {
+        let mut game = Game::empty();
+        game.spawn_building("headquarters", BuildingType::Headquarters, (20., 20.), 1);
+        queue(&mut game, "headquarters", UnitType::Worker, 1);
+        game.handle(vec![GameCommand::SetRallyPointCommand { building_id: "headquarters".to_string(), position: (40., 30.), player_id: 1 }]);
+
+        for _ in 0..5 {
+            game.run(produce_units_system());
+        }
+        let destination = game.component::<Destination>("headquarters-0").unwrap();
+        assert_eq!((destination.x, destination.y), (40., 30.));
+    }
+
+    #[test]
+    fn buildings_under_construction_produce_nothing() {
+        let mut game = Game::empty();
+        let building = game.spawn_building("headquarters", BuildingType::Headquarters, (20., 20.), 1);
+        queue(&mut game, "headquarters", UnitType::Worker, 1);
+        game.world.entry(building).unwrap().add_component(Construction::default());
+
+        for _ in 0..10 {
+            game.run(produce_units_system());
+        }
+        assert!(game.component::<UnitId>("headquarters-0").is_none());
+        assert_eq!(game.component::<ProductionQueue>("headquarters").unwrap().progress, 0.);
+    }
+}
@@ -0,0 +1,150 @@
+use legion::{system, systems::CommandBuffer, world::SubWorld, Entity, IntoQuery};
+use vector2d::Vector2D;
+
+use crate::{
+    game::{
+        combat::{attackables, deal_damage, enemies_within},
+        components::{Footprint, Health, Owner, Position, Projectile, UnitId},
+        events::{GameEvent, ProjectileImpact},
+        resources::{EventsResource, StatsResource, TeamsResource},
+    },
+    UidEntityMap,
+};
+
+/// Removes projectiles that reached their impact point, and damages what they hit.
+/// Homing projectiles only hit their target, others hit every enemy within their splash radius.
+#[system]
+#[read_component(UnitId)]
+#[read_component(Position)]
+#[read_component(Owner)]
+#[read_component(Footprint)]
+#[read_component(Projectile)]
+#[write_component(Health)]
+pub fn projectile_impact(
+    world: &mut SubWorld,
+    command_buffer: &mut CommandBuffer,
+    #[resource] id_map: &UidEntityMap,
+    #[resource] teams: &TeamsResource,
+    #[resource] events: &mut EventsResource,
+    #[resource] stats: &mut StatsResource,
+) {
+    let landed: Vec<(Entity, Projectile)> = <(Entity, &Position, &Projectile)>::query()
+        .iter(world)
+        .filter(|(_, pos, projectile)| {
+            let impact = Vector2D::new(projectile.impact.0, projectile.impact.1);
+            (impact - Vector2D::new(pos.x, pos.y)).length_squared() < 0.01
+        })
+        .map(|(entity, _, projectile)| (*entity, projectile.clone()))
+        .collect();
+    if landed.is_empty() {
+        return;
+    }
+
+    let attackables = attackables(world);
+    for (entity, projectile) in landed {
+        let impact = Vector2D::new(projectile.impact.0, projectile.impact.1);
+        let hit: Vec<(Entity, String)> = if projectile.stats.homing {
+            id_map
+                .get(&projectile.target)
+                .and_then(|target| attackables.iter().find(|a| a.entity == *target))
+                .map(|target| (target.entity, target.id.clone()))
+                .into_iter()
+                .collect()
+        } else {
+            enemies_within(
+                &attackables,
+                teams,
+                projectile.owner,
+                impact,
+                projectile.stats.splash_radius,
+            )
+            .map(|(target, _)| (target.entity, target.id.clone()))
+            .collect()
+        };
+        for (target, _) in &hit {
+            stats.player(projectile.owner).damage_dealt +=
+                deal_damage(world, *target, projectile.damage);
+        }
+        events
+            .events
+            .push(GameEvent::ProjectileImpact(ProjectileImpact {
+                id: projectile.id,
+                attacker: projectile.attacker,
+                target: projectile.target,
+                owner: projectile.owner,
+                position: projectile.impact,
+                hit: hit.into_iter().map(|(_, id)| id).collect(),
+            }));
+        command_buffer.remove(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        components::{ProjectileStats, UnitType, Velocity},
+        game_loop::Game,
+    };
+
+    fn fire(
+        game: &mut Game,
+        from: (f32, f32),
+        target: &str,
+        impact: (f32, f32),
+        homing: bool,
+    ) -> Entity {
+        let projectile = Projectile {
+            id: "shot".to_string(),
+            attacker: "attacker".to_string(),
+            owner: 1,
+            target: target.to_string(),
+            damage: 10.,
+            stats: ProjectileStats {
+                speed: 25.,
+                homing,
+                splash_radius: 3.,
+            },
+            impact,
+        };
+        game.world.push((
+            Position {
+                x: from.0,
+                y: from.1,
+            },
+            Velocity { dx: 0., dy: 0. },
+            projectile,
+        ))
+    }
+
+    #[test]
+    fn splash_hits_every_enemy_around_the_impact_point() {
+        let mut game = Game::empty();
+        game.spawn_unit("target", UnitType::Soldier, (20., 10.), 2);
+        game.spawn_unit("next to it", UnitType::Soldier, (22., 10.), 2);
+        game.spawn_unit("far", UnitType::Soldier, (30., 10.), 2);
+        game.spawn_unit("own", UnitType::Soldier, (20., 11.), 1);
+        let projectile = fire(&mut game, (20., 10.), "target", (20., 10.), false);
+
+        game.run(projectile_impact_system());
+        let health = |game: &Game, id| game.component::<Health>(id).unwrap().current;
+        assert_eq!(health(&game, "target"), 90.);
+        assert_eq!(health(&game, "next to it"), 90.);
+        assert_eq!(health(&game, "far"), 100.);
+        assert_eq!(health(&game, "own"), 100.);
+        assert!(!game.world.contains(projectile));
+    }
+
+    #[test]
+    fn homing_projectiles_only_hit_their_target() {
+        let mut game = Game::empty();
+        game.spawn_unit("target", UnitType::Soldier, (20., 10.), 2);
+        game.spawn_unit("next to it", UnitType::Soldier, (21., 10.), 2);
+        fire(&mut game, (20., 10.), "target", (20., 10.), true);
+
+        game.run(projectile_impact_system());
+        let health = |game: &Game, id| game.component::<Health>(id).unwrap().current;
+        assert_eq!(health(&game, "target"), 90.);
+        assert_eq!(health(&game, "next to it"), 100.);
+    }
+}
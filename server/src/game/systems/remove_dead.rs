@@ -0,0 +1,71 @@
+use legion::{system, systems::CommandBuffer, Entity};
+
+use crate::{
+    game::{
+        components::{Health, Owner, UnitId, UnitType},
+        events::GameEvent,
+        resources::{EventsResource, StatsResource},
+    },
+    UidEntityMap,
+};
+
+/// Removes units and buildings that have run out of health.
+#[system(for_each)]
+#[allow(clippy::too_many_arguments)]
+pub fn remove_dead(
+    id: &UnitId,
+    health: &Health,
+    owner: Option<&Owner>,
+    unit_type: Option<&UnitType>,
+    entity: &Entity,
+    #[resource] id_map: &mut UidEntityMap,
+    #[resource] events: &mut EventsResource,
+    #[resource] stats: &mut StatsResource,
+    command_buffer: &mut CommandBuffer,
+) {
+    if health.current > 0. {
+        return;
+    }
+    command_buffer.remove(*entity);
+    id_map.remove(&id.id);
+    if let Some(owner) = owner {
+        let stats = stats.player(owner.player_id);
+        match unit_type {
+            Some(_) => stats.units_lost += 1,
+            None => stats.buildings_lost += 1,
+        }
+    }
+    events.events.push(GameEvent::UnitDestroyed {
+        id: id.id.clone(),
+        owner: owner.map(|o| o.player_id),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        components::{BuildingType, UnitType},
+        game_loop::Game,
+    };
+
+    #[test]
+    fn counts_the_units_and_buildings_players_lose() {
+        let mut game = Game::empty();
+        let dead = game.spawn_unit("dead", UnitType::Soldier, (10., 10.), 1);
+        game.spawn_unit("alive", UnitType::Soldier, (12., 10.), 1);
+        let building = game.spawn_building("ruin", BuildingType::Barracks, (30., 30.), 1);
+        for entity in [dead, building].iter().copied() {
+            let mut entry = game.world.entry(entity).unwrap();
+            entry.get_component_mut::<Health>().unwrap().current = 0.;
+        }
+
+        game.run(remove_dead_system());
+        assert!(game.component::<UnitId>("dead").is_none());
+        assert!(game.component::<UnitId>("ruin").is_none());
+        assert!(game.component::<UnitId>("alive").is_some());
+        let mut stats = game.resources.get_mut::<StatsResource>().unwrap();
+        let stats = stats.player(1);
+        assert_eq!((stats.units_lost, stats.buildings_lost), (1, 1));
+    }
+}
@@ -1,7 +1,7 @@
 use legion::{system, systems::CommandBuffer, Entity};
 use vector2d::Vector2D;
 
-use crate::game::components::{Destination, Position, Velocity};
+use crate::game::components::{Destination, GroupSpeed, Position, Route, Velocity};
 
 #[system(for_each)]
 pub fn remove_destination_on_arrival(
@@ -22,5 +22,7 @@ pub fn remove_destination_on_arrival(
         pos.y = des.y;
         command_buffer.remove_component::<Velocity>(*entity);
         command_buffer.remove_component::<Destination>(*entity);
+        command_buffer.remove_component::<Route>(*entity);
+        command_buffer.remove_component::<GroupSpeed>(*entity);
     }
 }
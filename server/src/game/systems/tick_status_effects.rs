@@ -0,0 +1,95 @@
+use legion::{system, systems::CommandBuffer, world::SubWorld, Entity, IntoQuery};
+
+use crate::game::{
+    components::{DamageOverTime, Health, Slow, Stun},
+    resources::StatsResource,
+};
+
+/// Counts down the ticks left of status effects and removes the ones that ran out,
+/// units with damage over time lose health every tick.
+#[system]
+#[write_component(Slow)]
+#[write_component(Stun)]
+#[write_component(DamageOverTime)]
+#[write_component(Health)]
+pub fn tick_status_effects(
+    world: &mut SubWorld,
+    command_buffer: &mut CommandBuffer,
+    #[resource] stats: &mut StatsResource,
+) {
+    <(Entity, &mut Slow)>::query().for_each_mut(world, |(entity, slow)| {
+        slow.ticks_left = slow.ticks_left.saturating_sub(1);
+        if slow.ticks_left == 0 {
+            command_buffer.remove_component::<Slow>(*entity);
+        }
+    });
+    <(Entity, &mut Stun)>::query().for_each_mut(world, |(entity, stun)| {
+        stun.ticks_left = stun.ticks_left.saturating_sub(1);
+        if stun.ticks_left == 0 {
+            command_buffer.remove_component::<Stun>(*entity);
+        }
+    });
+    <(Entity, &mut DamageOverTime, &mut Health)>::query().for_each_mut(
+        world,
+        |(entity, dot, health)| {
+            let damage = dot.damage * dot.stacks as f32;
+            if let Some(source) = dot.source {
+                stats.player(source).damage_dealt += damage.min(health.current.max(0.));
+            }
+            health.current -= damage;
+            dot.ticks_left = dot.ticks_left.saturating_sub(1);
+            if dot.ticks_left == 0 {
+                command_buffer.remove_component::<DamageOverTime>(*entity);
+            }
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{components::UnitType, game_loop::Game};
+
+    #[test]
+    fn effects_wear_off_once_their_ticks_run_out() {
+        let mut game = Game::empty();
+        let unit = game.spawn_unit("unit", UnitType::Soldier, (10., 10.), 1);
+        let mut entry = game.world.entry(unit).unwrap();
+        entry.add_component(Stun { ticks_left: 1 });
+        entry.add_component(Slow {
+            factor: 0.5,
+            ticks_left: 2,
+        });
+
+        game.run(tick_status_effects_system());
+        assert!(game.component::<Stun>("unit").is_none());
+        assert_eq!(game.component::<Slow>("unit").unwrap().ticks_left, 1);
+        game.run(tick_status_effects_system());
+        assert!(game.component::<Slow>("unit").is_none());
+    }
+
+    #[test]
+    fn damage_over_time_hurts_every_tick_for_each_stack() {
+        let mut game = Game::empty();
+        let unit = game.spawn_unit("unit", UnitType::Soldier, (10., 10.), 1);
+        game.world
+            .entry(unit)
+            .unwrap()
+            .add_component(DamageOverTime {
+                damage: 5.,
+                stacks: 2,
+                ticks_left: 2,
+                source: Some(2),
+            });
+
+        game.run(tick_status_effects_system());
+        assert_eq!(game.component::<Health>("unit").unwrap().current, 90.);
+        game.run(tick_status_effects_system());
+        assert_eq!(game.component::<Health>("unit").unwrap().current, 80.);
+        assert!(game.component::<DamageOverTime>("unit").is_none());
+        game.run(tick_status_effects_system());
+        assert_eq!(game.component::<Health>("unit").unwrap().current, 80.);
+        let mut stats = game.resources.get_mut::<StatsResource>().unwrap();
+        assert_eq!(stats.player(2).damage_dealt, 20.);
+    }
+}
@@ -1,9 +1,24 @@
 use legion::system;
 
-use crate::game::{components::{Position, Velocity}, resources::TimeResource};
+use crate::game::{components::{Position, Projectile, Velocity}, resources::{MapResource, TimeResource}};
 
-#[system(par_for_each)]
-pub fn velocity_to_position(pos: &mut Position, vel: &Velocity, #[resource] time: &TimeResource) {
-    pos.x += vel.dx * (time.elapsed_seconds as f32);
-    pos.y += vel.dy * (time.elapsed_seconds as f32);
-}
\ No newline at end of file
+#[system(for_each)]
+pub fn velocity_to_position(
+    pos: &mut Position,
+    vel: &Velocity,
+    projectile: Option<&Projectile>,
+    #[resource] time: &TimeResource,
+    #[resource] map: &MapResource,
+) {
+    let x = pos.x + vel.dx * (time.elapsed_seconds as f32);
+    let y = pos.y + vel.dy * (time.elapsed_seconds as f32);
+    // Water, cliffs, obstacles and the edge of the map block movement, unless the unit is already stuck on them,
+    // projectiles fly over them
+    if projectile.is_some()
+        || map.speed_multiplier_at((x, y)) > 0.
+        || map.speed_multiplier_at((pos.x, pos.y)) <= 0.
+    {
+        pos.x = x;
+        pos.y = y;
+    }
+}
@@ -0,0 +1,586 @@
+//! Reads maps made with the Tiled editor (<https://www.mapeditor.org>), saved as json or tmx.
+//!
+//! Tile layers become the terrain: a tile gets its terrain from its `terrain` property,
+//! or from its class, and tiles without either are grass.
+//! Objects become things on the map by their class:
+//! `spawn_point`, `resource_node` (with an `amount` property), `obstacle` and `unit`
+//! (with `unit_type` and `owner` properties). Other objects are ignored.
+//! The `tile_size` map property sets how many meters a tile covers, 4 when missing.
+
+use std::{collections::HashMap, fs, io, path::Path};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::game::{
+    components::UnitType,
+    map::{MapDefinition, MapResourceNode, MapUnit, Obstacle, Terrain},
+};
+
+const DEFAULT_TILE_SIZE: f32 = 4.;
+const DEFAULT_NODE_AMOUNT: u32 = 1000;
+/// Tiled stores whether a tile is flipped in the highest bits of its gid.
+const GID_MASK: u32 = 0x0FFF_FFFF;
+const CSV_ONLY: &str = "only csv encoding is supported, set the tile layer format to csv";
+
+/// What the json and tmx formats are read into before they become a `MapDefinition`.
+struct TiledMap {
+    width: u32,
+    height: u32,
+    tile_width: f32,
+    tile_height: f32,
+    properties: HashMap<String, String>,
+    /// The terrain of every tile of the tilesets that has one, by gid.
+    tile_terrain: HashMap<u32, Terrain>,
+    /// The gids of every tile layer, row by row.
+    layers: Vec<Vec<u32>>,
+    objects: Vec<TiledObject>,
+}
+
+struct TiledObject {
+    class: String,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    properties: HashMap<String, String>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Whether a json value is a map saved by Tiled rather than a `MapDefinition`.
+pub fn is_tiled_json(value: &Value) -> bool {
+    value.get("tiledversion").is_some() || value.get("layers").is_some()
+}
+
+pub fn from_json(value: Value, name: &str) -> io::Result<MapDefinition> {
+    let json: JsonMap = serde_json::from_value(value)?;
+    let mut tile_terrain = HashMap::new();
+    for tileset in &json.tilesets {
+        if tileset.source.is_some() {
+            return Err(invalid(
+                "external tilesets are not supported, embed the tileset in the map".to_string(),
+            ));
+        }
+        for tile in &tileset.tiles {
+            let class = tile
+                .class
+                .as_ref()
+                .or(tile.old_class.as_ref())
+                .map(|c| c.as_str());
+            if let Some(terrain) = tile_terrain_of(&json_properties(&tile.properties), class)? {
+                tile_terrain.insert(tileset.firstgid + tile.id, terrain);
+            }
+        }
+    }
+    let mut json_layers = Vec::new();
+    flatten_groups(json.layers, &mut json_layers);
+    let mut layers = Vec::new();
+    let mut objects = Vec::new();
+    for layer in json_layers {
+        match layer.kind.as_str() {
+            "tilelayer" => {
+                // Csv layers are saved as an array of gids, without an encoding or with "csv"
+                if layer.encoding.as_deref().unwrap_or("csv") != "csv" {
+                    return Err(invalid(CSV_ONLY.to_string()));
+                }
+                if layer.data.is_null() {
+                    return Err(invalid("tile layer without data".to_string()));
+                }
+                layers.push(serde_json::from_value(layer.data)?);
+            }
+            "objectgroup" => {
+                for object in layer.objects {
+                    objects.push(TiledObject {
+                        class: object.class.or(object.old_class).unwrap_or_default(),
+                        x: object.x,
+                        y: object.y,
+                        width: object.width,
+                        height: object.height,
+                        properties: json_properties(&object.properties),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    TiledMap {
+        width: json.width,
+        height: json.height,
+        tile_width: json.tilewidth,
+        tile_height: json.tileheight,
+        properties: json_properties(&json.properties),
+        tile_terrain,
+        layers,
+        objects,
+    }
+    .into_map_definition(name)
+}
+
+pub fn load_tmx(path: &Path) -> io::Result<MapDefinition> {
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("Tiled map");
+    from_tmx(&fs::read_to_string(path)?, name)
+}
+
+fn from_tmx(text: &str, name: &str) -> io::Result<MapDefinition> {
+    let document = roxmltree::Document::parse(text).map_err(|e| invalid(e.to_string()))?;
+    let map = document.root_element();
+    if map.tag_name().name() != "map" {
+        return Err(invalid("tmx file does not start with a map".to_string()));
+    }
+
+    let mut tile_terrain = HashMap::new();
+    let mut layers = Vec::new();
+    let mut objects = Vec::new();
+    for node in map.descendants().filter(|node| node.is_element()) {
+        match node.tag_name().name() {
+            "tileset" => {
+                if node.has_attribute("source") {
+                    return Err(invalid(
+                        "external tilesets are not supported, embed the tileset in the map"
+                            .to_string(),
+                    ));
+                }
+                let firstgid: u32 = attribute(node, "firstgid")?;
+                for tile in node.children().filter(|n| n.has_tag_name("tile")) {
+                    let id: u32 = attribute(tile, "id")?;
+                    let class = tile.attribute("class").or_else(|| tile.attribute("type"));
+                    if let Some(terrain) = tile_terrain_of(&xml_properties(tile), class)? {
+                        tile_terrain.insert(firstgid + id, terrain);
+                    }
+                }
+            }
+            "layer" => {
+                let data = node
+                    .children()
+                    .find(|n| n.has_tag_name("data"))
+                    .ok_or_else(|| invalid("tile layer without data".to_string()))?;
+                if data.attribute("encoding") != Some("csv") {
+                    return Err(invalid(CSV_ONLY.to_string()));
+                }
+                let gids = data
+                    .text()
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(|gid| gid.trim())
+                    .filter(|gid| !gid.is_empty())
+                    .map(|gid| {
+                        gid.parse()
+                            .map_err(|_| invalid(format!("invalid tile {:?}", gid)))
+                    })
+                    .collect::<io::Result<Vec<u32>>>()?;
+                layers.push(gids);
+            }
+            // Objects inside tilesets are collision shapes of tiles, not things on the map
+            "object" if !node.ancestors().any(|n| n.has_tag_name("tileset")) => {
+                objects.push(TiledObject {
+                    class: node
+                        .attribute("class")
+                        .or_else(|| node.attribute("type"))
+                        .unwrap_or_default()
+                        .to_string(),
+                    x: optional_attribute(node, "x")?.unwrap_or(0.),
+                    y: optional_attribute(node, "y")?.unwrap_or(0.),
+                    width: optional_attribute(node, "width")?.unwrap_or(0.),
+                    height: optional_attribute(node, "height")?.unwrap_or(0.),
+                    properties: xml_properties(node),
+                })
+            }
+            _ => {}
+        }
+    }
+
+    TiledMap {
+        width: attribute(map, "width")?,
+        height: attribute(map, "height")?,
+        tile_width: attribute(map, "tilewidth")?,
+        tile_height: attribute(map, "tileheight")?,
+        properties: xml_properties(map),
+        tile_terrain,
+        layers,
+        objects,
+    }
+    .into_map_definition(name)
+}
+
+impl TiledMap {
+    fn into_map_definition(self, name: &str) -> io::Result<MapDefinition> {
+        let tile_size = match self.properties.get("tile_size") {
+            Some(size) => size
+                .parse()
+                .map_err(|_| invalid(format!("invalid tile_size {:?}", size)))?,
+            None => DEFAULT_TILE_SIZE,
+        };
+        if self.width == 0 || self.height == 0 {
+            return Err(invalid(format!(
+                "map is {}x{} tiles, it needs at least one",
+                self.width, self.height
+            )));
+        }
+        if self.tile_width <= 0. || self.tile_height <= 0. || tile_size <= 0. {
+            return Err(invalid("tiles need a size above 0".to_string()));
+        }
+        let tile_count = self.width as usize * self.height as usize;
+
+        // Later layers are drawn over earlier ones, so their tiles win where they have one
+        let mut terrain = vec![Terrain::Grass; tile_count];
+        for layer in &self.layers {
+            if layer.len() != tile_count {
+                return Err(invalid(format!(
+                    "tile layer has {} tiles, expected {}",
+                    layer.len(),
+                    tile_count
+                )));
+            }
+            for (tile, gid) in terrain.iter_mut().zip(layer) {
+                let gid = gid & GID_MASK;
+                if gid != 0 {
+                    *tile = self
+                        .tile_terrain
+                        .get(&gid)
+                        .copied()
+                        .unwrap_or(Terrain::Grass);
+                }
+            }
+        }
+        let tiles = terrain
+            .chunks(self.width as usize)
+            .map(|row| row.iter().map(|terrain| terrain.to_char()).collect())
+            .collect();
+
+        let mut map = MapDefinition {
+            name: self
+                .properties
+                .get("name")
+                .cloned()
+                .unwrap_or_else(|| name.to_string()),
+            width: self.width,
+            height: self.height,
+            tile_size,
+            tiles,
+            spawn_points: Vec::new(),
+            resource_nodes: Vec::new(),
+            obstacles: Vec::new(),
+            units: Vec::new(),
+            seed: None,
+        };
+        // Tiled positions are in pixels, from the top left corner of the object
+        let meters_x = tile_size / self.tile_width;
+        let meters_y = tile_size / self.tile_height;
+        for object in self.objects {
+            let position = (
+                (object.x + object.width / 2.) * meters_x,
+                (object.y + object.height / 2.) * meters_y,
+            );
+            match object.class.as_str() {
+                "spawn_point" => map.spawn_points.push(position),
+                "resource_node" => map.resource_nodes.push(MapResourceNode {
+                    position,
+                    amount: parse_property(&object.properties, "amount")?
+                        .unwrap_or(DEFAULT_NODE_AMOUNT),
+                }),
+                "obstacle" => map.obstacles.push(Obstacle {
+                    position,
+                    width: object.width * meters_x,
+                    height: object.height * meters_y,
+                }),
+                "unit" => {
+                    let unit_type = match object.properties.get("unit_type") {
+                        Some(unit_type) => serde_json::from_value(Value::String(unit_type.clone()))
+                            .map_err(|_| invalid(format!("unknown unit_type {:?}", unit_type)))?,
+                        None => UnitType::default(),
+                    };
+                    map.units.push(MapUnit {
+                        position,
+                        unit_type,
+                        owner: parse_property(&object.properties, "owner")?,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(map)
+    }
+}
+
+/// Layers in groups are read like the layers outside, in the order they are drawn.
+fn flatten_groups(layers: Vec<JsonLayer>, flattened: &mut Vec<JsonLayer>) {
+    for layer in layers {
+        if layer.kind == "group" {
+            flatten_groups(layer.layers, flattened);
+        } else {
+            flattened.push(layer);
+        }
+    }
+}
+
+/// The terrain a tile has from its `terrain` property or its class, if any.
+fn tile_terrain_of(
+    properties: &HashMap<String, String>,
+    class: Option<&str>,
+) -> io::Result<Option<Terrain>> {
+    let name = match properties.get("terrain").map(|t| t.as_str()).or(class) {
+        Some(name) if !name.is_empty() => name,
+        _ => return Ok(None),
+    };
+    match name.to_lowercase().as_str() {
+        "grass" => Ok(Some(Terrain::Grass)),
+        "mud" => Ok(Some(Terrain::Mud)),
+        "forest" => Ok(Some(Terrain::Forest)),
+        "water" => Ok(Some(Terrain::Water)),
+        "cliff" => Ok(Some(Terrain::Cliff)),
+        _ => Err(invalid(format!("unknown terrain {:?}", name))),
+    }
+}
+
+fn parse_property<T: std::str::FromStr>(
+    properties: &HashMap<String, String>,
+    name: &str,
+) -> io::Result<Option<T>> {
+    properties
+        .get(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| invalid(format!("invalid {} {:?}", name, value)))
+        })
+        .transpose()
+}
+
+fn attribute<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> io::Result<T> {
+    optional_attribute(node, name)?.ok_or_else(|| {
+        invalid(format!(
+            "{} is missing the {} attribute",
+            node.tag_name().name(),
+            name
+        ))
+    })
+}
+
+fn optional_attribute<T: std::str::FromStr>(
+    node: roxmltree::Node,
+    name: &str,
+) -> io::Result<Option<T>> {
+    node.attribute(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| invalid(format!("invalid {} {:?}", name, value)))
+        })
+        .transpose()
+}
+
+/// The custom properties of a tmx element, with their values as text.
+fn xml_properties(node: roxmltree::Node) -> HashMap<String, String> {
+    node.children()
+        .filter(|n| n.has_tag_name("properties"))
+        .flat_map(|properties| properties.children().filter(|n| n.has_tag_name("property")))
+        .filter_map(|property| {
+            let value = property.attribute("value").or_else(|| property.text())?;
+            Some((property.attribute("name")?.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// The custom properties of a json element, with their values as text.
+fn json_properties(properties: &[JsonProperty]) -> HashMap<String, String> {
+    properties
+        .iter()
+        .map(|property| {
+            let value = match &property.value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            (property.name.clone(), value)
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct JsonMap {
+    width: u32,
+    height: u32,
+    tilewidth: f32,
+    tileheight: f32,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+    #[serde(default)]
+    tilesets: Vec<JsonTileset>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+}
+
+#[derive(Deserialize)]
+struct JsonProperty {
+    name: String,
+    value: Value,
+}
+
+#[derive(Deserialize)]
+struct JsonTileset {
+    firstgid: u32,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    tiles: Vec<JsonTile>,
+}
+
+/// Tiled renamed `type` to `class` in version 1.9, both are read.
+#[derive(Deserialize)]
+struct JsonTile {
+    id: u32,
+    #[serde(default)]
+    class: Option<String>,
+    #[serde(default, rename = "type")]
+    old_class: Option<String>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    /// An array of gids for csv layers, a string for base64 ones.
+    #[serde(default)]
+    data: Value,
+    #[serde(default)]
+    encoding: Option<String>,
+    #[serde(default)]
+    objects: Vec<JsonObject>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+}
+
+#[derive(Deserialize)]
+struct JsonObject {
+    #[serde(default)]
+    class: Option<String>,
+    #[serde(default, rename = "type")]
+    old_class: Option<String>,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn json_map(width: u32, height: u32, layer: Value) -> Value {
+        json!({
+            "tiledversion": "1.10.2",
+            "width": width,
+            "height": height,
+            "tilewidth": 16,
+            "tileheight": 16,
+            "properties": [{ "name": "tile_size", "type": "float", "value": 2 }],
+            "tilesets": [{ "firstgid": 1, "tiles": [{ "id": 0, "class": "water" }] }],
+            "layers": [
+                layer,
+                {
+                    "type": "objectgroup",
+                    "objects": [
+                        { "class": "spawn_point", "x": 16, "y": 8 },
+                        {
+                            "class": "resource_node",
+                            "x": 0,
+                            "y": 0,
+                            "width": 16,
+                            "height": 16,
+                            "properties": [{ "name": "amount", "type": "int", "value": 300 }]
+                        }
+                    ]
+                }
+            ]
+        })
+    }
+
+    #[test]
+    fn reads_json_maps() {
+        let layer = json!({ "type": "tilelayer", "data": [1, 0, 0, 1 | 0x8000_0000u32] });
+        let map = from_json(json_map(2, 2, layer), "small").expect("Map should load");
+
+        assert_eq!(map.name, "small");
+        assert_eq!((map.width, map.height, map.tile_size), (2, 2, 2.));
+        assert_eq!(map.tiles, vec!["~.", ".~"]);
+        assert_eq!(map.spawn_points, vec![(2., 1.)]);
+        assert_eq!(map.resource_nodes.len(), 1);
+        assert_eq!(map.resource_nodes[0].position, (1., 1.));
+        assert_eq!(map.resource_nodes[0].amount, 300);
+    }
+
+    #[test]
+    fn reads_tmx_maps() {
+        let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" width="3" height="1" tilewidth="16" tileheight="16">
+ <tileset firstgid="1" name="terrain" tilewidth="16" tileheight="16" tilecount="2" columns="2">
+  <tile id="0" class="cliff"/>
+  <tile id="1"><properties><property name="terrain" value="forest"/></properties></tile>
+ </tileset>
+ <layer id="1" name="ground" width="3" height="1">
+  <data encoding="csv">1,2,0</data>
+ </layer>
+ <objectgroup id="2" name="objects">
+  <object id="1" class="obstacle" x="32" y="0" width="16" height="16"/>
+  <object id="2" class="unit" x="40" y="8">
+   <properties><property name="unit_type" value="Worker"/><property name="owner" type="int" value="1"/></properties>
+  </object>
+ </objectgroup>
+</map>"#;
+        let map = from_tmx(tmx, "small").expect("Map should load");
+
+        assert_eq!(map.tiles, vec!["#^."]);
+        assert_eq!(map.tile_size, DEFAULT_TILE_SIZE);
+        assert_eq!(map.obstacles.len(), 1);
+        assert_eq!(map.obstacles[0].position, (10., 2.));
+        assert_eq!((map.obstacles[0].width, map.obstacles[0].height), (4., 4.));
+        assert_eq!(map.units.len(), 1);
+        assert_eq!(map.units[0].position, (10., 2.));
+        assert_eq!(map.units[0].unit_type, UnitType::Worker);
+        assert_eq!(map.units[0].owner, Some(1));
+    }
+
+    #[test]
+    fn rejects_maps_without_tiles() {
+        let layer = json!({ "type": "tilelayer", "data": [] });
+        let error = from_json(json_map(0, 2, layer), "empty").expect_err("Map should not load");
+        assert!(error.to_string().contains("0x2"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_layers_of_the_wrong_size() {
+        let layer = json!({ "type": "tilelayer", "data": [1, 0, 0] });
+        let error = from_json(json_map(2, 2, layer), "small").expect_err("Map should not load");
+        assert!(error.to_string().contains("expected 4"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_encoded_layers() {
+        let layer = json!({
+            "type": "tilelayer",
+            "encoding": "base64",
+            "compression": "zlib",
+            "data": "eJxjZGBgAAAABAAB"
+        });
+        let error = from_json(json_map(2, 2, layer), "small").expect_err("Map should not load");
+        assert!(error.to_string().contains(CSV_ONLY), "{}", error);
+
+        let tmx = r#"<map width="1" height="1" tilewidth="16" tileheight="16">
+ <layer id="1" width="1" height="1"><data encoding="base64">AQAAAA==</data></layer>
+</map>"#;
+        let error = from_tmx(tmx, "small").expect_err("Map should not load");
+        assert!(error.to_string().contains(CSV_ONLY), "{}", error);
+    }
+}
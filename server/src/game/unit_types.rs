@@ -0,0 +1,75 @@
+use crate::game::components::{AbilityType, MovementProfile, ProjectileStats, UnitType, Weapon};
+
+/// The stats every unit of a `UnitType` starts with.
+pub struct UnitTypeDefinition {
+    /// Resources the owner pays to create the unit.
+    pub cost: u32,
+    /// Seconds a building takes to produce the unit.
+    pub build_time: f32,
+    pub sight_radius: f32,
+    pub max_health: f32,
+    /// Carry capacity and resources per second, for units that can harvest.
+    pub harvester: Option<(u32, f32)>,
+    pub movement: MovementProfile,
+    pub weapon: Weapon,
+    pub abilities: &'static [AbilityType],
+}
+
+impl UnitType {
+    pub fn definition(self) -> UnitTypeDefinition {
+        match self {
+            UnitType::Worker => UnitTypeDefinition {
+                cost: 50,
+                build_time: 5.,
+                sight_radius: 12.,
+                max_health: 40.,
+                harvester: Some((10, 2.)),
+                movement: MovementProfile {
+                    max_speed: 5.,
+                    acceleration: 5.,
+                    deceleration: 10.,
+                    turn_rate: std::f32::consts::PI,
+                },
+                weapon: Weapon {
+                    damage: 5.,
+                    range: 1.5,
+                    cooldown: 1.5,
+                    acquisition_range: 6.,
+                    projectile: None,
+                    ready_in: 0.,
+                },
+                abilities: &[AbilityType::Mend],
+            },
+            UnitType::Soldier => UnitTypeDefinition {
+                cost: 100,
+                build_time: 8.,
+                sight_radius: 15.,
+                max_health: 100.,
+                harvester: None,
+                movement: MovementProfile {
+                    max_speed: 4.5,
+                    acceleration: 3.,
+                    deceleration: 6.,
+                    turn_rate: std::f32::consts::FRAC_PI_2,
+                },
+                weapon: Weapon {
+                    damage: 10.,
+                    range: 10.,
+                    cooldown: 1.,
+                    acquisition_range: 15.,
+                    projectile: Some(ProjectileStats {
+                        speed: 25.,
+                        homing: true,
+                        splash_radius: 0.,
+                    }),
+                    ready_in: 0.,
+                },
+                abilities: &[
+                    AbilityType::Grenade,
+                    AbilityType::Flashbang,
+                    AbilityType::Rally,
+                ],
+            },
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::{
+    components::{PlayerId, TeamId},
+    resources::{PlayerStats, TeamsResource},
+};
+
+/// A way for a match to end, set when the server starts.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum VictoryCondition {
+    /// A side wins once every unit and building of the other sides is destroyed.
+    Elimination,
+    /// A side wins by having the only units within `radius` of `position` for `seconds` in a row.
+    ControlPoint {
+        position: (f32, f32),
+        radius: f32,
+        seconds: f64,
+    },
+    /// The match ends after `seconds`, the side with the highest score wins.
+    TimeLimit { seconds: f64 },
+}
+
+impl FromStr for VictoryCondition {
+    type Err = String;
+
+    /// Parses `elimination`, `control-point:x,y,radius,seconds` or `time-limit:seconds`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, values) = s.split_once(':').unwrap_or((s, ""));
+        let values = values
+            .split(',')
+            .filter(|value| !value.is_empty())
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| format!("{} expects numbers", name))?;
+        match (name, values.as_slice()) {
+            ("elimination", []) => Ok(VictoryCondition::Elimination),
+            ("control-point", [x, y, radius, seconds]) => Ok(VictoryCondition::ControlPoint {
+                position: (*x as f32, *y as f32),
+                radius: *radius as f32,
+                seconds: *seconds,
+            }),
+            ("time-limit", [seconds]) => Ok(VictoryCondition::TimeLimit { seconds: *seconds }),
+            ("control-point", _) => Err("control-point expects x,y,radius,seconds".to_string()),
+            ("time-limit", _) => Err("time-limit expects seconds".to_string()),
+            _ => Err(format!("unknown victory condition {}", s)),
+        }
+    }
+}
+
+/// The players that win or lose together, a team or a player that hasn't joined one.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Side {
+    Team(TeamId),
+    Player(PlayerId),
+}
+
+impl Side {
+    pub fn of(teams: &TeamsResource, player_id: PlayerId) -> Side {
+        match teams.team_of(player_id) {
+            Some(team_id) => Side::Team(team_id),
+            None => Side::Player(player_id),
+        }
+    }
+}
+
+/// How a player ended the match.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PlayerResult {
+    pub units: u32,
+    pub buildings: u32,
+    /// The resources in the player's bank plus what their units and buildings cost.
+    pub score: u32,
+    #[serde(flatten)]
+    pub stats: PlayerStats,
+}
+
+/// Sent to every client when a match is over, `winner` is `None` for a draw.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MatchEnded {
+    pub winner: Option<Side>,
+    /// The condition that ended the match.
+    pub condition: VictoryCondition,
+    pub ticks: u64,
+    pub stats: HashMap<PlayerId, PlayerResult>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_victory_conditions() {
+        assert_eq!("elimination".parse(), Ok(VictoryCondition::Elimination));
+        assert_eq!(
+            "control-point:128,64.5, 10,60".parse(),
+            Ok(VictoryCondition::ControlPoint {
+                position: (128., 64.5),
+                radius: 10.,
+                seconds: 60.,
+            })
+        );
+        assert_eq!(
+            "time-limit:900".parse(),
+            Ok(VictoryCondition::TimeLimit { seconds: 900. })
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_victory_conditions() {
+        let error = |s: &str| s.parse::<VictoryCondition>().unwrap_err();
+        assert_eq!(
+            error("control-point:1,2,3"),
+            "control-point expects x,y,radius,seconds"
+        );
+        assert_eq!(error("time-limit"), "time-limit expects seconds");
+        assert_eq!(error("time-limit:soon"), "time-limit expects numbers");
+        assert_eq!(
+            error("elimination:1"),
+            "unknown victory condition elimination:1"
+        );
+        assert_eq!(
+            error("king-of-the-hill"),
+            "unknown victory condition king-of-the-hill"
+        );
+    }
+}
@@ -1,10 +1,14 @@
 
 use crate::{
-    game::commands::GameCommand,
+    config::Config,
+    database::DatabaseRef,
+    game::{commands::GameCommand, components::TeamId, game_state::Unit},
     ws::{self},
-    Client, Clients, GameCommandSender, GameStateRef, Result,
+    Client, ClientRole, Clients, GameCommandSender, GameStateRef, GameStateSender, MapRef,
+    Result,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use uuid::Uuid;
 use warp::{http::StatusCode, reply::json, Reply};
 
@@ -13,17 +17,107 @@ pub struct RegisterResponse {
     url: String,
 }
 
+#[derive(Serialize, Debug)]
+pub struct GameStateResponse {
+    units: HashMap<String, Unit>,
+    /// The seed the map was generated from, so a match can be played again on the same map.
+    map_seed: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MatchHistoryQuery {
+    /// Only matches this player played in.
+    player_id: Option<usize>,
+    #[serde(default = "default_limit")]
+    limit: u32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LeaderboardQuery {
+    #[serde(default = "default_limit")]
+    limit: u32,
+}
+
+fn default_limit() -> u32 {
+    20
+}
+
 #[derive(Deserialize, Debug)]
 pub struct RegisterRequest {
     user_id: usize,
+    /// Spectators receive the game state but can't send commands.
+    #[serde(default)]
+    spectator: bool,
+    /// Players registering with the same team are allies.
+    #[serde(default)]
+    team: Option<TeamId>,
 }
 
-pub async fn get_game_state_handler(game_state: GameStateRef) -> Result<impl Reply> {
-    let game_state = &game_state.read().await.units;
-    let json = json(&game_state);
+/// Returns the units the client with `id` can see and the seed of the map, players are limited by fog of war.
+pub async fn get_game_state_handler(
+    id: String,
+    clients: Clients,
+    game_state: GameStateRef,
+    config: Config,
+) -> Result<impl Reply> {
+    let client = match clients.read().await.get(&id).cloned() {
+        Some(client) => client,
+        None => return Err(warp::reject::not_found()),
+    };
+    let game_state = game_state.read().await;
+    let units = match client.role {
+        ClientRole::Player => game_state.for_player(client.user_id).units,
+        // Delayed spectators only get the state from the websocket, or they could scout the current state here
+        ClientRole::Spectator if !config.spectator_delay.is_zero() => Default::default(),
+        ClientRole::Spectator => game_state.units.clone(),
+    };
+    let json = json(&GameStateResponse {
+        units,
+        map_seed: game_state.map_seed,
+    });
     Ok(json)
 }
 
+/// Returns the stats of every player in the match so far.
+pub async fn stats_handler(game_state: GameStateRef) -> Result<impl Reply> {
+    Ok(json(&game_state.read().await.stats))
+}
+
+/// Returns the most recent finished matches, with their players and results.
+pub async fn match_history_handler(
+    query: MatchHistoryQuery,
+    database: DatabaseRef,
+) -> Result<impl Reply> {
+    let database = database.lock().expect("Database lock should not be poisoned");
+    match database.match_history(query.player_id, query.limit) {
+        Ok(matches) => Ok(json(&matches)),
+        Err(e) => {
+            eprintln!("error reading match history: {}", e);
+            Err(warp::reject::reject())
+        }
+    }
+}
+
+/// Returns the players with the highest ratings.
+pub async fn leaderboard_handler(
+    query: LeaderboardQuery,
+    database: DatabaseRef,
+) -> Result<impl Reply> {
+    let database = database.lock().expect("Database lock should not be poisoned");
+    match database.leaderboard(query.limit) {
+        Ok(ratings) => Ok(json(&ratings)),
+        Err(e) => {
+            eprintln!("error reading leaderboard: {}", e);
+            Err(warp::reject::reject())
+        }
+    }
+}
+
+/// Returns the map the game is played on, for clients to render.
+pub async fn map_handler(map: MapRef) -> Result<impl Reply> {
+    Ok(json(&map.definition))
+}
+
 pub async fn reset_game_state_handler(sender: GameCommandSender) -> impl Reply {
     sender
         .send(GameCommand::ResetGameCommand)
@@ -32,24 +126,53 @@ pub async fn reset_game_state_handler(sender: GameCommandSender) -> impl Reply {
     ""
 }
 
+pub async fn save_game_handler(sender: GameCommandSender) -> impl Reply {
+    sender
+        .send(GameCommand::SaveGameCommand)
+        .await
+        .expect("Should be able to send");
+    ""
+}
+
+pub async fn load_game_handler(sender: GameCommandSender) -> impl Reply {
+    sender
+        .send(GameCommand::LoadGameCommand)
+        .await
+        .expect("Should be able to send");
+    ""
+}
+
 pub async fn register_handler(body: RegisterRequest, clients: Clients) -> Result<impl Reply> {
     let user_id = body.user_id;
     println!("Register Handler, user_id {}", user_id);
     let uuid = Uuid::new_v4().to_string();
+    let role = if body.spectator {
+        ClientRole::Spectator
+    } else {
+        ClientRole::Player
+    };
 
-    register_client(uuid.clone(), user_id, clients).await;
+    register_client(uuid.clone(), user_id, role, body.team, clients).await;
     Ok(json(&RegisterResponse {
         url: format!("ws/{}", uuid),
     }))
 }
 
-async fn register_client(id: String, user_id: usize, clients: Clients) {
-    println!("Register Client, id {}", id);
+async fn register_client(
+    id: String,
+    user_id: usize,
+    role: ClientRole,
+    team: Option<TeamId>,
+    clients: Clients,
+) {
+    println!("Register Client, id {}, role {:?}, team {:?}", id, role, team);
 
     clients.write().await.insert(
         id,
         Client {
             user_id,
+            role,
+            team,
             sender: None,
         },
     );
@@ -62,17 +185,32 @@ pub async fn unregister_handler(id: String, clients: Clients) -> Result<impl Rep
     Ok(StatusCode::OK)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn ws_handler(
     ws: warp::ws::Ws,
     id: String,
     clients: Clients,
     sender: GameCommandSender,
+    game_states: GameStateSender,
+    game_state: GameStateRef,
+    config: Config,
+    map: MapRef,
 ) -> Result<impl Reply> {
     let client = clients.read().await.get(&id).cloned();
     match client {
-        Some(c) => {
-            Ok(ws.on_upgrade(move |socket| ws::client_connection(socket, id, clients, c, sender)))
-        }
+        Some(c) => Ok(ws.on_upgrade(move |socket| {
+            ws::client_connection(
+                socket,
+                id,
+                clients,
+                c,
+                sender,
+                game_states,
+                game_state,
+                config.spectator_delay,
+                map,
+            )
+        })),
         None => Err(warp::reject::not_found()),
     }
 }
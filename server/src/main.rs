@@ -1,23 +1,32 @@
-use crate::game::components::Destination;
-use crate::game::components::Position;
-use crate::game::components::UnitId;
 // #![windows_subsystem = "windows"]
+use crate::game::bots::Bot;
+use crate::game::game_loop::Game;
+use crate::game::scripting::Script;
 use crate::game::game_state::GameStateCache;
-use crate::game::game_state::Unit;
-use crate::game::resources::TimeResource;
-use crate::game::schedule::create_schedule;
+use crate::game::journal::{CommandJournal, JournalHeader, Replay, JOURNAL_VERSION};
+use crate::game::map::MapDefinition;
+use crate::game::map_generator;
+use crate::game::resources::{MapResource, MatchResource, TeamsResource, TICK_DURATION};
+use crate::game::save_game::{check_version, read_save};
+use config::Config;
+use database::{Database, DatabaseRef, FinishedMatch};
 use futures::FutureExt;
 use game::commands::GameCommand;
-use legion::systems::CommandBuffer;
+use game::components::TeamId;
 use legion::*;
 use std::collections::HashMap;
 use std::convert::Infallible;
-use std::sync::Arc;
+use std::path::Path;
+use std::process;
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::{Duration, SystemTime};
-use tokio::sync::{mpsc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::runtime::Handle;
+use tokio::sync::{broadcast, mpsc, RwLock};
 use warp::{ws::Message, Filter, Rejection};
 
+mod config;
+mod database;
 mod game;
 mod handler;
 mod ws;
@@ -26,78 +35,162 @@ type Result<T> = std::result::Result<T, Rejection>;
 type Clients = Arc<RwLock<HashMap<String, Client>>>;
 type GameStateRef = Arc<RwLock<GameStateCache>>;
 type GameCommandSender = mpsc::Sender<GameCommand>;
+type GameStateSender = broadcast::Sender<Arc<GameStateCache>>;
+type MapRef = Arc<MapResource>;
 
 type UidEntityMap = HashMap<String, Entity>;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClientRole {
+    Player,
+    /// Gets the same messages as players, but can't send commands.
+    Spectator,
+}
+
 #[derive(Debug, Clone)]
 pub struct Client {
     pub user_id: usize,
+    pub role: ClientRole,
+    pub team: Option<TeamId>,
     pub sender: Option<mpsc::UnboundedSender<std::result::Result<Message, warp::Error>>>,
 }
 
 #[tokio::main]
 async fn main() {
+    let mut config = Config::from_args();
+    let replay = config.replay.as_ref().map(|path| {
+        println!("Replaying {:?}, commands from clients are ignored", path);
+        Replay::open(path).expect("Could not open replay")
+    });
+    // A replay starts from what the recorded match started from, whatever the command line says
+    if let Some(replay) = &replay {
+        config.players = replay.header.players;
+        config.victory_conditions = replay.header.victory_conditions.clone();
+        config.bots = replay.header.bots.clone();
+    }
+    let map: MapRef = Arc::new(match (&replay, &config.map, config.map_seed) {
+        (Some(replay), _, _) => MapResource::new(replay.header.map.clone())
+            .expect("The map of the replay should be valid"),
+        (None, Some(path), _) => MapDefinition::load(path)
+            .and_then(MapResource::new)
+            .expect("Could not load map"),
+        (None, None, Some(seed)) => MapResource::new(map_generator::generate(seed, config.players))
+            .expect("Generated maps should be valid"),
+        (None, None, None) => MapResource::default(),
+    });
+    match map.definition.seed {
+        Some(seed) => println!("Playing on {}, seed {}", map.definition.name, seed),
+        None => println!("Playing on {}", map.definition.name),
+    }
+    let database: DatabaseRef = Arc::new(Mutex::new(
+        Database::open(&config.database).expect("Could not open database"),
+    ));
+    // Loaded before the game starts, so a bad save stops the server instead of the game thread
+    let start = match &replay {
+        Some(replay) => replay.header.start.clone().map(|save| check_version(&save).map(|_| save)).transpose(),
+        None => config.load_on_start.as_deref().map(read_save).transpose(),
+    };
+    let start = start.unwrap_or_else(|e| {
+        eprintln!("Could not load the world to start from: {}", e);
+        process::exit(1);
+    });
     let game_state = Arc::new(RwLock::new(GameStateCache::default()));
     let (sender, mut receiver) = mpsc::channel::<GameCommand>(1000);
+    // Every tick the game thread sends the new state here, for the websocket connections to stream to clients
+    let (game_state_sender, _) = broadcast::channel::<Arc<GameStateCache>>(16);
 
     let game_state_cache_ref = game_state.clone();
+    let game_state_sender_ref = game_state_sender.clone();
+    let game_config = config.clone();
+    let game_map = map.clone();
+    let game_database = database.clone();
+    let runtime = Handle::current();
+    let replay = replay;
     thread::spawn(move || {
-        let mut world = World::default();
-        let mut schedule = create_schedule();
+        let config = game_config;
+        let database = game_database;
+        let mut game = Game::new(game_map.as_ref().clone(), config.victory_conditions.clone(), config.save_file.clone(), start.clone())
+            .expect("The save was checked before the game started");
+        if start.is_some() {
+            match &config.load_on_start {
+                Some(path) if replay.is_none() => println!("Loaded world from {:?}", path),
+                _ => println!("Loaded the world the replay starts from"),
+            }
+        }
 
-        let mut resources = Resources::default();
-        resources.insert(TimeResource::default());
-        resources.insert(UidEntityMap::default());
+        game.bots = config
+            .bots
+            .iter()
+            .enumerate()
+            .map(|(index, bot)| Bot::new(index, *bot, &game_map))
+            .collect();
+        for bot in &game.bots {
+            println!("Bot {} plays on {:?}", bot.player_id, bot.difficulty);
+        }
+        game.script = config.script.as_ref().map(|path| {
+            println!("Running script {:?}", path);
+            Script::load(path, &game_map).unwrap_or_else(|e| panic!("Could not load script {:?}: {}", path, e))
+        });
+
+        let mut journal_path = if replay.is_none() {
+            let started = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_secs();
+            Some(config.journal_dir.join(format!("{}.jsonl", started)))
+        } else {
+            None
+        };
+        let header = JournalHeader {
+            version: JOURNAL_VERSION,
+            map: game_map.definition.clone(),
+            players: config.players,
+            start,
+            victory_conditions: config.victory_conditions.clone(),
+            bots: config.bots.clone(),
+        };
+        game.journal = journal_path
+            .as_ref()
+            .and_then(|path| match CommandJournal::create(path, &header) {
+                Ok(journal) => {
+                    println!("Recording commands to {:?}", path);
+                    Some(journal)
+                }
+                Err(e) => {
+                    eprintln!("error creating command journal {:?}: {}", path, e);
+                    None
+                }
+            });
+        if game.journal.is_none() {
+            journal_path = None;
+        }
+        game.replay = replay;
 
         loop {
             let before = SystemTime::now();
-            {
-                let mut command_buffer = CommandBuffer::new(&world);
-
-                while let Some(Some(command)) = receiver.recv().fuse().now_or_never() {
-                    if matches!(command, GameCommand::ResetGameCommand) {
-                        world = World::default();
-                    } else {
-                        // The extra 1 here, is to get around bug that you need 2 components when pushing to buffer
-                        command_buffer.push((command, 1));
-                    }
-                }
-                command_buffer.flush(&mut world, &mut resources);
+            let mut commands = Vec::new();
+            while let Some(Some(command)) = receiver.recv().fuse().now_or_never() {
+                commands.push(command);
+            }
+            // Replays are of matches that were recorded when they were played
+            if game.tick(commands) && game.replay.is_none() {
+                record_match_result(&game.resources, &database, &config, journal_path.as_deref(), &runtime);
             }
-            schedule.execute(&mut world, &mut resources);
             let elapsed_duration = before.elapsed().unwrap();
-            let mut time = resources
-                .get_mut::<TimeResource>()
-                .expect("Must have a time resource");
-            time.ticks += 1;
-
-            let mut new_game_state_cache = GameStateCache::default();
-            <(&Position, Option<&Destination>, &UnitId)>::query().for_each(
-                &world,
-                |(pos, des_op, id)| {
-                    let des = des_op.map(|s| (s.x, s.y)).unwrap_or((pos.x, pos.y));
-                    new_game_state_cache.units.insert(
-                        id.id.clone(),
-                        Unit {
-                            destination: des,
-                            position: (pos.x, pos.y),
-                            id: id.id.clone(),
-                        },
-                    );
-                },
-            );
+
+            let new_game_state_cache = GameStateCache::from_world(&game.world, &game.resources);
             {
                 // This block_on is used to make the game thread block on an async.
                 // We don't want the game thread to use async, since it will require it to
                 let mut lock = futures::executor::block_on(game_state_cache_ref.write());
-                lock.units = new_game_state_cache.units;
+                *lock = new_game_state_cache.clone();
             }
+            // Sending only fails when no client is connected
+            let _ = game_state_sender_ref.send(Arc::new(new_game_state_cache));
 
-            let target_interval = Duration::from_secs(1);
-            if elapsed_duration.le(&target_interval) {
-                thread::sleep(target_interval - elapsed_duration);
+            if elapsed_duration.le(&TICK_DURATION) {
+                thread::sleep(TICK_DURATION - elapsed_duration);
             }
-            time.elapsed_seconds = before.elapsed().unwrap().as_secs_f64();
         }
     });
 
@@ -108,9 +201,34 @@ async fn main() {
     let game = warp::path("game");
     let game_route = game
         .and(warp::get())
+        .and(warp::path::param())
+        .and(with_clients(clients.clone()))
         .and(with_game_state(game_state.clone()))
+        .and(with_config(config.clone()))
         .and_then(handler::get_game_state_handler);
 
+    let stats_route = warp::path("stats")
+        .and(warp::get())
+        .and(with_game_state(game_state.clone()))
+        .and_then(handler::stats_handler);
+
+    let matches_route = warp::path("matches")
+        .and(warp::get())
+        .and(warp::query::<handler::MatchHistoryQuery>())
+        .and(with_database(database.clone()))
+        .and_then(handler::match_history_handler);
+
+    let leaderboard_route = warp::path("leaderboard")
+        .and(warp::get())
+        .and(warp::query::<handler::LeaderboardQuery>())
+        .and(with_database(database))
+        .and_then(handler::leaderboard_handler);
+
+    let map_route = warp::path("map")
+        .and(warp::get())
+        .and(with_map(map.clone()))
+        .and_then(handler::map_handler);
+
     let reset_path = warp::path("reset");
     let reset_route_get = reset_path
         .and(warp::get())
@@ -119,6 +237,20 @@ async fn main() {
             Ok::<_, Infallible>(handler::reset_game_state_handler(my_sender).await)
         });
 
+    let save_route = warp::path("save")
+        .and(warp::get())
+        .and(with_sender(sender.clone()))
+        .and_then(move |my_sender| async move {
+            Ok::<_, Infallible>(handler::save_game_handler(my_sender).await)
+        });
+
+    let load_route = warp::path("load")
+        .and(warp::get())
+        .and(with_sender(sender.clone()))
+        .and_then(move |my_sender| async move {
+            Ok::<_, Infallible>(handler::load_game_handler(my_sender).await)
+        });
+
     let register = warp::path("register");
     let register_routes = register
         .and(warp::post())
@@ -136,6 +268,10 @@ async fn main() {
         .and(warp::path::param())
         .and(with_clients(clients))
         .and(with_sender(sender.clone()))
+        .and(with_game_state_sender(game_state_sender))
+        .and(with_game_state(game_state.clone()))
+        .and(with_config(config))
+        .and(with_map(map))
         .and_then(handler::ws_handler);
     let cors = warp::cors()
         .allow_any_origin()
@@ -149,8 +285,14 @@ async fn main() {
 
     let routes = health_route
         .or(game_route)
+        .or(map_route)
+        .or(stats_route)
+        .or(matches_route)
+        .or(leaderboard_route)
         .or(register_routes)
         .or(reset_route_get)
+        .or(save_route)
+        .or(load_route)
         // .or(reset_route_post)
         .or(ws_route)
         .with(cors);
@@ -159,6 +301,49 @@ async fn main() {
     warp::serve(routes).run(address).await;
 }
 
+/// Stores the result of the match that just ended in the database,
+/// on a blocking thread of the runtime so the game thread doesn't wait for the write.
+fn record_match_result(
+    resources: &Resources,
+    database: &DatabaseRef,
+    config: &Config,
+    replay: Option<&Path>,
+    runtime: &Handle,
+) {
+    let match_state = resources
+        .get::<MatchResource>()
+        .expect("Must have a match resource");
+    let result = match &match_state.result {
+        Some(result) => result.clone(),
+        None => return,
+    };
+    let duration_seconds = match_state.elapsed_seconds;
+    let map = resources.get::<MapResource>().expect("Must have a map resource").definition.name.clone();
+    let teams = resources
+        .get::<TeamsResource>()
+        .expect("Must have a teams resource")
+        .player_teams
+        .clone();
+    let ranked = config.ranked;
+    let replay = replay.map(Path::to_path_buf);
+    let database = database.clone();
+    runtime.spawn_blocking(move || {
+        let finished = FinishedMatch {
+            result: &result,
+            duration_seconds,
+            map: &map,
+            ranked,
+            teams: &teams,
+            replay: replay.as_deref(),
+        };
+        let mut database = database.lock().expect("Database lock should not be poisoned");
+        match database.record_match(&finished) {
+            Ok(id) => println!("Recorded match {}", id),
+            Err(e) => eprintln!("error recording match: {}", e),
+        }
+    });
+}
+
 fn with_clients(clients: Clients) -> impl Filter<Extract = (Clients,), Error = Infallible> + Clone {
     warp::any().map(move || clients.clone())
 }
@@ -174,3 +359,23 @@ fn with_game_state(
 ) -> impl Filter<Extract = (GameStateRef,), Error = Infallible> + Clone {
     warp::any().map(move || game_state.clone())
 }
+
+fn with_game_state_sender(
+    game_state_sender: GameStateSender,
+) -> impl Filter<Extract = (GameStateSender,), Error = Infallible> + Clone {
+    warp::any().map(move || game_state_sender.clone())
+}
+
+fn with_config(config: Config) -> impl Filter<Extract = (Config,), Error = Infallible> + Clone {
+    warp::any().map(move || config.clone())
+}
+
+fn with_database(
+    database: DatabaseRef,
+) -> impl Filter<Extract = (DatabaseRef,), Error = Infallible> + Clone {
+    warp::any().map(move || database.clone())
+}
+
+fn with_map(map: MapRef) -> impl Filter<Extract = (MapRef,), Error = Infallible> + Clone {
+    warp::any().map(move || map.clone())
+}
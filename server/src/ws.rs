@@ -1,12 +1,27 @@
 use crate::{
-    game::{self, game_state::Unit},
-    Client, Clients, GameCommandSender,
+    game::{
+        self,
+        components::{AbilityType, BuildingType, PlayerId, TeamId, UnitType},
+        events::{AbilityUsed, GameEvent, ProjectileFired, ProjectileImpact},
+        formation::Formation,
+        game_state::{GameStateCache, Unit},
+        victory::MatchEnded,
+    },
+    Client, ClientRole, Clients, GameCommandSender, GameStateRef, GameStateSender, MapRef,
 };
 
 use futures::{FutureExt, StreamExt};
 use serde::{Deserialize, Serialize};
-use serde_json::{to_string,from_str};
-use tokio::sync::mpsc;
+use serde_json::{from_str, json, to_string};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    sync::{broadcast, mpsc},
+    time::Instant,
+};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use uuid::Uuid;
 use warp::ws::{Message, WebSocket};
@@ -14,48 +29,141 @@ use warp::ws::{Message, WebSocket};
 #[derive(Deserialize, Debug, Clone)]
 pub struct CreateUnitRequest {
     position: (f32, f32),
+    #[serde(default)]
+    unit_type: UnitType,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct HarvestRequest {
+    id: String,
+    node_id: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PlaceBuildingRequest {
+    building_type: BuildingType,
+    position: (f32, f32),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct QueueProductionRequest {
+    building_id: String,
+    unit_type: UnitType,
 }
 
 #[derive(Deserialize, Debug, Clone)]
-pub struct SetUnitRequest {}
+pub struct SetRallyPointRequest {
+    building_id: String,
+    position: (f32, f32),
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct ErrorResponse {
     message: String,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct SetUnitPositionRequest {
-    position: (f32, f32),
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SetUnitDestinationRequest {
+    destination: (f32, f32),
     id: String,
 }
+
 #[derive(Deserialize, Debug, Clone)]
-pub struct SetUnitDestinationRequest {
+pub struct UnitRequest {
+    id: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PatrolRequest {
+    id: String,
+    a: (f32, f32),
+    b: (f32, f32),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AttackMoveRequest {
+    id: String,
     destination: (f32, f32),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UseAbilityRequest {
     id: String,
+    ability: AbilityType,
+    /// For abilities used on a position.
+    #[serde(default)]
+    position: Option<(f32, f32)>,
+    /// For abilities used on a unit or building.
+    #[serde(default)]
+    target_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MoveGroupRequest {
+    ids: Vec<String>,
+    destination: (f32, f32),
+    #[serde(default)]
+    formation: Formation,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub enum RequestType {
     CreateUnit(CreateUnitRequest),
+    Harvest(HarvestRequest),
     SetUnitDestination(SetUnitDestinationRequest),
+    MoveGroup(MoveGroupRequest),
+    Stop(UnitRequest),
+    HoldPosition(UnitRequest),
+    Patrol(PatrolRequest),
+    AttackMove(AttackMoveRequest),
+    UseAbility(UseAbilityRequest),
+    PlaceBuilding(PlaceBuildingRequest),
+    QueueProduction(QueueProductionRequest),
+    SetRallyPoint(SetRallyPointRequest),
     ResetGame,
+    SaveGame,
+    LoadGame,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
 pub enum ResponseType {
     CreateUnit(Unit),
     ErrorResponse(ErrorResponse),
+    /// Sent to the owner when one of their units or buildings is destroyed.
+    UnitDestroyed { id: String },
+    /// Sent to players that can see the attacker or the target, for effects.
+    ProjectileFired(ProjectileFired),
+    ProjectileImpact(ProjectileImpact),
+    /// Sent to players that can see the caster or anything the ability hit.
+    AbilityUsed(AbilityUsed),
+    /// Sent to every client when the match is over, commands are rejected after it.
+    MatchEnded(MatchEnded),
+    /// Sent to every client after each tick.
+    GameState(Box<GameStateCache>),
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn client_connection(
     ws: WebSocket,
     id: String,
     clients: Clients,
     mut client: Client,
     sender: GameCommandSender,
+    game_states: GameStateSender,
+    game_state: GameStateRef,
+    spectator_delay: Duration,
+    map: MapRef,
 ) {
     let (client_ws_sender, mut client_ws_rcv) = ws.split();
     let (client_sender, client_rcv) = mpsc::unbounded_channel();
+    // Spectators get everything after the delay, through a queue that keeps the messages in order
+    let client_rcv = if client.role == ClientRole::Spectator && !spectator_delay.is_zero() {
+        let (delayed_sender, delayed_rcv) = mpsc::unbounded_channel();
+        tokio::task::spawn(delay_messages(client_rcv, delayed_sender, spectator_delay));
+        delayed_rcv
+    } else {
+        client_rcv
+    };
     let rx = UnboundedReceiverStream::new(client_rcv);
 
     tokio::task::spawn(rx.forward(client_ws_sender).map(|result| {
@@ -65,6 +173,17 @@ pub async fn client_connection(
     }));
 
     client.sender = Some(client_sender);
+    tokio::task::spawn(stream_game_states(game_states.subscribe(), client.clone()));
+    if let (ClientRole::Player, Some(team_id)) = (client.role, client.team) {
+        sender
+            .send(game::commands::GameCommand::JoinTeamCommand {
+                player_id: client.user_id,
+                team_id,
+            })
+            .await
+            .expect("Should be able to send");
+    }
+    let connected_client = client.clone();
     clients.write().await.insert(id.clone(), client);
 
     println!("{} connected", id);
@@ -77,14 +196,164 @@ pub async fn client_connection(
                 break;
             }
         };
-        client_msg(&id, msg, &clients, sender.clone()).await;
+        client_msg(
+            &id,
+            &connected_client,
+            msg,
+            &clients,
+            sender.clone(),
+            &game_state,
+            &map,
+        )
+        .await;
     }
 
     clients.write().await.remove(&id);
     println!("{} disconnected", id);
 }
 
-async fn client_msg(id: &str, msg: Message, clients: &Clients, sender: GameCommandSender) {
+async fn stream_game_states(
+    mut game_states: broadcast::Receiver<Arc<GameStateCache>>,
+    client: Client,
+) {
+    loop {
+        let game_state = match game_states.recv().await {
+            Ok(game_state) => game_state,
+            // A slow client skips the states it missed, the next one replaces them anyway
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        for event in &game_state.events {
+            if let Some(response) = event_response(event, &client, &game_state) {
+                let response_string = to_string(&response).expect("Should be able to respond");
+                send_to_client(&client, response_string);
+            }
+        }
+        let game_state = match client.role {
+            ClientRole::Player => game_state.for_player(client.user_id),
+            ClientRole::Spectator => game_state.as_ref().clone(),
+        };
+        let response = ResponseType::GameState(Box::new(game_state));
+        let response_string = to_string(&response).expect("Should be able to respond");
+        if !send_to_client(&client, response_string) {
+            break;
+        }
+    }
+}
+
+/// The message a client should get about an event, if any.
+fn event_response(
+    event: &GameEvent,
+    client: &Client,
+    game_state: &GameStateCache,
+) -> Option<ResponseType> {
+    let is_spectator = client.role == ClientRole::Spectator;
+    let sees_any = |ids: &[&String]| {
+        is_spectator
+            || game_state
+                .visibility
+                .visible
+                .get(&client.user_id)
+                .map(|visible| ids.iter().any(|id| visible.contains(*id)))
+                .unwrap_or(false)
+    };
+    match event {
+        GameEvent::UnitCreated { unit } => {
+            if is_spectator || unit.owner == Some(client.user_id) {
+                Some(ResponseType::CreateUnit(unit.clone()))
+            } else {
+                None
+            }
+        }
+        GameEvent::UnitDestroyed { id, owner } => {
+            if is_spectator || *owner == Some(client.user_id) {
+                Some(ResponseType::UnitDestroyed { id: id.clone() })
+            } else {
+                None
+            }
+        }
+        GameEvent::ProjectileFired(projectile) => {
+            if sees_any(&[&projectile.attacker, &projectile.target]) {
+                Some(ResponseType::ProjectileFired(projectile.clone()))
+            } else {
+                None
+            }
+        }
+        GameEvent::ProjectileImpact(projectile) => {
+            if sees_any(&[&projectile.attacker, &projectile.target]) {
+                Some(ResponseType::ProjectileImpact(projectile.clone()))
+            } else {
+                None
+            }
+        }
+        GameEvent::AbilityUsed(ability) => {
+            let mut ids: Vec<&String> = ability.hit.iter().collect();
+            ids.push(&ability.caster);
+            if sees_any(&ids) {
+                Some(ResponseType::AbilityUsed(ability.clone()))
+            } else {
+                None
+            }
+        }
+        GameEvent::MatchEnded(result) => Some(ResponseType::MatchEnded(result.clone())),
+        GameEvent::CommandRejected { player_id, message } => {
+            if !is_spectator && *player_id == client.user_id {
+                Some(ResponseType::ErrorResponse(ErrorResponse {
+                    message: message.clone(),
+                }))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Sends a message to a single client, delayed spectators get it once their delay has passed.
+/// Returns false if the client has disconnected.
+fn send_to_client(client: &Client, message: String) -> bool {
+    match &client.sender {
+        Some(sender) => sender.send(Ok(Message::text(message))).is_ok(),
+        None => true,
+    }
+}
+
+type ClientMessage = std::result::Result<Message, warp::Error>;
+
+/// Passes the messages sent to a spectator on `delay` after they were sent, in the order they were sent.
+async fn delay_messages(
+    mut messages: mpsc::UnboundedReceiver<ClientMessage>,
+    sender: mpsc::UnboundedSender<ClientMessage>,
+    delay: Duration,
+) {
+    let mut queue: VecDeque<(Instant, ClientMessage)> = VecDeque::new();
+    loop {
+        let next_due = queue.front().map(|(due, _)| *due);
+        tokio::select! {
+            message = messages.recv() => match message {
+                Some(message) => queue.push_back((Instant::now() + delay, message)),
+                // The client has disconnected, so the messages still waiting can't be delivered
+                None => break,
+            },
+            _ = tokio::time::sleep_until(next_due.unwrap_or_else(Instant::now)), if next_due.is_some() => {
+                let (_, message) = queue.pop_front().expect("A message is due");
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn client_msg(
+    id: &str,
+    client: &Client,
+    msg: Message,
+    clients: &Clients,
+    sender: GameCommandSender,
+    game_state: &GameStateRef,
+    map: &MapRef,
+) {
     println!("received message from {}: {:?}", id, msg);
     let message = match msg.to_str() {
         Ok(v) => v,
@@ -94,57 +363,261 @@ async fn client_msg(id: &str, msg: Message, clients: &Clients, sender: GameComma
     if message == "ping" || message == "ping\n" {
         return;
     }
-    let response = handle_request(message, sender).await;
+    if client.role == ClientRole::Spectator {
+        let response = ResponseType::ErrorResponse(ErrorResponse {
+            message: "Spectators can not send commands".to_string(),
+        });
+        if let Some(connected) = clients.read().await.get(id) {
+            send_to_client(
+                connected,
+                to_string(&response).expect("Should be able to respond"),
+            );
+        }
+        return;
+    }
+    let match_over = game_state.read().await.match_result.is_some();
+    let response = handle_request(
+        message,
+        sender,
+        client.user_id,
+        client.team,
+        map,
+        match_over,
+    )
+    .await;
 
-    send_response(response, clients).await;
+    send_response(response, client.user_id, clients).await;
 }
 
+/// Sends the response to a request to the clients of the player that sent it, and to spectators.
+/// Other players learn about the result from the game state, if they can see it.
 async fn send_response(
     response: Option<String>,
-    clients: &std::sync::Arc<tokio::sync::RwLock<std::collections::HashMap<String, Client>>>,
+    player_id: PlayerId,
+    clients: &Clients,
 ) {
     if let Some(response) = response {
-        clients.read().await.iter().for_each(|c| match &c.1.sender {
-            Some(sender) => {
-                let _result = sender.send(Ok(Message::text(response.clone())));
-            }
-            None => {}
-        });
+        clients
+            .read()
+            .await
+            .values()
+            .filter(|client| client.role == ClientRole::Spectator || client.user_id == player_id)
+            .for_each(|client| {
+                send_to_client(client, response.clone());
+            });
     }
 }
 
-async fn handle_request(message: &str, sender: GameCommandSender) -> Option<String> {
-    let request = from_str(&message);
+async fn handle_request(
+    message: &str,
+    sender: GameCommandSender,
+    player_id: PlayerId,
+    team: Option<TeamId>,
+    map: &MapRef,
+    match_over: bool,
+) -> Option<String> {
+    let request = from_str(message);
     use RequestType::*;
 
+    // Once the match is over the game can only be reset, saved or loaded
+    if let (true, Ok(request)) = (match_over, &request) {
+        if !matches!(request, ResetGame | SaveGame | LoadGame) {
+            return error_response("The match is over");
+        }
+    }
+
     match request {
-        Ok(CreateUnit(CreateUnitRequest { position })) => {
+        Ok(CreateUnit(CreateUnitRequest {
+            position,
+            unit_type,
+        })) => {
+            let position = map.clamp(position);
+            if !map.is_passable(position) {
+                return error_response("Units can't be created on impassable terrain");
+            }
             let uuid = Uuid::new_v4().to_string();
 
             let unit = Unit {
                 position,
                 destination: position,
                 id: uuid,
+                owner: Some(player_id),
+                team,
+                unit_type,
+                facing: 0.,
+                health: unit_type.definition().max_health,
+                cooldowns: HashMap::new(),
+                status_effects: Vec::new(),
             };
-            let unit_response = ResponseType::CreateUnit(unit.clone());            
-            let response_string = to_string(&unit_response).expect("Should be able to respond");
+            // The game thread answers with the unit once it is created, or an error if the player can't afford it
             sender
-                .send(game::commands::GameCommand::CreateUnitCommand {                    
-                    unit,
+                .send(game::commands::GameCommand::CreateUnitCommand { unit, free: false })
+                .await
+                .expect("Should be able to send");
+            None
+        }
+        Ok(Harvest(HarvestRequest { id, node_id })) => {
+            sender
+                .send(game::commands::GameCommand::HarvestCommand {
+                    uuid: id,
+                    node_id,
+                    player_id,
                 })
                 .await
                 .expect("Should be able to send");
-            Some(response_string)
+            None
         }
         Ok(SetUnitDestination(SetUnitDestinationRequest { id, destination })) => {
+            let destination = map.clamp(destination);
+            if !map.is_passable(destination) {
+                return error_response("Units can't move onto impassable terrain");
+            }
             sender
                 .send(game::commands::GameCommand::SetUnitDestinationCommand {
                     position: destination,
+                    uuid: id.clone(),
+                    player_id,
+                })
+                .await
+                .expect("Should be able to send");
+            // Echoed with the destination the unit is actually going to
+            let request = SetUnitDestinationRequest { id, destination };
+            Some(json!({ "SetUnitDestination": request }).to_string())
+        }
+        Ok(Stop(UnitRequest { id })) => {
+            sender
+                .send(game::commands::GameCommand::StopCommand {
                     uuid: id,
+                    player_id,
                 })
                 .await
                 .expect("Should be able to send");
-            Some(message.to_string())
+            None
+        }
+        Ok(HoldPosition(UnitRequest { id })) => {
+            sender
+                .send(game::commands::GameCommand::HoldPositionCommand {
+                    uuid: id,
+                    player_id,
+                })
+                .await
+                .expect("Should be able to send");
+            None
+        }
+        Ok(Patrol(PatrolRequest { id, a, b })) => {
+            let (a, b) = (map.clamp(a), map.clamp(b));
+            if !map.is_passable(a) || !map.is_passable(b) {
+                return error_response("Units can't patrol to impassable terrain");
+            }
+            sender
+                .send(game::commands::GameCommand::PatrolCommand {
+                    uuid: id,
+                    a,
+                    b,
+                    player_id,
+                })
+                .await
+                .expect("Should be able to send");
+            None
+        }
+        Ok(AttackMove(AttackMoveRequest { id, destination })) => {
+            let destination = map.clamp(destination);
+            if !map.is_passable(destination) {
+                return error_response("Units can't move onto impassable terrain");
+            }
+            sender
+                .send(game::commands::GameCommand::AttackMoveCommand {
+                    uuid: id,
+                    position: destination,
+                    player_id,
+                })
+                .await
+                .expect("Should be able to send");
+            None
+        }
+        Ok(UseAbility(UseAbilityRequest {
+            id,
+            ability,
+            position,
+            target_id,
+        })) => {
+            // The game thread answers with an error if the ability can't be used
+            sender
+                .send(game::commands::GameCommand::UseAbilityCommand {
+                    uuid: id,
+                    ability,
+                    position: position.map(|position| map.clamp(position)),
+                    target_id,
+                    player_id,
+                })
+                .await
+                .expect("Should be able to send");
+            None
+        }
+        Ok(MoveGroup(MoveGroupRequest {
+            ids,
+            destination,
+            formation,
+        })) => {
+            let destination = map.clamp(destination);
+            if !map.is_passable(destination) {
+                return error_response("Units can't move onto impassable terrain");
+            }
+            sender
+                .send(game::commands::GameCommand::MoveGroupCommand {
+                    ids,
+                    destination,
+                    formation,
+                    player_id,
+                })
+                .await
+                .expect("Should be able to send");
+            None
+        }
+        Ok(PlaceBuilding(PlaceBuildingRequest {
+            building_type,
+            position,
+        })) => {
+            sender
+                .send(game::commands::GameCommand::PlaceBuildingCommand {
+                    id: Uuid::new_v4().to_string(),
+                    building_type,
+                    position,
+                    owner: player_id,
+                })
+                .await
+                .expect("Should be able to send");
+            None
+        }
+        Ok(QueueProduction(QueueProductionRequest {
+            building_id,
+            unit_type,
+        })) => {
+            // The game thread answers with the unit once it is produced, or an error if it can't be queued
+            sender
+                .send(game::commands::GameCommand::QueueProductionCommand {
+                    building_id,
+                    unit_type,
+                    player_id,
+                })
+                .await
+                .expect("Should be able to send");
+            None
+        }
+        Ok(SetRallyPoint(SetRallyPointRequest {
+            building_id,
+            position,
+        })) => {
+            let position = map.clamp(position);
+            sender
+                .send(game::commands::GameCommand::SetRallyPointCommand {
+                    building_id,
+                    position,
+                    player_id,
+                })
+                .await
+                .expect("Should be able to send");
+            None
         }
         Ok(RequestType::ResetGame) => {
             sender
@@ -153,6 +626,20 @@ async fn handle_request(message: &str, sender: GameCommandSender) -> Option<Stri
                 .expect("Could not send message");
             None
         }
+        Ok(RequestType::SaveGame) => {
+            sender
+                .send(game::commands::GameCommand::SaveGameCommand)
+                .await
+                .expect("Could not send message");
+            None
+        }
+        Ok(RequestType::LoadGame) => {
+            sender
+                .send(game::commands::GameCommand::LoadGameCommand)
+                .await
+                .expect("Could not send message");
+            None
+        }
         Err(e) => {
             let example_string = "{\"CreatUnit\":{\"position\":[10.0,15.0]}}";
             //Send something like this {"CreatUnit":{"position":[10.0,15.0]}}
@@ -164,3 +651,10 @@ async fn handle_request(message: &str, sender: GameCommandSender) -> Option<Stri
         }
     }
 }
+
+fn error_response(message: &str) -> Option<String> {
+    let response = ResponseType::ErrorResponse(ErrorResponse {
+        message: message.to_string(),
+    });
+    Some(to_string(&response).expect("Should be able to respond"))
+}